@@ -1,6 +1,8 @@
 use std::net::IpAddr;
 use unionfind::bplustree::BPlusTree;
 use unionfind::bplustree::debug::{DebugOptions, print_bplustree};
+use unionfind::bplustree::entry::Entry;
+use unionfind::bplustree::multimap::BPlusMultiMap;
 use uuid::Uuid;
 
 #[derive(Debug)]
@@ -73,12 +75,14 @@ fn random_student() -> Student {
 fn main() {
     let mut students = BPlusTree::new(20);
     let mut names = BPlusTree::new(20);
+    let mut ages = BPlusMultiMap::new(20);
 
     for _ in 0..1000 {
         let student = random_student();
 
-        if names.find(&student.name).is_none() {
-            names.insert(student.name.clone(), student.id);
+        if let Entry::Vacant(entry) = names.entry(student.name.clone()) {
+            entry.insert(student.id);
+            ages.insert(student.age, student.id);
             students.insert(student.id, student);
         }
     }
@@ -88,4 +92,38 @@ fn main() {
     print_bplustree(&students, DebugOptions::default());
     println!();
     println!("students: {} | names: {}", students.size(), names.size());
+
+    let query = format!("{} {}", FEMALE_NAMES[0], SURNAMES[0]);
+    match names.find(query.as_str()) {
+        Some(id) => println!("{query}: {:?}", students.find(id)),
+        None => println!("{query}: not enrolled"),
+    }
+
+    for (age, count) in ages.counts() {
+        println!("age {age}: {count} students");
+    }
+
+    let youngest = ages
+        .get_all(&18)
+        .filter_map(|id| students.find(id))
+        .map(|student| student.name.as_str())
+        .collect::<Vec<_>>();
+    println!("18 year olds: {youngest:?}");
+
+    for order in [4, 8, 20, 64] {
+        let mut by_name = BPlusTree::new(order);
+        for (name, id) in &names {
+            by_name.insert(name.clone(), *id);
+        }
+
+        let stats = by_name.stats();
+        println!(
+            "order {order}: height {} | {} internal, {} leaves | leaf occupancy {:.2} | ~{} heap bytes",
+            stats.height,
+            stats.internal_nodes,
+            stats.leaf_nodes,
+            stats.average_leaf_occupancy,
+            stats.heap.total()
+        );
+    }
 }
@@ -1,62 +1,200 @@
+use crate::bplustree::aggregate::Aggregate;
+use crate::bplustree::comparator::{Comparator, OrdComparator};
 use crate::bplustree::debug::{DebugOptions, print_bplustree, print_node_ptr};
+use crate::bplustree::entry::{Entry, OccupiedEntry, VacantEntry};
 use crate::bplustree::internal::Internal;
+use crate::bplustree::iter::{Iter, IterMut, Keys, Range, RangeMut, Values, ValuesMut};
 use crate::bplustree::leaf::Leaf;
-use crate::bplustree::node::{Node, NodeEntry, NodeValue};
+use crate::bplustree::node::{Node, NodeEntry, NodeValue, Position};
+use std::borrow::Borrow;
 use std::collections::VecDeque;
-use std::fmt::Debug;
-use std::mem::swap;
+use std::mem::{replace, swap};
+use std::ops::{Bound, RangeBounds};
 use std::ptr::NonNull;
 
+pub mod aggregate;
+pub mod bulk;
+pub mod comparator;
+pub mod cursor;
 pub mod debug;
+pub mod entry;
+pub mod export;
+pub mod extract;
 pub(crate) mod internal;
+pub mod invariants;
+pub mod iter;
 pub(crate) mod leaf;
+pub mod multimap;
 pub(crate) mod node;
+pub mod paged;
+pub mod persistent;
+pub mod set;
+mod split;
+pub mod stats;
+mod traits;
+pub mod transaction;
 
-#[derive(Debug)]
-pub struct BPlusTree<K, V>
+const DEFAULT_ORDER: usize = 16;
+
+pub struct BPlusTree<K, V, A = (), C = OrdComparator>
 where
-    K: Ord + PartialOrd + Clone,
+    K: Clone,
+    A: Aggregate<K, V>,
 {
     order: usize,
-    root: Option<NonNull<Node<K, V>>>,
+    root: Option<NonNull<Node<K, V, A>>>,
     size: usize,
+    aggregate: A,
+    comparator: C,
+    /// Nodes taken out of the tree while a transaction is open. They're only freed on commit, as a
+    /// rollback puts them back.
+    retired: Option<Vec<NonNull<Node<K, V, A>>>>,
+}
+
+impl<K, V, A> BPlusTree<K, V, A>
+where
+    K: Ord + PartialOrd + Clone,
+    A: Aggregate<K, V>,
+{
+    /// Creates a tree that keeps a summary of every subtree, see [`BPlusTree::aggregate`]
+    pub fn with_aggregate(order: usize, aggregate: A) -> Self {
+        Self::with_aggregate_and_comparator(order, aggregate, OrdComparator)
+    }
 }
 
 impl<K, V> BPlusTree<K, V>
 where
     K: Ord + PartialOrd + Clone,
+{
+    pub fn new(order: usize) -> Self {
+        Self::with_aggregate(order, ())
+    }
+}
+
+// Handing out mutable values would let them change behind the back of the summaries, so trees with
+// an aggregate only change values through insert
+impl<K, V, C> BPlusTree<K, V, (), C>
+where
+    K: Clone,
+    C: Comparator<K>,
+{
+    /// Creates a tree that orders its keys by the comparator instead of by [`Ord`]
+    pub fn with_comparator(order: usize, comparator: C) -> Self {
+        Self::with_aggregate_and_comparator(order, (), comparator)
+    }
+
+    pub fn entry(&mut self, k: K) -> Entry<'_, K, V, (), C> {
+        let Some(leaf_ptr) = self.find_leaf_node_raw(&k) else {
+            return Entry::Vacant(VacantEntry {
+                key: k,
+                tree: self,
+                position: None,
+            });
+        };
+
+        let leaf = unsafe { leaf_ptr.as_ref().as_leaf() };
+        match leaf
+            .data
+            .binary_search_by(|(key, _)| self.comparator.compare(key, &k))
+        {
+            Ok(index) => Entry::Occupied(OccupiedEntry {
+                tree: self,
+                leaf_ptr,
+                index,
+            }),
+            Err(index) => Entry::Vacant(VacantEntry {
+                key: k,
+                tree: self,
+                position: Some((leaf_ptr, index)),
+            }),
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut::new(self)
+    }
+
+    pub fn range_mut<Q, R>(&mut self, range: R) -> RangeMut<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+        R: RangeBounds<Q>,
+    {
+        RangeMut::new(self, range)
+    }
+
+    pub fn values_mut(&mut self) -> ValuesMut<'_, K, V> {
+        ValuesMut {
+            inner: self.iter_mut(),
+        }
+    }
+
+    pub fn get_mut<Q>(&mut self, k: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let mut leaf_ptr = self.find_leaf_node_raw(k)?;
+        let leaf = unsafe { leaf_ptr.as_mut().as_leaf_mut() };
+        let (_, v) = leaf.find_mut(k, &self.comparator)?;
+        Some(v)
+    }
+}
+
+impl<K, V, A, C> BPlusTree<K, V, A, C>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
 {
     pub fn size(&self) -> usize {
         self.size
     }
 
+    pub fn smallest_key(&self) -> Option<&K> {
+        self.first_key_value().map(|(k, _)| k)
+    }
+
     pub fn largest_key(&self) -> Option<&K> {
-        let current = self.root?;
-        let mut queue = VecDeque::from([current]);
-        while let Some(current) = queue.pop_front() {
-            match unsafe { current.as_ref() } {
-                Node::Internal(internal) => {
-                    let (_, ptr) = internal
-                        .links
-                        .last()
-                        .expect("An Internal node MUST have a child");
-                    queue.push_front(*ptr);
-                }
-                Node::Leaf(_) => {
-                    break;
-                }
-            }
-        }
+        self.last_key_value().map(|(k, _)| k)
+    }
 
-        unsafe { current.as_ref().largest_key() }
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        let leaf_ptr = self.first_leaf_raw()?;
+        let leaf = unsafe { leaf_ptr.as_ref().as_leaf() };
+        leaf.data.first().map(|(k, v)| (k, v))
     }
 
-    pub fn new(order: usize) -> Self {
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        let leaf_ptr = self.last_leaf_raw()?;
+        let leaf = unsafe { leaf_ptr.as_ref().as_leaf() };
+        leaf.data.last().map(|(k, v)| (k, v))
+    }
+
+    /// Removes the smallest entry, rebalancing the tree exactly like [`BPlusTree::remove`].
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        let leaf_ptr = self.first_leaf_raw()?;
+        Some(unsafe { self.remove_at(leaf_ptr, 0) })
+    }
+
+    /// Removes the largest entry, rebalancing the tree exactly like [`BPlusTree::remove`].
+    pub fn pop_last(&mut self) -> Option<(K, V)> {
+        let leaf_ptr = self.last_leaf_raw()?;
+        let index = unsafe { leaf_ptr.as_ref() }.size() - 1;
+        Some(unsafe { self.remove_at(leaf_ptr, index) })
+    }
+
+    pub fn with_aggregate_and_comparator(order: usize, aggregate: A, comparator: C) -> Self {
         assert!(order > 2, "BPlusTree order must be at least 2");
         Self {
             order,
             root: None,
             size: 0,
+            aggregate,
+            comparator,
+            retired: None,
         }
     }
 
@@ -67,44 +205,87 @@ where
 
     fn internal_insert(&mut self, k: K, v: V) -> Option<V> {
         if self.root.is_none() {
-            let mut leaf = Leaf::new();
-            leaf.insert(k, v);
-            let ptr = Box::into_raw(Box::new(Node::Leaf(leaf)));
-            self.root = Some(unsafe { NonNull::new_unchecked(ptr) });
-
-            self.size = 1;
+            self.insert_into_empty(k, v);
             return None;
         }
 
         let mut leaf_ptr = self.find_leaf_node_raw(&k).unwrap(); // SAFETY: We checked that root is not None
         let leaf = unsafe { leaf_ptr.as_mut().as_leaf_mut() };
 
-        let mut need_to_recursively_update_parents = false;
-        let smallest_key = leaf.smallest_key();
-        if &k < smallest_key {
-            need_to_recursively_update_parents = true;
+        match leaf
+            .data
+            .binary_search_by(|(key, _)| self.comparator.compare(key, &k))
+        {
+            Ok(index) => {
+                let (_, value) = replace(&mut leaf.data[index], (k, v));
+                unsafe { self.refresh_links_above(leaf_ptr) };
+                Some(value)
+            }
+            Err(index) => {
+                unsafe { self.insert_at(leaf_ptr, index, k, v) };
+                None
+            }
         }
+    }
 
-        let value = leaf.insert(k, v);
-        if value.is_none() {
-            self.size += 1;
-        }
+    fn insert_into_empty(&mut self, k: K, v: V) -> NonNull<Node<K, V, A>> {
+        debug_assert!(self.root.is_none());
+
+        let mut leaf = Leaf::new();
+        leaf.insert(k, v, &self.comparator);
+        let ptr = Box::into_raw(Box::new(Node::Leaf(leaf)));
+        let ptr = unsafe { NonNull::new_unchecked(ptr) };
+        self.root = Some(ptr);
+
+        self.size = 1;
+        ptr
+    }
 
-        if need_to_recursively_update_parents {
-            leaf.update_parent_smallest_key();
+    /// Inserts an entry whose key is not in the tree yet at `index` of the leaf, splitting the leaf
+    /// if it overflows. Returns the leaf and the index the entry ended up at.
+    unsafe fn insert_at(
+        &mut self,
+        mut leaf_ptr: NonNull<Node<K, V, A>>,
+        index: usize,
+        k: K,
+        v: V,
+    ) -> Position<K, V, A> {
+        let leaf = unsafe { leaf_ptr.as_mut().as_leaf_mut() };
+        leaf.data.insert(index, (k, v));
+        self.size += 1;
+        unsafe { self.refresh_links_above(leaf_ptr) };
+
+        // Only the leftmost leaf can get a new smallest key, every other leaf is reached through a separator <= k
+        if index == 0 {
+            leaf.update_parent_smallest_key(&self.comparator);
         }
 
         let leaf = unsafe { leaf_ptr.as_mut().as_leaf_mut() };
         let requires_splitting = leaf.size() > self.max_node_size();
-        if requires_splitting {
-            let new_leaf = leaf.split();
-            unsafe { self.insert_into_parent_node(leaf_ptr, new_leaf) };
+        if !requires_splitting {
+            return (leaf_ptr, index);
         }
 
-        value
+        let new_leaf = leaf.split();
+        let left_size = leaf.size();
+        unsafe {
+            Leaf::link_after(leaf_ptr, new_leaf);
+            self.insert_into_parent_node(leaf_ptr, new_leaf);
+        }
+
+        if index < left_size {
+            (leaf_ptr, index)
+        } else {
+            (new_leaf, index - left_size)
+        }
     }
 
-    fn find_leaf_node_raw(&self, k: &K) -> Option<NonNull<Node<K, V>>> {
+    fn find_leaf_node_raw<Q>(&self, k: &Q) -> Option<NonNull<Node<K, V, A>>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
         let root = self.root?;
 
         let mut current = root;
@@ -114,45 +295,176 @@ where
                 break;
             };
 
-            let left_value = internal.find_value_less_or_equal_to(k);
+            let left_value = internal.find_value_less_or_equal_to(k, &self.comparator);
             current = left_value;
         }
 
         Some(current)
     }
 
-    fn find_leaf_node(&self, k: &K) -> Option<&Leaf<K, V>> {
-        let mut leaf = self.find_leaf_node_raw(k)?;
-        Some(unsafe { leaf.as_ref().as_leaf() })
+    fn first_leaf_raw(&self) -> Option<NonNull<Node<K, V, A>>> {
+        let mut current = self.root?;
+        while let Node::Internal(internal) = unsafe { current.as_ref() } {
+            current = internal.smallest_value();
+        }
+
+        Some(current)
+    }
+
+    fn last_leaf_raw(&self) -> Option<NonNull<Node<K, V, A>>> {
+        let mut current = self.root?;
+        while let Node::Internal(internal) = unsafe { current.as_ref() } {
+            let (_, ptr) = internal
+                .links
+                .last()
+                .expect("An Internal node MUST have a child");
+            current = *ptr;
+        }
+
+        Some(current)
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V, A> {
+        Iter::new(self)
+    }
+
+    pub fn range<Q, R>(&self, range: R) -> Range<'_, K, V, A>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+        R: RangeBounds<Q>,
+    {
+        Range::new(self, range)
+    }
+
+    /// Position of the first entry that is not below the bound
+    fn lower_position<Q>(&self, bound: Bound<&Q>) -> Option<Position<K, V, A>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let (leaf_ptr, index) = match bound {
+            Bound::Unbounded => (self.first_leaf_raw()?, 0),
+            Bound::Included(k) => {
+                let leaf_ptr = self.find_leaf_node_raw(k)?;
+                let leaf = unsafe { leaf_ptr.as_ref().as_leaf() };
+                (
+                    leaf_ptr,
+                    leaf.data.partition_point(|(key, _)| {
+                        self.comparator.compare(key.borrow(), k).is_lt()
+                    }),
+                )
+            }
+            Bound::Excluded(k) => {
+                let leaf_ptr = self.find_leaf_node_raw(k)?;
+                let leaf = unsafe { leaf_ptr.as_ref().as_leaf() };
+                (
+                    leaf_ptr,
+                    leaf.data.partition_point(|(key, _)| {
+                        self.comparator.compare(key.borrow(), k).is_le()
+                    }),
+                )
+            }
+        };
+
+        Some((leaf_ptr, index))
+    }
+
+    /// Position right after the last entry that is not above the bound
+    fn upper_position<Q>(&self, bound: Bound<&Q>) -> Option<Position<K, V, A>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let (leaf_ptr, index) = match bound {
+            Bound::Unbounded => {
+                let leaf_ptr = self.last_leaf_raw()?;
+                (leaf_ptr, unsafe { leaf_ptr.as_ref().size() })
+            }
+            Bound::Included(k) => {
+                let leaf_ptr = self.find_leaf_node_raw(k)?;
+                let leaf = unsafe { leaf_ptr.as_ref().as_leaf() };
+                (
+                    leaf_ptr,
+                    leaf.data.partition_point(|(key, _)| {
+                        self.comparator.compare(key.borrow(), k).is_le()
+                    }),
+                )
+            }
+            Bound::Excluded(k) => {
+                let leaf_ptr = self.find_leaf_node_raw(k)?;
+                let leaf = unsafe { leaf_ptr.as_ref().as_leaf() };
+                (
+                    leaf_ptr,
+                    leaf.data.partition_point(|(key, _)| {
+                        self.comparator.compare(key.borrow(), k).is_lt()
+                    }),
+                )
+            }
+        };
+
+        Some((leaf_ptr, index))
+    }
+
+    pub fn keys(&self) -> Keys<'_, K, V, A> {
+        Keys { inner: self.iter() }
+    }
+
+    pub fn values(&self) -> Values<'_, K, V, A> {
+        Values { inner: self.iter() }
     }
 
-    fn find_leaf_node_mut(&mut self, k: &K) -> Option<&mut Leaf<K, V>> {
+    fn find_leaf_node<Q>(&self, k: &Q) -> Option<&Leaf<K, V, A>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
         let mut leaf = self.find_leaf_node_raw(k)?;
-        Some(unsafe { leaf.as_mut().as_leaf_mut() })
+        Some(unsafe { leaf.as_ref().as_leaf() })
     }
 
-    pub fn remove(&mut self, k: &K) -> Option<V> {
+    pub fn remove<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
         // println!("btree.remove(&{k:?});");
-        let mut node_ptr = self.find_leaf_node_raw(k)?;
+        let leaf_ptr = self.find_leaf_node_raw(k)?;
+        let leaf = unsafe { leaf_ptr.as_ref().as_leaf() };
+        let index = leaf.find_index(k, &self.comparator)?;
+
+        let (_, value) = unsafe { self.remove_at(leaf_ptr, index) };
+        Some(value)
+    }
+
+    /// Removes the entry at `index` of the leaf, rebalancing the tree if the leaf underflows
+    unsafe fn remove_at(&mut self, mut node_ptr: NonNull<Node<K, V, A>>, index: usize) -> (K, V) {
         let leaf = unsafe { node_ptr.as_mut().as_leaf_mut() };
-        let removing_smallest = leaf.smallest_key() == k;
+        let removing_smallest = index == 0;
 
-        let value = leaf.remove(k)?;
+        let entry = leaf.data.remove(index);
         self.size -= 1;
 
         if self.size == 0 {
             let root = self
                 .root
                 .expect("Size shrunk to 0, there MUST have been a root node before");
-            let _ = unsafe { Box::from_raw(root.as_ptr()) };
+            unsafe { self.release_node(root, "freeing emptied root") };
             self.root = None;
-            return Some(value);
+            return entry;
         }
 
         if leaf.is_root() {
-            return Some(value);
+            return entry;
         }
 
+        unsafe { self.refresh_links_above(node_ptr) };
+
         if removing_smallest {
             unsafe { self.update_parent_smallest_key(node_ptr) };
         }
@@ -162,19 +474,19 @@ where
             unsafe { self.transfer_or_merge(node_ptr) };
         }
 
-        Some(value)
+        entry
     }
 
     unsafe fn remove_value_from_node(
         &mut self,
-        mut node_ptr: NonNull<Node<K, V>>,
+        mut node_ptr: NonNull<Node<K, V, A>>,
         k: &K,
-    ) -> Option<NodeValue<K, V>> {
+    ) -> Option<NodeValue<K, V, A>> {
         let node = unsafe { node_ptr.as_mut() };
 
-        let removing_smallest = node.smallest_key() == k;
+        let removing_smallest = self.comparator.compare(node.smallest_key(), k).is_eq();
 
-        let value = node.remove(k)?;
+        let value = node.remove(k, &self.comparator)?;
         if let NodeValue::Internal(mut child_ptr) = value {
             // We must set parent of child node we just removed to None, or risk accessing a dangling pointer
             let child = child_ptr.as_mut();
@@ -193,16 +505,196 @@ where
         Some(value)
     }
 
-    pub fn contains(&mut self, k: &K) -> bool {
+    pub fn contains<Q>(&self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
         self.find(k).is_some()
     }
 
-    pub fn find(&self, k: &K) -> Option<&V> {
+    pub fn find<Q>(&self, k: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
         let leaf = self.find_leaf_node(k)?;
-        let (_, v) = leaf.find(k)?;
+        let (_, v) = leaf.find(k, &self.comparator)?;
         Some(v)
     }
 
+    /// Number of entries with a key smaller than k, which is the index k has, or would have, in
+    /// iteration order
+    pub fn rank<Q>(&self, k: &Q) -> usize
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        self.count_below(k, false)
+    }
+
+    /// The entry at index n in iteration order
+    pub fn select(&self, mut n: usize) -> Option<(&K, &V)> {
+        if n >= self.size {
+            return None;
+        }
+
+        let mut current = self.root?;
+        while let Node::Internal(internal) = unsafe { current.as_ref() } {
+            let mut index = 0;
+            while n >= internal.counts[index] {
+                n -= internal.counts[index];
+                index += 1;
+            }
+            current = internal.links[index].1;
+        }
+
+        let leaf = unsafe { current.as_ref() }.as_leaf();
+        let (k, v) = &leaf.data[n];
+        Some((k, v))
+    }
+
+    /// Number of entries in the range, without visiting them
+    pub fn count_range<Q, R>(&self, range: R) -> usize
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+        R: RangeBounds<Q>,
+    {
+        let start = match range.start_bound() {
+            Bound::Included(k) => self.count_below(k, false),
+            Bound::Excluded(k) => self.count_below(k, true),
+            Bound::Unbounded => 0,
+        };
+
+        let end = match range.end_bound() {
+            Bound::Included(k) => self.count_below(k, true),
+            Bound::Excluded(k) => self.count_below(k, false),
+            Bound::Unbounded => self.size,
+        };
+
+        end.saturating_sub(start)
+    }
+
+    /// Number of entries with a key smaller than k, or smaller than or equal to k if inclusive
+    fn count_below<Q>(&self, k: &Q, inclusive: bool) -> usize
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let Some(mut current) = self.root else {
+            return 0;
+        };
+
+        let mut count = 0;
+        while let Node::Internal(internal) = unsafe { current.as_ref() } {
+            let index = internal.less_or_equal_to_index(k, &self.comparator);
+            count += internal.counts[..index].iter().sum::<usize>();
+            current = internal.links[index].1;
+        }
+
+        let leaf = unsafe { current.as_ref() }.as_leaf();
+        count
+            + leaf.data.partition_point(|(key, _)| match inclusive {
+                true => self.comparator.compare(key.borrow(), k).is_le(),
+                false => self.comparator.compare(key.borrow(), k).is_lt(),
+            })
+    }
+
+    /// Combines the summaries of all entries in the range, in key order. Subtrees that lie entirely
+    /// within the range contribute their stored summary, so only the paths to both ends of the
+    /// range are visited.
+    pub fn aggregate<Q, R>(&self, range: R) -> A::Summary
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+        R: RangeBounds<Q>,
+    {
+        match self.root {
+            Some(root_ptr) => unsafe {
+                self.aggregate_node(root_ptr, range.start_bound(), range.end_bound())
+            },
+            None => self.aggregate.identity(),
+        }
+    }
+
+    /// SAFETY: node_ptr MUST point to a node of this tree
+    unsafe fn aggregate_node<Q>(
+        &self,
+        node_ptr: NonNull<Node<K, V, A>>,
+        start: Bound<&Q>,
+        end: Bound<&Q>,
+    ) -> A::Summary
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let internal = match unsafe { node_ptr.as_ref() } {
+            Node::Leaf(leaf) => {
+                let from = match start {
+                    Bound::Included(k) => leaf.data.partition_point(|(key, _)| {
+                        self.comparator.compare(key.borrow(), k).is_lt()
+                    }),
+                    Bound::Excluded(k) => leaf.data.partition_point(|(key, _)| {
+                        self.comparator.compare(key.borrow(), k).is_le()
+                    }),
+                    Bound::Unbounded => 0,
+                };
+                let to = match end {
+                    Bound::Included(k) => leaf.data.partition_point(|(key, _)| {
+                        self.comparator.compare(key.borrow(), k).is_le()
+                    }),
+                    Bound::Excluded(k) => leaf.data.partition_point(|(key, _)| {
+                        self.comparator.compare(key.borrow(), k).is_lt()
+                    }),
+                    Bound::Unbounded => leaf.size(),
+                };
+                return self.aggregate.summarize(&leaf.data[from..to.max(from)]);
+            }
+            Node::Internal(internal) => internal,
+        };
+
+        let first = match start {
+            Bound::Included(k) | Bound::Excluded(k) => {
+                internal.less_or_equal_to_index(k, &self.comparator)
+            }
+            Bound::Unbounded => 0,
+        };
+        let last = match end {
+            Bound::Included(k) | Bound::Excluded(k) => {
+                internal.less_or_equal_to_index(k, &self.comparator)
+            }
+            Bound::Unbounded => internal.size() - 1,
+        };
+
+        let mut summary = self.aggregate.identity();
+        for index in first..=last {
+            // Only the first and the last child can be cut by the range
+            let child_start = if index == first {
+                start
+            } else {
+                Bound::Unbounded
+            };
+            let child_end = if index == last { end } else { Bound::Unbounded };
+            let child_summary = match (child_start, child_end) {
+                (Bound::Unbounded, Bound::Unbounded) => internal.summaries[index].clone(),
+                _ => unsafe {
+                    self.aggregate_node(internal.links[index].1, child_start, child_end)
+                },
+            };
+            summary = self.aggregate.combine(&summary, &child_summary);
+        }
+
+        summary
+    }
+
     pub fn max_node_size(&self) -> usize {
         self.order // This BPlusTree is slightly different, each ENTRY in internal node points to a child, not the LINKS between entries
     }
@@ -213,8 +705,8 @@ where
 
     unsafe fn insert_into_parent_node(
         &mut self,
-        old_ptr: NonNull<Node<K, V>>,
-        mut new_ptr: NonNull<Node<K, V>>,
+        old_ptr: NonNull<Node<K, V, A>>,
+        mut new_ptr: NonNull<Node<K, V, A>>,
     ) {
         let old = unsafe { old_ptr.as_ref() };
         if let Some(mut parent_ptr) = old.parent_raw() {
@@ -231,9 +723,12 @@ where
                 let key = new.smallest_key();
                 let index = parent
                     .links
-                    .binary_search_by(|(k, _)| k.cmp(key))
+                    .binary_search_by(|(k, _)| self.comparator.compare(k, key))
                     .unwrap_err();
                 parent.links.insert(index, (key.clone(), new_ptr));
+                parent.counts.insert(index, new.subtree_size());
+                parent.summaries.insert(index, new.summary(&self.aggregate));
+                parent.refresh_link(old_ptr, &self.aggregate);
 
                 let need_to_split_parent = parent.size() > self.max_node_size();
                 if need_to_split_parent {
@@ -260,12 +755,14 @@ where
                 }
             }
         } else {
-            let parent_ptr = unsafe { Internal::new_with_children(old_ptr, new_ptr) };
+            let parent_ptr = unsafe {
+                Internal::new_with_children(old_ptr, new_ptr, &self.aggregate, &self.comparator)
+            };
             self.root = Some(parent_ptr);
         }
     }
 
-    unsafe fn transfer_or_merge(&mut self, mut node_ptr: NonNull<Node<K, V>>) {
+    unsafe fn transfer_or_merge(&mut self, mut node_ptr: NonNull<Node<K, V, A>>) {
         let (left_neighbour, right_neighbour) = unsafe { self.get_node_neighbours(node_ptr) };
 
         if let Some(neighbour_ptr) = left_neighbour {
@@ -301,6 +798,11 @@ where
             "No neighbours to left, no neighbours to the right, this must be the root"
         );
 
+        // The root is allowed to be smaller than min_node_size, it only collapses once it has a single child left
+        if node.size() > 1 {
+            return;
+        }
+
         let old_ptr = self.root.take().expect("There must have been a root node");
 
         self.root = match node {
@@ -315,84 +817,248 @@ where
         // println!("freeing old root:");
         // print_node_ptr(old_ptr);
         // let _ = unsafe { Box::from_raw(old_ptr.as_ptr()) };
-        free_node_ptr(old_ptr, "freeing old root:")
+        self.release_node(old_ptr, "freeing old root:")
     }
 
-    unsafe fn get_node_neighbours(
-        &self,
-        node_ptr: NonNull<Node<K, V>>,
-    ) -> (Option<NonNull<Node<K, V>>>, Option<NonNull<Node<K, V>>>) {
-        let node = unsafe { node_ptr.as_ref() };
-        let k = node.smallest_key();
-
-        let Some(parent_ptr) = node.parent_raw() else {
-            return (None, None);
-        };
+    /// Unlike transfer_or_merge, keeps transferring until the node is no longer underfull, or merges it
+    /// into a neighbour. Nodes that were cut or grafted can be missing more than a single entry.
+    unsafe fn fill_underfull_node(&mut self, node_ptr: NonNull<Node<K, V, A>>) {
+        loop {
+            let node = unsafe { node_ptr.as_ref() };
+            if node.is_root() || node.size() >= self.min_node_size() {
+                return;
+            }
 
-        let parent = unsafe { parent_ptr.as_ref() };
+            let (left_neighbour, right_neighbour) = unsafe { self.get_node_neighbours(node_ptr) };
+            let can_transfer = [left_neighbour, right_neighbour]
+                .into_iter()
+                .flatten()
+                .any(|ptr| unsafe { ptr.as_ref() }.size() > self.min_node_size());
 
-        let left = if let Some((_, left_neighbour)) = parent.as_internal().left_entry(k) {
-            Some(*left_neighbour)
-        } else {
-            None
-        };
-        let right = if let Some((_, right_neighbour)) = parent.as_internal().right_entry(k) {
-            Some(*right_neighbour)
-        } else {
-            None
-        };
+            unsafe { self.transfer_or_merge(node_ptr) };
 
-        (left, right)
+            if !can_transfer {
+                // node_ptr got merged and might have been freed
+                return;
+            }
+        }
     }
 
-    unsafe fn transfer(
-        &mut self,
-        mut left_ptr: NonNull<Node<K, V>>,
-        mut right_ptr: NonNull<Node<K, V>>,
-    ) {
-        let left = unsafe { left_ptr.as_mut() };
-        let right = unsafe { right_ptr.as_mut() };
-        let l_size = left.size();
-        let r_size = right.size();
-        if l_size < r_size {
-            let old = right.smallest_key().clone();
-            let entry = right.remove_smallest_entry();
-            let new = right.smallest_key();
+    /// Restores the invariants after entries were taken out of a leaf in bulk, which can free the leaf
+    /// when it ends up empty. self.size MUST already account for the removed entries.
+    unsafe fn fix_trimmed_leaf(&mut self, leaf_ptr: NonNull<Node<K, V, A>>) {
+        if self.size == 0 {
+            // Only a root Leaf can hold every entry
+            let root_ptr = self
+                .root
+                .take()
+                .expect("The trimmed leaf MUST be in the tree");
+            unsafe { self.release_node(root_ptr, "freeing emptied root") };
+            return;
+        }
 
-            if let NodeEntry::Internal((_, mut ptr)) = entry {
-                let child = unsafe { ptr.as_mut() };
-                unsafe { child.set_parent(Some(left_ptr)) }
-            }
+        let leaf = unsafe { leaf_ptr.as_ref() };
+        if leaf.is_root() {
+            return;
+        }
 
-            left.insert_largest_entry(entry);
-            unsafe {
-                self.update_node_key(right.parent_raw().unwrap(), old, &new);
-            }
-        } else {
-            let entry = left.remove_largest_entry();
+        unsafe { self.refresh_links_above(leaf_ptr) };
 
-            if let NodeEntry::Internal((_, mut ptr)) = entry {
-                let child = unsafe { ptr.as_mut() };
-                unsafe { child.set_parent(Some(right_ptr)) }
+        if leaf.size() > 0 {
+            unsafe {
+                self.update_separators_above(leaf_ptr);
+                self.fill_underfull_node(leaf_ptr);
             }
+            return;
+        }
 
-            let old = right.smallest_key().clone(); // TODO: Check if we can do this without needless allocations
-            right.insert_smallest_entry(entry);
-            let new = right.smallest_key();
+        let parent_ptr = leaf.parent_raw().unwrap();
+        let (separator, _) = unsafe { parent_ptr.as_ref() }
+            .as_internal()
+            .links
+            .iter()
+            .find(|(_, ptr)| *ptr == leaf_ptr)
+            .expect("A node MUST be linked from its parent");
+        let separator = separator.clone();
 
-            unsafe {
-                self.update_node_key(right.parent_raw().unwrap(), old, new);
-            }
-        };
+        unsafe {
+            Leaf::unlink(leaf_ptr);
+            self.remove_value_from_node(parent_ptr, &separator);
+            self.release_node(leaf_ptr, "freeing emptied leaf");
+        }
     }
 
-    unsafe fn merge(
-        &mut self,
-        mut left_ptr: NonNull<Node<K, V>>,
-        mut right_ptr: NonNull<Node<K, V>>,
-    ) {
-        let left = unsafe { left_ptr.as_mut() };
-        let right = unsafe { right_ptr.as_mut() };
+    /// After cutting or grafting subtrees only the nodes along one edge of the tree can be underfull.
+    /// They are fixed top-down, so every underfull node has a parent with enough children to have a neighbour.
+    unsafe fn fix_edge(&mut self, rightmost: bool) {
+        unsafe { self.collapse_root() };
+
+        'restart: loop {
+            let Some(mut current_ptr) = self.root else {
+                return;
+            };
+
+            loop {
+                let current = unsafe { current_ptr.as_ref() };
+                if !current.is_root() && current.size() < self.min_node_size() {
+                    unsafe { self.fill_underfull_node(current_ptr) };
+                    continue 'restart;
+                }
+
+                let Node::Internal(internal) = current else {
+                    return;
+                };
+
+                current_ptr = if rightmost {
+                    internal
+                        .links
+                        .last()
+                        .expect("An Internal node MUST have a child")
+                        .1
+                } else {
+                    internal.smallest_value()
+                };
+            }
+        }
+    }
+
+    /// Frees a node that was taken out of the tree, unless a transaction is open
+    unsafe fn release_node(&mut self, ptr: NonNull<Node<K, V, A>>, msg: &str) {
+        match &mut self.retired {
+            Some(retired) => retired.push(ptr),
+            None => unsafe { free_node_ptr(ptr, msg) },
+        }
+    }
+
+    /// Replaces an Internal root that has a single child with that child
+    unsafe fn collapse_root(&mut self) {
+        while let Some(root_ptr) = self.root {
+            let Node::Internal(internal) = (unsafe { root_ptr.as_ref() }) else {
+                return;
+            };
+
+            if internal.size() > 1 {
+                return;
+            }
+
+            let mut child_ptr = internal.smallest_value();
+            unsafe {
+                child_ptr.as_mut().set_parent(None);
+                self.release_node(root_ptr, "freeing collapsed root");
+            }
+            self.root = Some(child_ptr);
+        }
+    }
+
+    /// Makes the separators above node_ptr match its smallest key again
+    unsafe fn update_separators_above(&self, node_ptr: NonNull<Node<K, V, A>>) {
+        let mut current_ptr = node_ptr;
+        while let Some(mut parent_ptr) = unsafe { current_ptr.as_ref() }.parent_raw() {
+            let current = unsafe { current_ptr.as_ref() };
+            let parent = unsafe { parent_ptr.as_mut().as_internal_mut() };
+            let (k, _) = parent
+                .links
+                .iter_mut()
+                .find(|(_, ptr)| *ptr == current_ptr)
+                .expect("A node MUST be linked from its parent");
+
+            if self.comparator.compare(k, current.smallest_key()).is_eq() {
+                return;
+            }
+
+            *k = current.smallest_key().clone();
+            current_ptr = parent_ptr;
+        }
+    }
+
+    unsafe fn get_node_neighbours(
+        &self,
+        node_ptr: NonNull<Node<K, V, A>>,
+    ) -> (
+        Option<NonNull<Node<K, V, A>>>,
+        Option<NonNull<Node<K, V, A>>>,
+    ) {
+        let node = unsafe { node_ptr.as_ref() };
+        let k = node.smallest_key();
+
+        let Some(parent_ptr) = node.parent_raw() else {
+            return (None, None);
+        };
+
+        let parent = unsafe { parent_ptr.as_ref() };
+
+        let left = if let Some((_, left_neighbour)) =
+            parent.as_internal().left_entry(k, &self.comparator)
+        {
+            Some(*left_neighbour)
+        } else {
+            None
+        };
+        let right = if let Some((_, right_neighbour)) =
+            parent.as_internal().right_entry(k, &self.comparator)
+        {
+            Some(*right_neighbour)
+        } else {
+            None
+        };
+
+        (left, right)
+    }
+
+    unsafe fn transfer(
+        &mut self,
+        mut left_ptr: NonNull<Node<K, V, A>>,
+        mut right_ptr: NonNull<Node<K, V, A>>,
+    ) {
+        let left = unsafe { left_ptr.as_mut() };
+        let right = unsafe { right_ptr.as_mut() };
+        let l_size = left.size();
+        let r_size = right.size();
+        if l_size < r_size {
+            let old = right.smallest_key().clone();
+            let entry = right.remove_smallest_entry();
+            let new = right.smallest_key();
+
+            if let NodeEntry::Internal((_, mut ptr)) = entry {
+                let child = unsafe { ptr.as_mut() };
+                unsafe { child.set_parent(Some(left_ptr)) }
+            }
+
+            left.insert_largest_entry(entry, &self.aggregate);
+            unsafe {
+                self.update_node_key(right.parent_raw().unwrap(), old, &new);
+            }
+        } else {
+            let entry = left.remove_largest_entry();
+
+            if let NodeEntry::Internal((_, mut ptr)) = entry {
+                let child = unsafe { ptr.as_mut() };
+                unsafe { child.set_parent(Some(right_ptr)) }
+            }
+
+            let old = right.smallest_key().clone(); // TODO: Check if we can do this without needless allocations
+            right.insert_smallest_entry(entry, &self.aggregate);
+            let new = right.smallest_key();
+
+            unsafe {
+                self.update_node_key(right.parent_raw().unwrap(), old, new);
+            }
+        };
+
+        // Both neighbours share a parent, its own count and summary don't change
+        let mut parent_ptr = unsafe { right_ptr.as_ref() }.parent_raw().unwrap();
+        let parent = unsafe { parent_ptr.as_mut().as_internal_mut() };
+        parent.refresh_link(left_ptr, &self.aggregate);
+        parent.refresh_link(right_ptr, &self.aggregate);
+    }
+
+    unsafe fn merge(
+        &mut self,
+        mut left_ptr: NonNull<Node<K, V, A>>,
+        mut right_ptr: NonNull<Node<K, V, A>>,
+    ) {
+        let left = unsafe { left_ptr.as_mut() };
+        let right = unsafe { right_ptr.as_mut() };
         let l_size = left.size();
         let r_size = right.size();
 
@@ -402,6 +1068,7 @@ where
             left.lmerge_into(right);
 
             if let Some(right_parent_ptr) = right.parent_raw() {
+                unsafe { self.refresh_merged_links(right_parent_ptr, left_ptr, right_ptr) };
                 let ptr = if let Some(NodeValue::Internal(ptr)) =
                     self.remove_value_from_node(right_parent_ptr, &l_smallest)
                 {
@@ -419,7 +1086,7 @@ where
                     // println!("freeing ptr we got from removed_value_from_node 1: {ptr:?}");
                     // print_node_ptr(ptr);
                     // let _ = Box::from_raw(ptr.as_ptr());
-                    free_node_ptr(
+                    self.release_node(
                         ptr,
                         &format!("freeing ptr we got from removed_value_from_node 1: {ptr:?}"),
                     );
@@ -431,13 +1098,14 @@ where
 
             if let Some(right_parent_ptr) = right.parent_raw() {
                 unsafe {
+                    self.refresh_merged_links(right_parent_ptr, left_ptr, right_ptr);
                     if let Some(NodeValue::Internal(ptr)) =
                         self.remove_value_from_node(right_parent_ptr, &r_smallest)
                     {
                         // println!("freeing ptr we got from removed_value_from_node 2: {ptr:?}");
                         // print_node_ptr(ptr);
                         // let _ = Box::from_raw(ptr.as_ptr());
-                        free_node_ptr(
+                        self.release_node(
                             ptr,
                             &format!("freeing ptr we got from removed_value_from_node 2: {ptr:?}"),
                         );
@@ -447,14 +1115,40 @@ where
         };
     }
 
-    unsafe fn update_parent_smallest_key(&self, mut node_ptr: NonNull<Node<K, V>>) {
+    /// Links have to be refreshed before the emptied node is unlinked, as unlinking it can move the
+    /// other one to a different parent
+    unsafe fn refresh_merged_links(
+        &self,
+        mut parent_ptr: NonNull<Node<K, V, A>>,
+        left_ptr: NonNull<Node<K, V, A>>,
+        right_ptr: NonNull<Node<K, V, A>>,
+    ) {
+        let parent = unsafe { parent_ptr.as_mut().as_internal_mut() };
+        parent.refresh_link(left_ptr, &self.aggregate);
+        parent.refresh_link(right_ptr, &self.aggregate);
+    }
+
+    /// Recounts and resummarizes the links on the path from node_ptr up to the root
+    unsafe fn refresh_links_above(&self, node_ptr: NonNull<Node<K, V, A>>) {
+        let mut current_ptr = node_ptr;
+        while let Some(mut parent_ptr) = unsafe { current_ptr.as_ref() }.parent_raw() {
+            unsafe { parent_ptr.as_mut().as_internal_mut() }
+                .refresh_link(current_ptr, &self.aggregate);
+            current_ptr = parent_ptr;
+        }
+    }
+
+    unsafe fn update_parent_smallest_key(&self, mut node_ptr: NonNull<Node<K, V, A>>) {
         let mut current = unsafe { node_ptr.as_mut() };
         while let Some(mut parent_ptr) = current.parent_raw() {
             let current_smallest = current.smallest_key().clone();
             let parent = unsafe { parent_ptr.as_mut().as_internal_mut() };
-            let needs_updating = *parent.smallest_key() < current_smallest;
+            let needs_updating = self
+                .comparator
+                .compare(parent.smallest_key(), &current_smallest)
+                .is_lt();
             if needs_updating {
-                let k = parent.find_key_mut_less_or_equal_to(&current_smallest);
+                let k = parent.find_key_mut_less_or_equal_to(&current_smallest, &self.comparator);
                 *k = current_smallest.clone();
             } else {
                 break;
@@ -465,16 +1159,17 @@ where
 
     unsafe fn update_node_key<'a>(
         &self,
-        node_ptr: NonNull<Node<K, V>>,
+        node_ptr: NonNull<Node<K, V, A>>,
         mut old_key: K,
         mut new_key: &'a K,
     ) where
         V: 'a,
+        A: 'a,
     {
         let mut current_ptr = Some(node_ptr);
         while let Some(mut current) = current_ptr {
             let current = unsafe { current.as_mut().as_internal_mut() }; // This function is currently only called on internal nodes, so this is safe to do.
-            let Some((k, ptr)) = current.find_entry_mut(&old_key) else {
+            let Some((k, ptr)) = current.find_entry_mut(&old_key, &self.comparator) else {
                 return;
             };
 
@@ -487,11 +1182,17 @@ where
     }
 }
 
-impl<K, V> Drop for BPlusTree<K, V>
+impl<K, V, A, C> Drop for BPlusTree<K, V, A, C>
 where
-    K: Ord + PartialOrd + Clone,
+    K: Clone,
+    A: Aggregate<K, V>,
 {
     fn drop(&mut self) {
+        // Only left over if a transaction was leaked, none of them are in the tree anymore
+        for ptr in self.retired.take().into_iter().flatten() {
+            unsafe { free_node_ptr(ptr, "BPlusTree Drop impl: retired node") };
+        }
+
         let Some(current) = self.root else { return };
 
         let mut queue = VecDeque::from([current]);
@@ -515,9 +1216,10 @@ where
     }
 }
 
-unsafe fn free_node_ptr<K, V>(mut ptr: NonNull<Node<K, V>>, msg: &str)
+unsafe fn free_node_ptr<K, V, A>(mut ptr: NonNull<Node<K, V, A>>, msg: &str)
 where
-    K: Ord + PartialOrd + Clone,
+    K: Clone,
+    A: Aggregate<K, V>,
 {
     let node = unsafe { ptr.as_mut() };
 
@@ -581,6 +1283,46 @@ mod tests {
         }
     }
 
+    fn assert_node_sizes<V>(btree: &BPlusTree<i32, V>)
+    where
+        V: Ord + PartialOrd + Clone + std::fmt::Debug,
+    {
+        let mut iter = LevelIterator::new(btree);
+        let mut level = iter.next();
+        let mut depth = 0;
+        while !level.is_empty() {
+            let leaves = level.iter().filter(|node| matches!(node, Node::Leaf(_)));
+            let leaves = leaves.count();
+            assert!(
+                leaves == 0 || leaves == level.len(),
+                "Leaves MUST all be on the same level"
+            );
+            for node in &level {
+                assert!(node.size() <= btree.max_node_size());
+                if depth > 0 {
+                    assert!(node.size() >= btree.min_node_size());
+                }
+                if let Node::Internal(internal) = node {
+                    for (k, child) in &internal.links {
+                        assert_eq!(k, unsafe { child.as_ref() }.smallest_key());
+                    }
+                }
+            }
+            level = iter.next();
+            depth += 1;
+        }
+    }
+
+    /// Inserts every key of `keys` as its own value
+    fn filled<T: Extend<(i32, i32)>>(mut btree: T, keys: impl Iterator<Item = i32>) -> T {
+        btree.extend(keys.map(|k| (k, k)));
+        btree
+    }
+
+    fn tree(order: usize, keys: impl Iterator<Item = i32>) -> BPlusTree<i32, i32> {
+        filled(BPlusTree::new(order), keys)
+    }
+
     mod bplustree {
         mod print {
             use crate::bplustree::BPlusTree;
@@ -2015,6 +2757,24 @@ mod tests {
                     assert_eq!(root_links[1].0, 15);
                 }
             }
+
+            #[test]
+            fn underflowing_root_with_multiple_children_is_not_collapsed() {
+                let mut btree = BPlusTree::new(5);
+                for i in 0..20 {
+                    btree.insert(i, i);
+                }
+
+                for i in 0..20 {
+                    assert_eq!(btree.remove(&i), Some(i));
+                    verify(&btree);
+                    for j in (i + 1)..20 {
+                        assert_eq!(btree.find(&j), Some(&j));
+                    }
+                }
+
+                assert!(btree.root.is_none());
+            }
         }
 
         mod fuzz {
@@ -2081,7 +2841,10 @@ mod tests {
                         btree.remove(&k);
                     }
 
-                    verify(&btree)
+                    verify(&btree);
+                    if let Err(violation) = btree.check_invariants() {
+                        panic!("{violation}");
+                    }
                 }
 
                 println!();
@@ -2094,12 +2857,13 @@ mod tests {
         mod find {
             use crate::bplustree::BPlusTree;
             use crate::bplustree::debug::{DebugOptions, print_bplustree};
+            use std::ops::Bound;
 
             #[test]
             fn find_leaf_node_1() {
                 let mut btree = BPlusTree::new(4);
                 btree.insert((12345, 1), 0);
-                let leaf = btree.find_leaf_node_mut(&(12345, 2)).unwrap();
+                let leaf = btree.find_leaf_node(&(12345, 2)).unwrap();
                 assert_eq!(leaf.data[0], ((12345, 1), 0));
             }
 
@@ -2126,7 +2890,7 @@ mod tests {
                 print_bplustree(&btree, DebugOptions::default());
                 println!();
 
-                let leaf = btree.find_leaf_node_mut(&0).unwrap();
+                let leaf = btree.find_leaf_node(&0).unwrap();
                 assert_eq!(leaf.data[0], (0, 0));
                 assert_eq!(leaf.data[1], (5, 1));
             }
@@ -2144,15 +2908,3862 @@ mod tests {
                 print_bplustree(&btree, DebugOptions::default());
                 println!();
 
-                let leaf = btree.find_leaf_node_mut(&7).unwrap();
+                let leaf = btree.find_leaf_node(&7).unwrap();
                 assert_eq!(leaf.data[0], (0, 0));
                 assert_eq!(leaf.data[1], (5, 1));
             }
+
+            #[test]
+            fn get_mut_updates_in_place() {
+                let mut btree = BPlusTree::new(4);
+                for i in 0..50 {
+                    btree.insert(i, i);
+                }
+
+                assert_eq!(btree.get_mut(&50), None);
+                for i in 0..50 {
+                    *btree.get_mut(&i).unwrap() *= 2;
+                }
+
+                assert_eq!(btree.size(), 50);
+                for i in 0..50 {
+                    assert_eq!(btree.find(&i), Some(&(i * 2)));
+                }
+            }
+
+            #[test]
+            fn find_with_borrowed_key() {
+                let mut btree = BPlusTree::new(4);
+                for name in ["Oliver", "Emma", "Liam", "Sophia", "Ethan", "Ava", "Mason"] {
+                    btree.insert(name.to_string(), name.len());
+                }
+
+                let btree_ref = &btree;
+                assert!(btree_ref.contains("Emma"));
+                assert!(!btree_ref.contains("Harper"));
+                assert_eq!(btree.find("Sophia"), Some(&6));
+
+                *btree.get_mut("Ava").unwrap() = 0;
+                assert_eq!(btree.find("Ava"), Some(&0));
+
+                assert_eq!(btree.remove("Liam"), Some(4));
+                assert_eq!(btree.remove("Liam"), None);
+                assert!(!btree.contains("Liam"));
+
+                let names = btree
+                    .range::<str, _>((Bound::Included("E"), Bound::Excluded("O")))
+                    .map(|(k, _)| k.as_str())
+                    .collect::<Vec<_>>();
+                assert_eq!(names, ["Emma", "Ethan", "Mason"]);
+            }
+        }
+
+        mod iter {
+            use crate::bplustree::BPlusTree;
+            use std::collections::BTreeMap;
+            use std::thread;
+
+            fn tree_with_keys(
+                order: usize,
+                keys: impl Iterator<Item = i32>,
+            ) -> BPlusTree<i32, i32> {
+                let mut btree = BPlusTree::new(order);
+                for k in keys {
+                    btree.insert(k, k * 10);
+                }
+                btree
+            }
+
+            #[test]
+            fn iter_on_empty() {
+                let mut btree: BPlusTree<i32, i32> = BPlusTree::new(4);
+                assert_eq!(btree.iter().next(), None);
+                assert_eq!(btree.iter().next_back(), None);
+                assert_eq!(btree.iter_mut().next(), None);
+                assert_eq!(btree.into_iter().next(), None);
+            }
+
+            #[test]
+            fn iter_yields_entries_in_key_order() {
+                let keys = [
+                    191, 173, 143, 158, 45, 133, 76, 95, 31, 134, 118, 17, 20, 74, 2,
+                ];
+                let btree = tree_with_keys(4, keys.into_iter());
+
+                let mut expected = keys.to_vec();
+                expected.sort();
+
+                let entries = btree.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>();
+                let expected_entries = expected.iter().map(|k| (*k, k * 10)).collect::<Vec<_>>();
+                assert_eq!(entries, expected_entries);
+                assert_eq!(btree.iter().len(), keys.len());
+
+                assert_eq!(btree.keys().copied().collect::<Vec<_>>(), expected);
+                assert_eq!(
+                    btree.values().copied().collect::<Vec<_>>(),
+                    expected.iter().map(|k| k * 10).collect::<Vec<_>>()
+                );
+
+                let reversed = btree.keys().rev().copied().collect::<Vec<_>>();
+                expected.reverse();
+                assert_eq!(reversed, expected);
+            }
+
+            #[test]
+            fn iter_from_both_ends() {
+                let btree = tree_with_keys(4, 0..100);
+                for split in 0..=100 {
+                    let mut iter = btree.keys();
+                    let front = iter.by_ref().take(split).copied().collect::<Vec<_>>();
+                    let back = iter.rev().copied().collect::<Vec<_>>();
+
+                    assert_eq!(front, (0..split as i32).collect::<Vec<_>>());
+                    assert_eq!(back, (split as i32..100).rev().collect::<Vec<_>>());
+                }
+
+                let mut iter = btree.iter();
+                let mut alternating = vec![];
+                while let Some((front, _)) = iter.next() {
+                    alternating.push(*front);
+                    if let Some((back, _)) = iter.next_back() {
+                        alternating.push(*back);
+                    }
+                }
+                assert_eq!(alternating.len(), 100);
+                assert_eq!(&alternating[..4], &[0, 99, 1, 98]);
+            }
+
+            #[test]
+            fn iter_mut_and_values_mut() {
+                let mut btree = tree_with_keys(4, 0..50);
+                for (k, v) in btree.iter_mut() {
+                    *v = k + 1;
+                }
+
+                for v in btree.values_mut().rev().take(10) {
+                    *v = 0;
+                }
+
+                for k in 0..40 {
+                    assert_eq!(btree.find(&k), Some(&(k + 1)));
+                }
+                for k in 40..50 {
+                    assert_eq!(btree.find(&k), Some(&0));
+                }
+            }
+
+            #[test]
+            fn into_iter_from_both_ends() {
+                let mut btree = BPlusTree::new(4);
+                for i in 0..30 {
+                    btree.insert(i, i.to_string());
+                }
+
+                let mut iter = btree.into_iter();
+                assert_eq!(iter.len(), 30);
+                assert_eq!(iter.next(), Some((0, "0".to_string())));
+                assert_eq!(iter.next_back(), Some((29, "29".to_string())));
+
+                let rest = iter.collect::<Vec<_>>();
+                assert_eq!(rest.len(), 28);
+                assert_eq!(rest.first(), Some(&(1, "1".to_string())));
+                assert_eq!(rest.last(), Some(&(28, "28".to_string())));
+
+                // Dropping a partially consumed iterator must free everything it still owns
+                let btree = tree_with_keys(4, 0..30);
+                let mut iter = btree.into_iter();
+                iter.next();
+                iter.next_back();
+            }
+
+            #[test]
+            fn sibling_links_survive_removals() {
+                for order in 3..10 {
+                    let mut btree = BPlusTree::new(order);
+                    let mut expected = BTreeMap::new();
+                    for i in 0..300 {
+                        let k = (i * 7919) % 503;
+                        btree.insert(k, i);
+                        expected.insert(k, i);
+                    }
+
+                    for i in 0..300 {
+                        let k = (i * 104729) % 503;
+                        assert_eq!(btree.remove(&k), expected.remove(&k));
+
+                        if i % 10 == 0 {
+                            assert!(btree.iter().eq(expected.iter()));
+                            assert!(btree.iter().rev().eq(expected.iter().rev()));
+                        }
+                    }
+
+                    assert!(btree.into_iter().eq(expected.into_iter()));
+                }
+            }
+
+            #[test]
+            fn iterators_are_send_and_sync() {
+                fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+                let mut btree = tree_with_keys(4, 0..100);
+                assert_send_sync(&btree.iter());
+                assert_send_sync(&btree.range(10..20));
+                assert_send_sync(&btree.keys());
+                assert_send_sync(&btree.values());
+                assert_send_sync(&btree.iter_mut());
+                assert_send_sync(&btree.range_mut(10..20));
+                assert_send_sync(&btree.values_mut());
+
+                let (left, right) = thread::scope(|scope| {
+                    let left = scope.spawn(|| btree.range(..50).map(|(_, v)| v).sum::<i32>());
+                    let right = scope.spawn(|| btree.range(50..).map(|(_, v)| v).sum::<i32>());
+                    (left.join().unwrap(), right.join().unwrap())
+                });
+                assert_eq!(left + right, btree.values().sum());
+
+                let values = btree.values_mut();
+                thread::scope(|scope| {
+                    scope.spawn(move || values.for_each(|v| *v += 1));
+                });
+                assert!(btree.iter().all(|(k, v)| *v == k * 10 + 1));
+            }
+        }
+
+        mod bulk {
+            use crate::bplustree::BPlusTree;
+            use crate::bplustree::bulk::BulkLoadError;
+            use crate::bplustree::debug::verify;
+            use crate::bplustree::tests::assert_node_sizes;
+            use std::collections::BTreeMap;
+
+            #[test]
+            fn from_sorted_iter_builds_a_valid_tree() {
+                for order in 3..12 {
+                    for n in [0, 1, 2, order - 1, order, order + 1, 100, 1000] {
+                        let entries = (0..n as i32).map(|k| (k, k * 2));
+                        let btree = BPlusTree::from_sorted_iter(order, entries.clone()).unwrap();
+
+                        verify(&btree);
+                        assert_node_sizes(&btree);
+                        assert_eq!(btree.size(), n);
+                        assert!(btree.into_iter().eq(entries));
+                    }
+                }
+            }
+
+            #[test]
+            fn from_sorted_iter_with_fill_factor() {
+                for fill_factor in [0.1, 0.5, 0.75, 1.0] {
+                    let btree = BPlusTree::from_sorted_iter_with_fill_factor(
+                        10,
+                        fill_factor,
+                        (0..1000).map(|k| (k, k)),
+                    )
+                    .unwrap();
+
+                    verify(&btree);
+                    assert_node_sizes(&btree);
+                    assert!(btree.iter().map(|(k, _)| *k).eq(0..1000));
+                }
+
+                let leaf = |fill_factor| {
+                    let btree = BPlusTree::from_sorted_iter_with_fill_factor(
+                        10,
+                        fill_factor,
+                        (0..1000).map(|k| (k, k)),
+                    )
+                    .unwrap();
+                    btree.find_leaf_node(&0).unwrap().size()
+                };
+                assert_eq!(leaf(1.0), 10);
+                assert_eq!(leaf(0.7), 7);
+                assert_eq!(leaf(0.1), 5);
+            }
+
+            #[test]
+            fn bulk_loaded_tree_accepts_inserts_and_removes() {
+                let mut btree =
+                    BPlusTree::from_sorted_iter(4, (0..500).map(|k| (k * 2, k))).unwrap();
+                let mut expected = (0..500).map(|k| (k * 2, k)).collect::<BTreeMap<_, _>>();
+
+                for i in 0..500 {
+                    let k = (i * 7919) % 1000;
+                    if i % 3 == 0 {
+                        assert_eq!(btree.remove(&k), expected.remove(&k));
+                    } else {
+                        assert_eq!(btree.insert(k, i), expected.insert(k, i));
+                    }
+                    verify(&btree);
+                }
+
+                assert!(btree.iter().eq(expected.iter()));
+            }
+
+            #[test]
+            fn from_sorted_iter_rejects_bad_input() {
+                let duplicate = BPlusTree::from_sorted_iter(4, [(0, 0), (1, 1), (1, 2), (3, 3)]);
+                assert_eq!(
+                    duplicate.unwrap_err(),
+                    BulkLoadError::DuplicateKey { index: 2 }
+                );
+
+                let unsorted = (0..100).map(|k| if k == 50 { (0, k) } else { (k, k) });
+                let unsorted = BPlusTree::from_sorted_iter(4, unsorted);
+                assert_eq!(
+                    unsorted.unwrap_err(),
+                    BulkLoadError::UnsortedKey { index: 50 }
+                );
+            }
+
+            #[test]
+            fn from_iter_sorts_and_keeps_the_last_value() {
+                let entries = [(5, 'a'), (1, 'b'), (3, 'c'), (5, 'd'), (0, 'e'), (3, 'f')];
+                let btree = entries.into_iter().collect::<BPlusTree<_, _>>();
+
+                verify(&btree);
+                let collected = btree.into_iter().collect::<Vec<_>>();
+                assert_eq!(collected, [(0, 'e'), (1, 'b'), (3, 'f'), (5, 'd')]);
+
+                let btree = (0..10_000)
+                    .rev()
+                    .map(|k| (k, k))
+                    .collect::<BPlusTree<_, _>>();
+                verify(&btree);
+                assert!(btree.keys().copied().eq(0..10_000));
+            }
+        }
+
+        mod entry {
+            use crate::bplustree::BPlusTree;
+            use crate::bplustree::debug::verify;
+            use crate::bplustree::entry::Entry;
+            use std::collections::BTreeMap;
+
+            #[test]
+            fn entry_on_empty() {
+                let mut btree = BPlusTree::new(4);
+                assert!(matches!(btree.entry(5), Entry::Vacant(_)));
+                assert_eq!(btree.entry(5).key(), &5);
+
+                *btree.entry(5).or_insert(1) += 1;
+                assert_eq!(btree.find(&5), Some(&2));
+                assert_eq!(btree.size(), 1);
+            }
+
+            #[test]
+            fn entry_as_counter() {
+                let mut btree = BPlusTree::new(4);
+                let mut expected = BTreeMap::new();
+                for i in 0..1000 {
+                    let k = (i * 7919) % 97;
+                    *btree.entry(k).or_insert(0) += 1;
+                    *expected.entry(k).or_insert(0) += 1;
+                }
+
+                verify(&btree);
+                assert_eq!(btree.size(), expected.len());
+                assert!(btree.iter().eq(expected.iter()));
+            }
+
+            #[test]
+            fn vacant_insert_returns_the_inserted_value_after_splits() {
+                let mut btree = BPlusTree::new(4);
+                for k in (0..200).rev() {
+                    let value = btree.entry(k).or_insert_with_key(|k| k * 2);
+                    assert_eq!(*value, k * 2);
+                    *value += 1;
+                }
+
+                verify(&btree);
+                for k in 0..200 {
+                    assert_eq!(btree.find(&k), Some(&(k * 2 + 1)));
+                }
+            }
+
+            #[test]
+            fn and_modify_or_default() {
+                let mut btree: BPlusTree<&str, Vec<i32>> = BPlusTree::new(4);
+                btree.entry("a").or_default().push(1);
+                btree
+                    .entry("a")
+                    .and_modify(|v| v.push(2))
+                    .or_insert(vec![0]);
+                btree
+                    .entry("b")
+                    .and_modify(|v| v.push(3))
+                    .or_insert_with(|| vec![4]);
+
+                assert_eq!(btree.find(&"a"), Some(&vec![1, 2]));
+                assert_eq!(btree.find(&"b"), Some(&vec![4]));
+            }
+
+            #[test]
+            fn occupied_entry_get_insert_and_remove() {
+                let mut btree = BPlusTree::new(4);
+                let mut expected = BTreeMap::new();
+                for k in 0..200 {
+                    btree.insert(k, k);
+                    expected.insert(k, k);
+                }
+
+                let Entry::Occupied(mut entry) = btree.entry(10) else {
+                    panic!("10 is in the tree")
+                };
+                assert_eq!(entry.key(), &10);
+                assert_eq!(entry.insert(11), 10);
+                assert_eq!(entry.get(), &11);
+                *entry.get_mut() += 1;
+                assert_eq!(entry.remove_entry(), (10, 12));
+                expected.remove(&10);
+
+                for i in 0..150 {
+                    let k = (i * 104729) % 200;
+                    let removed = match btree.entry(k) {
+                        Entry::Occupied(entry) => Some(entry.remove()),
+                        Entry::Vacant(_) => None,
+                    };
+                    assert_eq!(removed, expected.remove(&k));
+                    verify(&btree);
+                }
+
+                assert_eq!(btree.size(), expected.len());
+                assert!(btree.iter().eq(expected.iter()));
+            }
+        }
+
+        mod range {
+            use crate::bplustree::BPlusTree;
+            use std::collections::BTreeMap;
+            use std::ops::Bound;
+
+            type TupleKey = (i32, i32, i32);
+
+            fn tuple_tree(order: usize) -> (BPlusTree<TupleKey, usize>, BTreeMap<TupleKey, usize>) {
+                let mut btree = BPlusTree::new(order);
+                let mut expected = BTreeMap::new();
+                for i in 0..500 {
+                    let i0 = (i * 31 % 7) as i32;
+                    let i1 = (i * 17 % 13) as i32;
+                    let i2 = (i * 7 % 5) as i32;
+                    btree.insert((i0, i1, i2), i);
+                    expected.insert((i0, i1, i2), i);
+                }
+                (btree, expected)
+            }
+
+            #[test]
+            fn range_on_empty() {
+                let btree: BPlusTree<i32, i32> = BPlusTree::new(4);
+                assert_eq!(btree.range(..).next(), None);
+                assert_eq!(btree.range(0..10).next_back(), None);
+            }
+
+            #[test]
+            fn range_with_tuple_keys() {
+                let (btree, expected) = tuple_tree(4);
+                let start = (2, 3, 0);
+                let end = (5, 0, 2);
+
+                assert!(btree.range(start..end).eq(expected.range(start..end)));
+                assert!(btree.range(start..=end).eq(expected.range(start..=end)));
+                assert!(btree.range(..end).eq(expected.range(..end)));
+                assert!(btree.range(start..).eq(expected.range(start..)));
+                assert!(btree.range(..).eq(expected.range(..)));
+
+                let excluded = (Bound::Excluded(start), Bound::Excluded(end));
+                assert!(btree.range(excluded).eq(expected.range(excluded)));
+
+                let everything_with_i0_3 = (3, i32::MIN, i32::MIN)..(4, i32::MIN, i32::MIN);
+                assert!(
+                    btree
+                        .range(everything_with_i0_3)
+                        .all(|((i0, _, _), _)| *i0 == 3)
+                );
+            }
+
+            #[test]
+            fn range_every_bound_combination() {
+                let mut btree = BPlusTree::new(3);
+                let mut expected = BTreeMap::new();
+                for k in (0..70).step_by(3) {
+                    btree.insert(k, k);
+                    expected.insert(k, k);
+                }
+
+                let bounds = |k: i32| [Bound::Included(k), Bound::Excluded(k), Bound::Unbounded];
+                for start in -2..73 {
+                    for end in start..73 {
+                        for start_bound in bounds(start) {
+                            for end_bound in bounds(end) {
+                                if start == end
+                                    && matches!(start_bound, Bound::Excluded(_))
+                                    && matches!(end_bound, Bound::Excluded(_))
+                                {
+                                    continue;
+                                }
+
+                                let range = (start_bound, end_bound);
+                                assert!(btree.range(range).eq(expected.range(range)));
+                                assert!(btree.range(range).rev().eq(expected.range(range).rev()));
+                            }
+                        }
+                    }
+                }
+            }
+
+            #[test]
+            fn range_from_both_ends() {
+                let (btree, expected) = tuple_tree(6);
+                let range = (1, 5, 0)..=(6, 1, 3);
+                let mut actual = btree.range(range.clone());
+                let mut expected = expected.range(range);
+                loop {
+                    let front = actual.next();
+                    assert_eq!(front, expected.next());
+                    let back = actual.next_back();
+                    assert_eq!(back, expected.next_back());
+                    if front.is_none() && back.is_none() {
+                        break;
+                    }
+                }
+            }
+
+            #[test]
+            fn range_mut_updates_only_the_range() {
+                let mut btree = BPlusTree::new(4);
+                for i in 0..100 {
+                    btree.insert(i, 0);
+                }
+
+                for (k, v) in btree.range_mut(20..40) {
+                    *v = *k;
+                }
+
+                for (k, v) in btree.range_mut(60..=70).rev() {
+                    *v = -*k;
+                }
+
+                for (k, v) in btree.iter() {
+                    let expected = match k {
+                        20..40 => *k,
+                        60..=70 => -*k,
+                        _ => 0,
+                    };
+                    assert_eq!(*v, expected);
+                }
+            }
+
+            #[test]
+            #[should_panic(expected = "range start is greater than range end")]
+            fn range_start_greater_than_end() {
+                let mut btree = BPlusTree::new(4);
+                btree.insert(0, 0);
+                let _ = btree.range((Bound::Included(5), Bound::Excluded(2)));
+            }
+        }
+
+        mod split {
+            use crate::bplustree::BPlusTree;
+            use crate::bplustree::debug::verify;
+            use crate::bplustree::tests::{assert_node_sizes, tree};
+
+            fn assert_contents(btree: &BPlusTree<i32, i32>, expected: impl Iterator<Item = i32>) {
+                let expected = expected.map(|k| (k, k)).collect::<Vec<_>>();
+                verify(btree);
+                assert_node_sizes(btree);
+                assert_eq!(btree.size(), expected.len());
+                assert!(
+                    btree
+                        .iter()
+                        .map(|(k, v)| (*k, *v))
+                        .eq(expected.iter().copied())
+                );
+                assert!(
+                    btree
+                        .iter()
+                        .rev()
+                        .map(|(k, v)| (*k, *v))
+                        .eq(expected.iter().rev().copied())
+                );
+            }
+
+            #[test]
+            fn split_off_at_every_key() {
+                for order in [3, 4, 5, 8] {
+                    for n in [1, 10, 60] {
+                        for at in -1..=n {
+                            let mut left = tree(order, 0..n);
+                            let right = left.split_off(&at);
+
+                            assert_contents(&left, 0..at.max(0));
+                            assert_contents(&right, at.max(0)..n);
+                        }
+                    }
+                }
+            }
+
+            #[test]
+            fn split_off_on_empty() {
+                let mut left: BPlusTree<i32, i32> = BPlusTree::new(4);
+                let right = left.split_off(&5);
+                assert_contents(&left, 0..0);
+                assert_contents(&right, 0..0);
+            }
+
+            #[test]
+            fn split_off_then_insert_and_remove() {
+                let mut left = tree(4, (0..200).step_by(2));
+                let mut right = left.split_off(&101);
+
+                for k in (1..200).step_by(2) {
+                    if k < 101 {
+                        left.insert(k, k);
+                    } else {
+                        right.insert(k, k);
+                    }
+                }
+                assert_contents(&left, 0..101);
+                assert_contents(&right, 101..200);
+
+                for k in 0..90 {
+                    left.remove(&k);
+                }
+                for k in 110..200 {
+                    right.remove(&k);
+                }
+                assert_contents(&left, 90..101);
+                assert_contents(&right, 101..110);
+            }
+
+            #[test]
+            fn split_off_and_append_back() {
+                for order in [3, 4, 7] {
+                    for at in [0, 1, 17, 50, 99, 100] {
+                        let mut left = tree(order, 0..100);
+                        let mut right = left.split_off(&at);
+                        left.append(&mut right);
+
+                        assert_contents(&left, 0..100);
+                        assert_contents(&right, 0..0);
+                    }
+                }
+            }
+
+            #[test]
+            fn append_trees_of_different_heights() {
+                for order in [3, 4, 5] {
+                    for small in [1, 2, 5, 20] {
+                        let mut left = tree(order, 0..small);
+                        let mut right = tree(order, small..500);
+                        left.append(&mut right);
+                        assert_contents(&left, 0..500);
+                        assert_contents(&right, 0..0);
+
+                        let mut left = tree(order, 0..500 - small);
+                        let mut right = tree(order, 500 - small..500);
+                        left.append(&mut right);
+                        assert_contents(&left, 0..500);
+                        assert_contents(&right, 0..0);
+                    }
+                }
+            }
+
+            #[test]
+            fn append_smaller_keys() {
+                let mut left = tree(4, 100..200);
+                let mut right = tree(4, 0..100);
+                left.append(&mut right);
+                assert_contents(&left, 0..200);
+                assert_contents(&right, 0..0);
+            }
+
+            #[test]
+            fn append_overlapping_keys() {
+                let mut left = tree(4, (0..100).step_by(2));
+                let mut right = BPlusTree::new(4);
+                for k in 50..150 {
+                    right.insert(k, k);
+                }
+                left.insert(60, -1);
+                left.append(&mut right);
+
+                let expected = (0..50).step_by(2).chain(50..150);
+                assert_contents(&left, expected);
+                assert_contents(&right, 0..0);
+            }
+
+            #[test]
+            fn append_different_orders() {
+                let mut left = tree(4, 0..50);
+                let mut right = tree(7, 50..100);
+                left.append(&mut right);
+                assert_contents(&left, 0..100);
+                assert_eq!(left.max_node_size(), 4);
+
+                let mut left = tree(4, 0..0);
+                let mut right = tree(7, 0..100);
+                left.append(&mut right);
+                assert_contents(&left, 0..100);
+                assert_eq!(left.max_node_size(), 4);
+                assert_contents(&right, 0..0);
+            }
+
+            #[test]
+            fn append_empty() {
+                let mut left = tree(4, 0..10);
+                let mut right = BPlusTree::new(4);
+                left.append(&mut right);
+                assert_contents(&left, 0..10);
+
+                right.append(&mut left);
+                assert_contents(&right, 0..10);
+                assert_contents(&left, 0..0);
+            }
+        }
+
+        mod remove_range {
+            use crate::bplustree::BPlusTree;
+            use crate::bplustree::debug::verify;
+            use crate::bplustree::tests::tree;
+            use rand::rngs::StdRng;
+            use rand::{Rng, SeedableRng};
+            use std::collections::BTreeMap;
+            use std::ops::Bound;
+
+            fn check(btree: &BPlusTree<i32, i32>, expected: &BTreeMap<i32, i32>) {
+                verify(btree);
+                if let Err(violation) = btree.check_invariants() {
+                    panic!("{violation}");
+                }
+                assert_eq!(btree.size(), expected.len());
+                assert!(btree.iter().eq(expected.iter()));
+                assert!(btree.iter().rev().eq(expected.iter().rev()));
+            }
+
+            fn expected(keys: impl Iterator<Item = i32>) -> BTreeMap<i32, i32> {
+                keys.map(|k| (k, k)).collect()
+            }
+
+            #[test]
+            fn middle() {
+                let mut btree = tree(4, 0..1000);
+                let before = btree.stats();
+
+                assert_eq!(btree.remove_range(100..900), 800);
+                check(&btree, &expected((0..100).chain(900..1000)));
+
+                let after = btree.stats();
+                assert!(after.leaf_nodes < before.leaf_nodes / 4);
+                assert!(after.heap.nodes < before.heap.nodes / 4);
+            }
+
+            #[test]
+            fn bounds() {
+                let mut btree = tree(5, 0..100);
+                let bounds = (Bound::Excluded(10), Bound::Included(20));
+                assert_eq!(btree.remove_range(bounds), 10);
+                check(&btree, &expected((0..=10).chain(21..100)));
+
+                assert_eq!(btree.remove_range(30..=40), 11);
+                check(&btree, &expected((0..=10).chain(21..30).chain(41..100)));
+
+                // Keys that aren't in the tree work as bounds as well
+                assert_eq!(btree.remove_range(35..50), 9);
+                check(&btree, &expected((0..=10).chain(21..30).chain(50..100)));
+            }
+
+            #[test]
+            fn prefix_and_suffix() {
+                let mut btree = tree(4, 0..200);
+                assert_eq!(btree.remove_range(..50), 50);
+                check(&btree, &expected(50..200));
+
+                assert_eq!(btree.remove_range(150..), 50);
+                check(&btree, &expected(50..150));
+
+                assert_eq!(btree.remove_range(..), 100);
+                assert!(btree.root.is_none());
+                check(&btree, &expected(0..0));
+            }
+
+            #[test]
+            fn nothing_to_remove() {
+                let mut btree = tree(4, 0..100);
+                assert_eq!(btree.remove_range(200..300), 0);
+                assert_eq!(btree.remove_range(-10..0), 0);
+                assert_eq!(btree.remove_range(50..50), 0);
+                check(&btree, &expected(0..100));
+
+                let mut empty = tree(4, 0..0);
+                assert_eq!(empty.remove_range(..), 0);
+                assert!(empty.root.is_none());
+            }
+
+            #[test]
+            fn drain() {
+                let mut btree = tree(4, 0..100);
+                let drained = btree.drain(20..40).collect::<Vec<_>>();
+                assert_eq!(drained, (20..40).map(|k| (k, k)).collect::<Vec<_>>());
+                check(&btree, &expected((0..20).chain(40..100)));
+
+                let mut drain = btree.drain(60..);
+                assert_eq!(drain.len(), 40);
+                assert_eq!(drain.next_back(), Some((99, 99)));
+                drop(drain);
+                check(&btree, &expected((0..20).chain(40..60)));
+            }
+
+            #[test]
+            fn remove_range_at_random() {
+                let mut rng = StdRng::seed_from_u64(19);
+                for _ in 0..50 {
+                    let order = rng.random_range(3..10);
+                    let mut btree = BPlusTree::new(order);
+                    let mut expected = BTreeMap::new();
+                    for _ in 0..rng.random_range(0..2000) {
+                        let k = rng.random_range(-1000..1000);
+                        btree.insert(k, k);
+                        expected.insert(k, k);
+                    }
+
+                    let start = rng.random_range(-1100..1100);
+                    let end = rng.random_range(start..1100);
+                    let removed = btree.remove_range(start..=end);
+                    let before = expected.len();
+                    expected.retain(|k, _| !(start..=end).contains(k));
+                    assert_eq!(removed, before - expected.len());
+                    check(&btree, &expected);
+                }
+            }
+        }
+
+        mod first_last {
+            use crate::bplustree::BPlusTree;
+            use crate::bplustree::debug::verify;
+            use crate::bplustree::tests::assert_node_sizes;
+
+            #[test]
+            fn first_and_last_on_empty() {
+                let mut btree: BPlusTree<i32, i32> = BPlusTree::new(4);
+                assert_eq!(btree.first_key_value(), None);
+                assert_eq!(btree.last_key_value(), None);
+                assert_eq!(btree.smallest_key(), None);
+                assert_eq!(btree.largest_key(), None);
+                assert_eq!(btree.pop_first(), None);
+                assert_eq!(btree.pop_last(), None);
+            }
+
+            #[test]
+            fn first_and_last_key_value() {
+                let mut btree = BPlusTree::new(3);
+                for k in (0..100).rev() {
+                    btree.insert(k, -k);
+                    assert_eq!(btree.first_key_value(), Some((&k, &-k)));
+                    assert_eq!(btree.last_key_value(), Some((&99, &-99)));
+                }
+
+                assert_eq!(btree.smallest_key(), Some(&0));
+                assert_eq!(btree.largest_key(), Some(&99));
+            }
+
+            #[test]
+            fn pop_first_until_empty() {
+                for order in [3, 4, 5, 8] {
+                    let mut btree = BPlusTree::new(order);
+                    for k in 0..200 {
+                        btree.insert(k, k * 2);
+                    }
+
+                    for k in 0..200 {
+                        assert_eq!(btree.pop_first(), Some((k, k * 2)));
+                        verify(&btree);
+                        assert_node_sizes(&btree);
+                        assert_eq!(btree.size(), 199 - k as usize);
+                    }
+
+                    assert_eq!(btree.pop_first(), None);
+                }
+            }
+
+            #[test]
+            fn pop_last_until_empty() {
+                for order in [3, 4, 5, 8] {
+                    let mut btree = BPlusTree::new(order);
+                    for k in 0..200 {
+                        btree.insert(k, k * 2);
+                    }
+
+                    for k in (0..200).rev() {
+                        assert_eq!(btree.pop_last(), Some((k, k * 2)));
+                        verify(&btree);
+                        assert_node_sizes(&btree);
+                        assert_eq!(btree.size(), k as usize);
+                    }
+
+                    assert_eq!(btree.pop_last(), None);
+                }
+            }
+
+            #[test]
+            fn work_queue_ordered_by_deadline() {
+                let mut queue = BPlusTree::new(4);
+                for (deadline, job) in [(30, "c"), (10, "a"), (50, "e"), (20, "b"), (40, "d")] {
+                    queue.insert(deadline, job);
+                }
+
+                let mut done = vec![];
+                while let Some((deadline, job)) = queue.pop_first() {
+                    if deadline == 20 {
+                        queue.insert(25, "late");
+                    }
+                    done.push(job);
+                }
+
+                assert_eq!(done, ["a", "b", "late", "c", "d", "e"]);
+            }
+        }
+
+        mod cursor {
+            use crate::bplustree::BPlusTree;
+            use crate::bplustree::debug::verify;
+            use crate::bplustree::tests::{assert_node_sizes, tree};
+            use std::collections::BTreeMap;
+            use std::ops::Bound;
+
+            #[test]
+            fn bounds_match_btreemap() {
+                let btree = tree(3, (0..60).step_by(3));
+                let expected = (0..60)
+                    .step_by(3)
+                    .map(|k| (k, k))
+                    .collect::<BTreeMap<_, _>>();
+
+                for k in -2..62 {
+                    for bound in [Bound::Included(&k), Bound::Excluded(&k), Bound::Unbounded] {
+                        let lower = btree.lower_bound(bound).key_value();
+                        let expected_lower = expected.range((bound, Bound::Unbounded)).next();
+                        assert_eq!(lower, expected_lower);
+
+                        let upper = btree.upper_bound(bound).key_value();
+                        let expected_upper = expected.range((Bound::Unbounded, bound)).next_back();
+                        assert_eq!(upper, expected_upper);
+                    }
+                }
+            }
+
+            #[test]
+            fn move_across_the_ghost() {
+                let btree = tree(3, 0..20);
+                let mut cursor = btree.lower_bound(Bound::Included(&18));
+                assert_eq!(cursor.key(), Some(&18));
+                assert_eq!(cursor.peek_next(), Some((&19, &19)));
+
+                cursor.move_next();
+                cursor.move_next();
+                assert_eq!(cursor.key(), None);
+                assert_eq!(cursor.peek_next(), Some((&0, &0)));
+                assert_eq!(cursor.peek_prev(), Some((&19, &19)));
+
+                cursor.move_next();
+                assert_eq!(cursor.key(), Some(&0));
+                assert_eq!(cursor.peek_prev(), None);
+
+                cursor.move_prev();
+                cursor.move_prev();
+                assert_eq!(cursor.key(), Some(&19));
+            }
+
+            #[test]
+            fn walk_in_both_directions() {
+                let btree = tree(4, 0..100);
+                let mut cursor = btree.lower_bound(Bound::Unbounded);
+                for k in 0..100 {
+                    assert_eq!(cursor.key_value(), Some((&k, &k)));
+                    cursor.move_next();
+                }
+                assert_eq!(cursor.key(), None);
+
+                for k in (0..100).rev() {
+                    cursor.move_prev();
+                    assert_eq!(cursor.key_value(), Some((&k, &k)));
+                }
+            }
+
+            #[test]
+            fn cursor_on_empty_tree() {
+                let mut btree: BPlusTree<i32, i32> = BPlusTree::new(4);
+                let mut cursor = btree.lower_bound(Bound::Unbounded);
+                cursor.move_next();
+                assert_eq!(cursor.key(), None);
+                assert_eq!(cursor.peek_prev(), None);
+
+                let mut cursor = btree.upper_bound_mut(Bound::Unbounded);
+                assert_eq!(cursor.remove_current(), None);
+                cursor.insert_after(1, 1);
+                cursor.insert_before(2, 2);
+                assert_eq!(cursor.key(), None);
+                assert!(btree.into_iter().eq([(1, 1), (2, 2)]));
+            }
+
+            #[test]
+            fn insert_around_the_cursor() {
+                for order in [3, 4, 5] {
+                    let mut btree = tree(order, (0..300).step_by(10));
+                    let mut cursor = btree.lower_bound_mut(Bound::Unbounded);
+                    while let Some(&k) = cursor.key() {
+                        cursor.insert_before(k - 2, k - 2);
+                        cursor.insert_after(k + 3, k + 3);
+                        cursor.insert_after(k + 1, k + 1);
+                        cursor.insert_before(k - 1, k - 1);
+                        assert_eq!(cursor.key(), Some(&k));
+
+                        cursor.move_next();
+                        cursor.move_next();
+                        cursor.move_next();
+                    }
+
+                    verify(&btree);
+                    assert_node_sizes(&btree);
+                    let expected = (0..300)
+                        .step_by(10)
+                        .flat_map(|k| [k - 2, k - 1, k, k + 1, k + 3]);
+                    assert!(btree.keys().copied().eq(expected));
+                    assert_eq!(btree.size(), 150);
+                }
+            }
+
+            #[test]
+            fn remove_current_keeps_the_cursor_valid() {
+                for order in [3, 4, 5, 8] {
+                    let mut btree = tree(order, 0..300);
+                    let mut cursor = btree.lower_bound_mut(Bound::Unbounded);
+                    while let Some(&k) = cursor.key() {
+                        if k % 3 == 0 {
+                            cursor.move_next();
+                            continue;
+                        }
+
+                        assert_eq!(cursor.remove_current(), Some((k, k)));
+                        verify(cursor.tree);
+                        assert_node_sizes(cursor.tree);
+                        assert_eq!(cursor.key().copied(), (k + 1..300).next());
+                    }
+
+                    assert!(btree.keys().copied().eq((0..300).step_by(3)));
+                    assert_eq!(btree.size(), 100);
+                }
+            }
+
+            #[test]
+            fn remove_current_backwards_until_empty() {
+                let mut btree = tree(3, 0..100);
+                let mut cursor = btree.upper_bound_mut(Bound::Unbounded);
+                for k in (0..100).rev() {
+                    assert_eq!(cursor.remove_current(), Some((k, k)));
+                    assert_eq!(cursor.key(), None);
+                    cursor.move_prev();
+                }
+
+                assert_eq!(cursor.remove_current(), None);
+                assert_eq!(btree.size(), 0);
+            }
+
+            #[test]
+            fn dedup_adjacent_values() {
+                let mut btree = BPlusTree::new(4);
+                for (k, v) in (0..50).zip([1, 1, 2, 2, 2, 3, 1, 1].into_iter().cycle()) {
+                    btree.insert(k, v);
+                }
+
+                let mut cursor = btree.lower_bound_mut(Bound::Unbounded);
+                cursor.move_next();
+                while cursor.key().is_some() {
+                    let prev = cursor.peek_prev().map(|(_, v)| *v);
+                    if cursor.value().copied() == prev {
+                        cursor.remove_current();
+                    } else {
+                        cursor.move_next();
+                    }
+                }
+
+                let mut cursor = btree.lower_bound_mut(Bound::Included(&0));
+                *cursor.value_mut().unwrap() = 10;
+
+                verify(&btree);
+                let values = btree.values().copied().collect::<Vec<_>>();
+                assert!(values.windows(2).all(|w| w[0] != w[1]));
+                assert_eq!(values[..5], [10, 2, 3, 1, 2]);
+            }
+
+            #[test]
+            #[should_panic(expected = "key must be ordered below the next element")]
+            fn insert_after_out_of_order() {
+                let mut btree = tree(4, 0..10);
+                let mut cursor = btree.lower_bound_mut(Bound::Included(&3));
+                cursor.insert_after(7, 7);
+            }
+
+            #[test]
+            #[should_panic(expected = "key must be ordered below the current element")]
+            fn insert_before_existing_key() {
+                let mut btree = tree(4, 0..10);
+                let mut cursor = btree.lower_bound_mut(Bound::Included(&3));
+                cursor.insert_before(3, 3);
+            }
+        }
+
+        mod set {
+            use crate::bplustree::set::BPlusSet;
+            use std::collections::BTreeSet;
+
+            fn sets(a: &[i32], b: &[i32]) -> (BPlusSet<i32>, BPlusSet<i32>) {
+                let mut x = BPlusSet::new(3);
+                let mut y = BPlusSet::new(4);
+                for k in a {
+                    x.insert(*k);
+                }
+                for k in b {
+                    y.insert(*k);
+                }
+                (x, y)
+            }
+
+            #[test]
+            fn insert_remove_contains() {
+                let mut set = BPlusSet::new(4);
+                assert!(set.is_empty());
+                assert!(set.insert(5));
+                assert!(set.insert(3));
+                assert!(!set.insert(5));
+                assert_eq!(set.size(), 2);
+                assert!(set.contains(&3));
+                assert!(!set.contains(&4));
+
+                assert!(set.remove(&3));
+                assert!(!set.remove(&3));
+                assert!(!set.contains(&3));
+                assert_eq!(set.size(), 1);
+            }
+
+            #[test]
+            fn iteration_is_ordered() {
+                let set = [5, 1, 9, 3, 7, 3].into_iter().collect::<BPlusSet<_>>();
+                assert!(set.iter().copied().eq([1, 3, 5, 7, 9]));
+                assert!(set.iter().rev().copied().eq([9, 7, 5, 3, 1]));
+                assert!(set.range(3..8).copied().eq([3, 5, 7]));
+                assert!(set.range(..=5).rev().copied().eq([5, 3, 1]));
+                assert_eq!(set.first(), Some(&1));
+                assert_eq!(set.last(), Some(&9));
+                assert!(set.into_iter().eq([1, 3, 5, 7, 9]));
+            }
+
+            #[test]
+            fn set_operations_match_btreeset() {
+                let cases: [(&[i32], &[i32]); 6] = [
+                    (&[], &[]),
+                    (&[1, 2, 3], &[]),
+                    (&[], &[4, 5]),
+                    (&[1, 3, 5, 7, 9, 11], &[2, 3, 4, 5, 6]),
+                    (&[1, 2, 3], &[1, 2, 3]),
+                    (&[10, 20, 30], &[1, 2, 3]),
+                ];
+
+                for (a, b) in cases {
+                    let (x, y) = sets(a, b);
+                    let ex = a.iter().copied().collect::<BTreeSet<_>>();
+                    let ey = b.iter().copied().collect::<BTreeSet<_>>();
+
+                    assert!(x.union(&y).eq(ex.union(&ey)));
+                    assert!(x.intersection(&y).eq(ex.intersection(&ey)));
+                    assert!(x.difference(&y).eq(ex.difference(&ey)));
+                    assert!(y.difference(&x).eq(ey.difference(&ex)));
+                    assert!(x.symmetric_difference(&y).eq(ex.symmetric_difference(&ey)));
+                    assert_eq!(x.is_subset(&y), ex.is_subset(&ey));
+                    assert_eq!(y.is_subset(&x), ey.is_subset(&ex));
+                    assert_eq!(x.is_superset(&y), ex.is_superset(&ey));
+                    assert_eq!(x.is_disjoint(&y), ex.is_disjoint(&ey));
+                }
+            }
+
+            #[test]
+            fn set_operations_on_larger_sets() {
+                let a = (0..1000).step_by(2).collect::<Vec<_>>();
+                let b = (0..1000).step_by(3).collect::<Vec<_>>();
+                let (x, y) = sets(&a, &b);
+
+                assert!(x.intersection(&y).copied().eq((0..1000).step_by(6)));
+                assert_eq!(x.union(&y).count(), 500 + 334 - 167);
+                assert!(x.difference(&y).all(|k| k % 2 == 0 && k % 3 != 0));
+            }
+
+            #[test]
+            fn set_operations_are_lazy() {
+                let (x, y) = sets(&[1, 2, 3, 4], &[2, 4, 6]);
+                let mut union = x.union(&y);
+                assert_eq!(union.next(), Some(&1));
+                assert_eq!(union.next(), Some(&2));
+                assert_eq!(union.size_hint(), (2, Some(4)));
+
+                let mut difference = x.difference(&y);
+                assert_eq!(difference.next(), Some(&1));
+                assert_eq!(difference.next(), Some(&3));
+                assert_eq!(difference.next(), None);
+            }
+        }
+
+        mod multimap {
+            use crate::bplustree::debug::verify;
+            use crate::bplustree::multimap::BPlusMultiMap;
+
+            #[test]
+            fn keeps_values_in_insertion_order() {
+                let mut map = BPlusMultiMap::new(4);
+                map.insert("b", 1);
+                map.insert("a", 2);
+                map.insert("b", 3);
+                map.insert("b", 0);
+
+                assert!(map.get_all(&"b").copied().eq([1, 3, 0]));
+                assert!(map.get_all(&"a").copied().eq([2]));
+                assert!(map.get_all(&"c").next().is_none());
+                assert_eq!(map.get_first(&"b"), Some(&1));
+                assert!(map.iter().map(|(k, v)| (*k, *v)).eq([
+                    ("a", 2),
+                    ("b", 1),
+                    ("b", 3),
+                    ("b", 0)
+                ]));
+            }
+
+            #[test]
+            fn counts() {
+                let mut map = BPlusMultiMap::new(3);
+                for i in 0..100 {
+                    map.insert(i % 7, i);
+                }
+
+                assert_eq!(map.size(), 100);
+                assert_eq!(map.key_count(), 7);
+                assert_eq!(map.count(&0), 15);
+                assert_eq!(map.count(&6), 14);
+                assert_eq!(map.count(&7), 0);
+                assert_eq!(map.counts().map(|(_, c)| c).sum::<usize>(), 100);
+                let expected = (0..7).map(|k| (k, if k < 2 { 15 } else { 14 }));
+                assert!(map.counts().map(|(k, c)| (*k, c)).eq(expected));
+            }
+
+            #[test]
+            fn run_of_one_key_spans_several_leaves() {
+                let mut map = BPlusMultiMap::new(3);
+                map.insert(1, -1);
+                for i in 0..200 {
+                    map.insert(5, i);
+                }
+                map.insert(9, -9);
+                map.insert(0, 0);
+                map.insert(5, 200);
+                verify(&map.tree);
+
+                assert!(map.get_all(&5).copied().eq(0..=200));
+                assert!(map.get_all(&5).rev().copied().eq((0..=200).rev()));
+                assert!(map.get_all(&1).copied().eq([-1]));
+                assert!(map.get_all(&9).copied().eq([-9]));
+                assert!(map.get_all(&4).next().is_none());
+
+                for i in 0..100 {
+                    assert_eq!(map.remove_one(&5), Some(i));
+                    verify(&map.tree);
+                }
+                assert_eq!(map.count(&5), 101);
+                assert!(map.get_all(&5).copied().eq(100..=200));
+            }
+
+            #[test]
+            fn remove_one_and_remove_all() {
+                let mut map = BPlusMultiMap::new(4);
+                assert_eq!(map.remove_one(&1), None);
+                assert!(map.remove_all(&1).is_empty());
+
+                for i in 0..300 {
+                    map.insert(i % 3, i);
+                }
+
+                assert_eq!(map.remove_one(&1), Some(1));
+                assert_eq!(map.remove_one(&1), Some(4));
+                assert_eq!(map.count(&1), 98);
+
+                let removed = map.remove_all(&1);
+                assert!(removed.into_iter().eq((7..300).step_by(3)));
+                assert_eq!(map.count(&1), 0);
+                assert!(!map.contains(&1));
+                assert_eq!(map.key_count(), 2);
+                assert_eq!(map.size(), 200);
+                verify(&map.tree);
+
+                assert!(map.get_all(&0).copied().eq((0..300).step_by(3)));
+                assert!(map.get_all(&2).copied().eq((2..300).step_by(3)));
+
+                map.insert(1, 1000);
+                assert!(map.get_all(&1).copied().eq([1000]));
+            }
+        }
+
+        mod order_statistics {
+            use crate::bplustree::BPlusTree;
+            use crate::bplustree::debug::verify;
+            use crate::bplustree::tests::tree;
+            use std::ops::Bound;
+
+            #[test]
+            fn rank_and_select() {
+                for order in [3, 4, 5, 16] {
+                    let btree = tree(order, (0..500).map(|k| k * 2).rev());
+
+                    for (index, k) in (0..500).map(|k| k * 2).enumerate() {
+                        assert_eq!(btree.rank(&k), index);
+                        assert_eq!(btree.rank(&(k + 1)), index + 1);
+                        assert_eq!(btree.select(index), Some((&k, &k)));
+                    }
+
+                    assert_eq!(btree.rank(&-5), 0);
+                    assert_eq!(btree.rank(&5000), 500);
+                    assert_eq!(btree.select(500), None);
+                }
+            }
+
+            #[test]
+            fn order_statistics_on_empty() {
+                let btree: BPlusTree<i32, i32> = BPlusTree::new(4);
+                assert_eq!(btree.rank(&1), 0);
+                assert_eq!(btree.select(0), None);
+                assert_eq!(btree.count_range(..), 0);
+            }
+
+            #[test]
+            fn count_range_matches_range() {
+                let btree = tree(3, (0..90).step_by(3));
+                for start in -2..92 {
+                    for end in start..92 {
+                        let bounds = [
+                            (Bound::Included(start), Bound::Included(end)),
+                            (Bound::Included(start), Bound::Excluded(end)),
+                            (Bound::Excluded(start), Bound::Included(end)),
+                            (Bound::Unbounded, Bound::Excluded(end)),
+                            (Bound::Excluded(start), Bound::Unbounded),
+                        ];
+                        for range in bounds {
+                            if range == (Bound::Excluded(start), Bound::Excluded(start)) {
+                                continue;
+                            }
+                            assert_eq!(btree.count_range(range), btree.range(range).count());
+                        }
+                    }
+                }
+            }
+
+            #[test]
+            fn counts_survive_removes_and_restructuring() {
+                for order in [3, 4, 5] {
+                    let mut btree = tree(order, 0..400);
+                    for k in (0..400).filter(|k| k % 3 != 0) {
+                        btree.remove(&k);
+                        verify(&btree);
+                    }
+
+                    for (index, k) in (0..400).step_by(3).enumerate() {
+                        assert_eq!(btree.rank(&k), index);
+                        assert_eq!(btree.select(index), Some((&k, &k)));
+                    }
+
+                    let mut right = btree.split_off(&200);
+                    verify(&btree);
+                    verify(&right);
+                    assert_eq!(right.rank(&201), 0);
+                    assert_eq!(right.select(0), Some((&201, &201)));
+                    assert_eq!(btree.count_range(..), 67);
+
+                    right.pop_first();
+                    btree.append(&mut right);
+                    verify(&btree);
+                    assert_eq!(btree.rank(&399), 132);
+                    assert_eq!(btree.select(67), Some((&204, &204)));
+
+                    while btree.pop_last().is_some() {
+                        verify(&btree);
+                    }
+                }
+            }
+
+            #[test]
+            fn leaderboard() {
+                let mut scores = BPlusTree::new(8);
+                for player in 0..10_000 {
+                    scores.insert((player * 7919) % 10_007, player);
+                }
+
+                let (score, _) = scores.select(9_999).unwrap();
+                assert_eq!(scores.rank(score), 9_999);
+                assert_eq!(scores.count_range(5_000..), scores.range(5_000..).count());
+            }
+        }
+
+        mod aggregate {
+            use crate::bplustree::BPlusTree;
+            use crate::bplustree::aggregate::Aggregate;
+            use crate::bplustree::debug::verify_summaries;
+            use crate::bplustree::tests::filled;
+            use std::ops::Bound;
+
+            #[derive(Debug, Clone)]
+            struct Sum;
+
+            impl Aggregate<i32, i32> for Sum {
+                type Summary = i64;
+
+                fn identity(&self) -> Self::Summary {
+                    0
+                }
+
+                fn summarize_entry(&self, _: &i32, v: &i32) -> Self::Summary {
+                    *v as i64
+                }
+
+                fn combine(&self, left: &Self::Summary, right: &Self::Summary) -> Self::Summary {
+                    left + right
+                }
+            }
+
+            #[derive(Debug, Clone)]
+            struct MinMax;
+
+            impl Aggregate<i32, i32> for MinMax {
+                type Summary = Option<(i32, i32)>;
+
+                fn identity(&self) -> Self::Summary {
+                    None
+                }
+
+                fn summarize_entry(&self, _: &i32, v: &i32) -> Self::Summary {
+                    Some((*v, *v))
+                }
+
+                fn combine(&self, left: &Self::Summary, right: &Self::Summary) -> Self::Summary {
+                    match (left, right) {
+                        (Some((lmin, lmax)), Some((rmin, rmax))) => {
+                            Some((*lmin.min(rmin), *lmax.max(rmax)))
+                        }
+                        (summary, None) | (None, summary) => *summary,
+                    }
+                }
+            }
+
+            /// Not commutative, catches summaries combined out of key order
+            #[derive(Debug, Clone)]
+            struct Keys;
+
+            impl Aggregate<i32, i32> for Keys {
+                type Summary = Vec<i32>;
+
+                fn identity(&self) -> Self::Summary {
+                    vec![]
+                }
+
+                fn summarize_entry(&self, k: &i32, _: &i32) -> Self::Summary {
+                    vec![*k]
+                }
+
+                fn combine(&self, left: &Self::Summary, right: &Self::Summary) -> Self::Summary {
+                    left.iter().chain(right).copied().collect()
+                }
+            }
+
+            fn bounds(start: i32, end: i32) -> [(Bound<i32>, Bound<i32>); 5] {
+                [
+                    (Bound::Included(start), Bound::Included(end)),
+                    (Bound::Included(start), Bound::Excluded(end)),
+                    (Bound::Excluded(start), Bound::Included(end)),
+                    (Bound::Unbounded, Bound::Excluded(end)),
+                    (Bound::Excluded(start), Bound::Unbounded),
+                ]
+            }
+
+            #[test]
+            fn aggregate_matches_range() {
+                for order in [3, 4, 5] {
+                    let sums = filled(
+                        BPlusTree::with_aggregate(order, Sum),
+                        (0..120).map(|k| k * 2).rev(),
+                    );
+                    let extremes = filled(
+                        BPlusTree::with_aggregate(order, MinMax),
+                        (0..120).map(|k| k * 2),
+                    );
+                    let keys = filled(
+                        BPlusTree::with_aggregate(order, Keys),
+                        (0..120).map(|k| (k * 7) % 120 * 2),
+                    );
+                    verify_summaries(&sums);
+                    verify_summaries(&extremes);
+                    verify_summaries(&keys);
+
+                    for start in (-3..243).step_by(5) {
+                        for end in (start..243).step_by(3) {
+                            for range in bounds(start, end) {
+                                if range == (Bound::Excluded(start), Bound::Excluded(start)) {
+                                    continue;
+                                }
+
+                                let values = sums.range(range).map(|(_, v)| *v);
+                                assert_eq!(sums.aggregate(range), values.map(i64::from).sum());
+
+                                let values = extremes.range(range).map(|(_, v)| *v);
+                                let expected = values.clone().min().zip(values.max());
+                                assert_eq!(extremes.aggregate(range), expected);
+
+                                let expected: Vec<_> = keys.range(range).map(|(k, _)| *k).collect();
+                                assert_eq!(keys.aggregate(range), expected);
+                            }
+                        }
+                    }
+                }
+            }
+
+            #[test]
+            fn aggregate_on_empty() {
+                let btree: BPlusTree<i32, i32, Sum> = BPlusTree::with_aggregate(4, Sum);
+                assert_eq!(btree.aggregate(..), 0);
+                assert_eq!(btree.aggregate((Bound::Included(5), Bound::Included(1))), 0);
+            }
+
+            #[test]
+            fn summaries_survive_every_mutation() {
+                for order in [3, 4, 5] {
+                    let mut btree = filled(BPlusTree::with_aggregate(order, Sum), 0..300);
+                    for k in (0..300).step_by(7) {
+                        btree.insert(k, 1000);
+                        verify_summaries(&btree);
+                    }
+
+                    for k in (0..300).filter(|k| k % 3 == 1) {
+                        btree.remove(&k);
+                        verify_summaries(&btree);
+                    }
+
+                    let mut right = btree.split_off(&150);
+                    verify_summaries(&btree);
+                    verify_summaries(&right);
+                    let sum = |btree: &BPlusTree<i32, i32, Sum>| {
+                        btree.iter().map(|(_, v)| *v as i64).sum::<i64>()
+                    };
+                    assert_eq!(btree.aggregate(..), sum(&btree));
+                    assert_eq!(right.aggregate(..), sum(&right));
+
+                    right.pop_first();
+                    btree.pop_last();
+                    btree.append(&mut right);
+                    verify_summaries(&btree);
+                    assert_eq!(btree.aggregate(..), sum(&btree));
+
+                    let mut cursor = btree.lower_bound_mut(Bound::Included(&100));
+                    cursor.remove_current();
+                    cursor.insert_before(100, 5);
+                    verify_summaries(&btree);
+
+                    while btree.pop_first().is_some() {
+                        verify_summaries(&btree);
+                        assert_eq!(btree.aggregate(..), sum(&btree));
+                    }
+                }
+            }
+
+            #[test]
+            fn total_score_by_student_id() {
+                let mut scores = BPlusTree::with_aggregate(8, Sum);
+                for id in 0..1000 {
+                    scores.insert(id, id % 10);
+                }
+
+                assert_eq!(scores.aggregate(100..200), 450);
+                assert_eq!(scores.aggregate(..), 4500);
+                scores.insert(150, 100);
+                assert_eq!(scores.aggregate(100..200), 550);
+            }
+        }
+
+        mod comparator {
+            use crate::bplustree::BPlusTree;
+            use crate::bplustree::aggregate::Aggregate;
+            use crate::bplustree::comparator::Comparator;
+            use crate::bplustree::debug::{verify, verify_summaries};
+            use std::cmp::Ordering;
+            use std::ops::Bound;
+
+            #[derive(Debug, Clone)]
+            struct Descending;
+
+            impl Comparator<i32> for Descending {
+                fn compare(&self, a: &i32, b: &i32) -> Ordering {
+                    b.cmp(a)
+                }
+            }
+
+            #[derive(Debug, Clone)]
+            struct Keys;
+
+            impl Aggregate<i32, ()> for Keys {
+                type Summary = Vec<i32>;
+
+                fn identity(&self) -> Self::Summary {
+                    vec![]
+                }
+
+                fn summarize_entry(&self, k: &i32, _: &()) -> Self::Summary {
+                    vec![*k]
+                }
+
+                fn combine(&self, left: &Self::Summary, right: &Self::Summary) -> Self::Summary {
+                    left.iter().chain(right).copied().collect()
+                }
+            }
+
+            #[test]
+            fn descending_order() {
+                for order in [3, 4, 5] {
+                    let mut btree = BPlusTree::with_comparator(order, Descending);
+                    for k in 0..200 {
+                        btree.insert((k * 37) % 200, k);
+                        verify(&btree);
+                    }
+
+                    let keys: Vec<_> = btree.keys().copied().collect();
+                    assert_eq!(keys, (0..200).rev().collect::<Vec<_>>());
+                    assert_eq!(btree.smallest_key(), Some(&199));
+                    assert_eq!(btree.largest_key(), Some(&0));
+
+                    for k in (0..200).step_by(3) {
+                        assert!(btree.remove(&k).is_some());
+                        verify(&btree);
+                    }
+
+                    let keys: Vec<_> = btree
+                        .range((Bound::Included(150), Bound::Included(100)))
+                        .map(|(k, _)| *k)
+                        .collect();
+                    let expected: Vec<_> = (100..=150).rev().filter(|k| k % 3 != 0).collect();
+                    assert_eq!(keys, expected);
+
+                    assert_eq!(btree.rank(&150), (151..200).filter(|k| k % 3 != 0).count());
+                    assert_eq!(
+                        btree.count_range((Bound::Included(150), Bound::Included(100))),
+                        expected.len()
+                    );
+
+                    let cursor = btree.lower_bound(Bound::Excluded(&100));
+                    assert_eq!(cursor.key(), Some(&98));
+
+                    let mut right = btree.split_off(&100);
+                    verify(&btree);
+                    verify(&right);
+                    assert!(btree.keys().all(|k| *k > 100));
+                    assert!(right.keys().all(|k| *k <= 100));
+
+                    btree.append(&mut right);
+                    verify(&btree);
+                    let keys: Vec<_> = btree.keys().copied().collect();
+                    let expected: Vec<_> = (0..200).rev().filter(|k| k % 3 != 0).collect();
+                    assert_eq!(keys, expected);
+                }
+            }
+
+            #[test]
+            fn case_insensitive_keys() {
+                let mut btree = BPlusTree::with_comparator(4, |a: &String, b: &String| {
+                    a.to_lowercase().cmp(&b.to_lowercase())
+                });
+
+                for word in ["banana", "Apple", "cherry", "Date", "elderberry", "fig"] {
+                    btree.insert(word.to_string(), word.len());
+                }
+                assert_eq!(btree.insert("APPLE".to_string(), 0), Some(5));
+                assert_eq!(btree.size(), 6);
+                verify(&btree);
+
+                assert_eq!(btree.find(&"apple".to_string()), Some(&0));
+                assert_eq!(btree.find(&"DATE".to_string()), Some(&4));
+                *btree.entry("CHERRY".to_string()).or_insert(0) += 10;
+                assert_eq!(btree.get_mut(&"Cherry".to_string()), Some(&mut 16));
+
+                let keys: Vec<_> = btree.keys().map(String::as_str).collect();
+                assert_eq!(
+                    keys,
+                    ["APPLE", "banana", "cherry", "Date", "elderberry", "fig"]
+                );
+
+                assert_eq!(btree.remove(&"FIG".to_string()), Some(3));
+                verify(&btree);
+            }
+
+            #[test]
+            fn aggregate_combines_in_comparator_order() {
+                let mut btree = BPlusTree::with_aggregate_and_comparator(3, Keys, Descending);
+                for k in 0..100 {
+                    btree.insert(k, ());
+                }
+                verify_summaries(&btree);
+
+                assert_eq!(btree.aggregate(..), (0..100).rev().collect::<Vec<_>>());
+                assert_eq!(
+                    btree.aggregate((Bound::Included(60), Bound::Excluded(40))),
+                    (41..=60).rev().collect::<Vec<_>>()
+                );
+            }
+        }
+
+        mod invariants {
+            use crate::bplustree::BPlusTree;
+            use crate::bplustree::aggregate::Aggregate;
+            use crate::bplustree::invariants::{InvariantViolation, ViolationKind};
+            use crate::bplustree::stats::TreeStats;
+            use crate::bplustree::tests::{filled, tree};
+            use std::mem::swap;
+
+            #[derive(Debug, Clone)]
+            struct Sum;
+
+            impl Aggregate<i32, i32> for Sum {
+                type Summary = i64;
+
+                fn identity(&self) -> Self::Summary {
+                    0
+                }
+
+                fn summarize_entry(&self, _: &i32, v: &i32) -> Self::Summary {
+                    *v as i64
+                }
+
+                fn combine(&self, left: &Self::Summary, right: &Self::Summary) -> Self::Summary {
+                    left + right
+                }
+            }
+
+            #[test]
+            fn empty_tree() {
+                let btree: BPlusTree<i32, i32> = BPlusTree::new(4);
+                assert_eq!(btree.check_invariants(), Ok(TreeStats::default()));
+            }
+
+            #[test]
+            fn returns_stats() {
+                for n in [3, 16] {
+                    let btree = tree(4, 0..n);
+                    let stats = btree.check_invariants().unwrap();
+                    assert_eq!(stats, btree.stats());
+                    assert_eq!(stats.entries, n as usize);
+                }
+            }
+
+            #[test]
+            fn holds_through_every_mutation() {
+                for order in [3, 4, 5, 8] {
+                    let mut btree = BPlusTree::new(order);
+                    for k in 0..300 {
+                        btree.insert((k * 67) % 300, k);
+                        btree.check_invariants().unwrap();
+                    }
+
+                    for k in (0..300).filter(|k| k % 3 != 0) {
+                        btree.remove(&k);
+                        btree.check_invariants().unwrap();
+                    }
+
+                    let mut right = btree.split_off(&150);
+                    btree.check_invariants().unwrap();
+                    right.check_invariants().unwrap();
+
+                    btree.append(&mut right);
+                    assert_eq!(btree.check_invariants().unwrap().entries, 100);
+                }
+            }
+
+            #[test]
+            fn unsorted_leaf() {
+                let btree = tree(4, 0..16);
+                let mut leaf_ptr = btree.find_leaf_node_raw(&6).unwrap();
+                let leaf = unsafe { leaf_ptr.as_mut().as_leaf_mut() };
+                leaf.data.swap(0, 1);
+
+                let violation = btree.check_invariants().unwrap_err();
+                assert_eq!(violation.path, vec![1, 1]);
+                assert_eq!(violation.kind, ViolationKind::UnsortedKey { index: 1 });
+            }
+
+            #[test]
+            fn separator_mismatch() {
+                let btree = tree(4, 0..16);
+                let mut root_ptr = btree.root.unwrap();
+                let root = unsafe { root_ptr.as_mut().as_internal_mut() };
+                root.links[1].0 = 7;
+
+                assert_eq!(
+                    btree.check_invariants(),
+                    Err(InvariantViolation {
+                        path: vec![],
+                        kind: ViolationKind::SeparatorMismatch { index: 1 },
+                    })
+                );
+            }
+
+            #[test]
+            fn underfull_and_overfull() {
+                let btree = tree(4, 0..16);
+                let mut leaf_ptr = btree.find_leaf_node_raw(&9).unwrap();
+                let leaf = unsafe { leaf_ptr.as_mut().as_leaf_mut() };
+                let (k, v) = leaf.data.pop().unwrap();
+
+                let violation = btree.check_invariants().unwrap_err();
+                assert_eq!(violation.path, vec![2, 0]);
+                assert_eq!(violation.kind, ViolationKind::Underfull { size: 1, min: 2 });
+
+                let leaf = unsafe { leaf_ptr.as_mut().as_leaf_mut() };
+                leaf.data.push((k, v));
+                leaf.data.extend([(16, 16), (17, 17), (18, 18)]);
+
+                let violation = btree.check_invariants().unwrap_err();
+                assert_eq!(violation.path, vec![2, 0]);
+                assert_eq!(violation.kind, ViolationKind::Overfull { size: 5, max: 4 });
+            }
+
+            #[test]
+            fn uneven_leaf_depth() {
+                let btree = tree(4, 0..16);
+                let mut root_ptr = btree.root.unwrap();
+                let root = unsafe { root_ptr.as_mut().as_internal_mut() };
+                let (_, mut middle_ptr) = root.links[1];
+                let middle = unsafe { middle_ptr.as_mut().as_internal_mut() };
+                let (_, mut leaf_ptr) = middle.links[0];
+
+                // Hang the first leaf of the middle subtree directly from the root
+                let mut links = vec![];
+                swap(&mut middle.links, &mut links);
+                middle.links = links.split_off(1);
+                middle.counts.remove(0);
+                middle.summaries.remove(0);
+                root.links.insert(1, links.pop().unwrap());
+                root.links[2].0 = 6;
+                root.counts.insert(1, 2);
+                root.summaries.insert(1, ());
+                unsafe { leaf_ptr.as_mut().set_parent(Some(root_ptr)) };
+
+                let violation = btree.check_invariants().unwrap_err();
+                assert_eq!(violation.path, vec![1]);
+                assert_eq!(
+                    violation.kind,
+                    ViolationKind::LeafDepth {
+                        depth: 2,
+                        expected: 3,
+                    }
+                );
+
+                // Undo, so the tree can be dropped
+                let root = unsafe { root_ptr.as_mut().as_internal_mut() };
+                let link = root.links.remove(1);
+                root.counts.remove(1);
+                root.summaries.remove(1);
+                root.links[1].0 = 4;
+                let middle = unsafe { middle_ptr.as_mut().as_internal_mut() };
+                middle.links.insert(0, link);
+                middle.counts.insert(0, 2);
+                middle.summaries.insert(0, ());
+                unsafe { leaf_ptr.as_mut().set_parent(Some(middle_ptr)) };
+                btree.check_invariants().unwrap();
+            }
+
+            #[test]
+            fn summary_mismatch() {
+                let btree = filled(BPlusTree::with_aggregate(4, Sum), 0..16);
+                btree.check_invariants().unwrap();
+
+                let mut leaf_ptr = btree.find_leaf_node_raw(&9).unwrap();
+                let leaf = unsafe { leaf_ptr.as_mut().as_leaf_mut() };
+                leaf.data[0].1 += 1;
+
+                let violation = btree.check_invariants().unwrap_err();
+                assert_eq!(violation.path, vec![2]);
+                assert_eq!(violation.kind, ViolationKind::SummaryMismatch { index: 0 });
+                assert_eq!(
+                    violation.to_string(),
+                    "node [2]: summary of the link at index 0 doesn't match its subtree"
+                );
+            }
+
+            #[test]
+            fn size_counter() {
+                let mut btree = tree(4, 0..16);
+                btree.size += 1;
+
+                let violation = btree.check_invariants().unwrap_err();
+                assert_eq!(violation.path, vec![]);
+                assert_eq!(
+                    violation.kind,
+                    ViolationKind::SizeMismatch {
+                        size: 17,
+                        counted: 16,
+                    }
+                );
+                assert_eq!(
+                    violation.to_string(),
+                    "node []: tree size is 17, but its leaves hold 16 entries"
+                );
+                btree.size -= 1;
+            }
+        }
+
+        mod stats {
+            use crate::bplustree::BPlusTree;
+            use crate::bplustree::internal::Link;
+            use crate::bplustree::node::Node;
+            use crate::bplustree::stats::{FILL_BUCKETS, HeapUsage, TreeStats};
+            use std::mem::size_of;
+
+            #[test]
+            fn empty_tree() {
+                let btree: BPlusTree<i32, i32> = BPlusTree::new(4);
+                assert_eq!(btree.stats(), TreeStats::default());
+            }
+
+            #[test]
+            fn shape_and_fill() {
+                let mut btree = BPlusTree::new(4);
+                for k in 0..16 {
+                    btree.insert(k, k);
+                }
+
+                let stats = btree.stats();
+                assert_eq!(stats.height, 3);
+                assert_eq!(stats.internal_nodes, 4);
+                assert_eq!(stats.leaf_nodes, 7);
+                assert_eq!(stats.entries, 16);
+                // Six leaves and two Internal nodes are half full, the last leaf is full
+                assert_eq!(stats.fill_histogram, [0, 0, 0, 0, 0, 8, 0, 2, 0, 1]);
+                assert_eq!(stats.average_leaf_occupancy, 16.0 / 28.0);
+            }
+
+            #[test]
+            fn bulk_loaded_nodes_are_full() {
+                let btree = BPlusTree::from_sorted_iter(4, (0..64).map(|k| (k, k))).unwrap();
+                let stats = btree.stats();
+                assert_eq!(stats.height, 3);
+                assert_eq!(stats.fill_histogram[FILL_BUCKETS - 1], 21);
+                assert_eq!(stats.fill_histogram.iter().sum::<usize>(), 21);
+                assert_eq!(stats.average_leaf_occupancy, 1.0);
+            }
+
+            #[test]
+            fn heap_usage() {
+                let mut btree = BPlusTree::new(4);
+                for k in 0..16 {
+                    btree.insert(k, k);
+                }
+
+                let HeapUsage {
+                    nodes,
+                    entries,
+                    spare_capacity,
+                } = btree.stats().heap;
+                assert_eq!(nodes, 11 * size_of::<Node<i32, i32>>());
+                let links = 10 * (size_of::<Link<i32, i32>>() + size_of::<usize>());
+                assert_eq!(entries, 16 * size_of::<(i32, i32)>() + links);
+                assert_eq!(btree.stats().heap.total(), nodes + entries + spare_capacity);
+
+                // Removing entries keeps the capacity around
+                let before = btree.stats().heap;
+                btree.pop_last();
+                let after = btree.stats().heap;
+                assert_eq!(after.nodes, before.nodes);
+                assert!(after.spare_capacity > before.spare_capacity);
+                assert_eq!(after.entries, before.entries - size_of::<(i32, i32)>());
+            }
+        }
+
+        mod export {
+            use crate::bplustree::BPlusTree;
+            use crate::bplustree::debug::DebugOptions;
+
+            #[test]
+            fn empty_tree() {
+                let btree: BPlusTree<i32, i32> = BPlusTree::new(4);
+
+                let mut dot = String::new();
+                btree.write_dot(&mut dot, DebugOptions::default()).unwrap();
+                assert_eq!(dot, "digraph BPlusTree {\n    node [shape=record];\n}\n");
+
+                let mut json = String::new();
+                btree
+                    .write_json(&mut json, DebugOptions::default())
+                    .unwrap();
+                assert_eq!(json, r#"{"order":4,"size":0,"root":null}"#);
+            }
+
+            #[test]
+            fn dot_nodes_and_edges() {
+                let mut btree = BPlusTree::new(4);
+                for k in 0..16 {
+                    btree.insert(k, k * 10);
+                }
+
+                let mut dot = String::new();
+                btree.write_dot(&mut dot, DebugOptions::default()).unwrap();
+                assert!(dot.starts_with("digraph BPlusTree {\n"));
+                assert!(dot.ends_with("}\n"));
+
+                let stats = btree.stats();
+                let nodes = dot.lines().filter(|line| line.contains("[label=")).count();
+                assert_eq!(nodes, stats.internal_nodes + stats.leaf_nodes);
+                let child_edges = dot
+                    .lines()
+                    .filter(|line| line.ends_with(";") && line.contains(":f"))
+                    .count();
+                assert_eq!(child_edges, nodes - 1);
+                let parent_edges = dot
+                    .lines()
+                    .filter(|line| line.contains("style=dashed"))
+                    .count();
+                assert_eq!(parent_edges, nodes - 1);
+
+                assert!(dot.contains("    n0 [label=\"<f0> 0|<f1> 4|<f2> 8\"];\n"));
+                assert!(dot.contains("    n0:f2 -> n7;\n"));
+                assert!(dot.contains("[label=\"12: 120|13: 130|14: 140|15: 150\"]"));
+                assert!(!dot.contains("0x"));
+            }
+
+            #[test]
+            fn dot_escapes_record_labels() {
+                let mut btree = BPlusTree::new(4);
+                btree.insert("{a|b}".to_string(), "<\"c\">");
+
+                let mut dot = String::new();
+                btree.write_dot(&mut dot, DebugOptions::default()).unwrap();
+                assert!(dot.contains(r#"[label="\"\{a\|b\}\": \"\<\\\"c\\\"\>\""];"#));
+            }
+
+            #[test]
+            fn dot_addresses() {
+                let mut btree = BPlusTree::new(4);
+                for k in 0..8 {
+                    btree.insert(k, k);
+                }
+
+                let mut dot = String::new();
+                btree
+                    .write_dot(&mut dot, DebugOptions::default().all_address())
+                    .unwrap();
+                let root_ptr = btree.root.unwrap();
+                assert!(dot.contains(&format!("[label=\"[{root_ptr:p} \\| parent: None]|")));
+                assert!(dot.contains(&format!("parent: Some({root_ptr:p})")));
+
+                let mut dot = String::new();
+                btree
+                    .write_dot(&mut dot, DebugOptions::default().leaf_address())
+                    .unwrap();
+                assert!(!dot.contains(&format!("[{root_ptr:p}")));
+                assert!(dot.contains(&format!("parent: Some({root_ptr:p})")));
+            }
+
+            #[test]
+            fn json_tree() {
+                let mut btree = BPlusTree::new(4);
+                for k in 0..5 {
+                    btree.insert(k, format!("v{k}"));
+                }
+
+                let mut json = String::new();
+                btree
+                    .write_json(&mut json, DebugOptions::default())
+                    .unwrap();
+                assert_eq!(
+                    json,
+                    concat!(
+                        r#"{"order":4,"size":5,"root":{"type":"internal","keys":["0","2"],"counts":[2,3],"children":["#,
+                        r#"{"type":"leaf","keys":["0","1"],"values":["\"v0\"","\"v1\""]},"#,
+                        r#"{"type":"leaf","keys":["2","3","4"],"values":["\"v2\"","\"v3\"","\"v4\""]}]}}"#
+                    )
+                );
+            }
+
+            #[test]
+            fn json_addresses() {
+                let mut btree = BPlusTree::new(4);
+                for k in 0..5 {
+                    btree.insert(k, k);
+                }
+
+                let mut json = String::new();
+                btree
+                    .write_json(&mut json, DebugOptions::default().all_address())
+                    .unwrap();
+                let root_ptr = btree.root.unwrap();
+                assert!(json.contains(&format!(
+                    r#"{{"type":"internal","address":"{root_ptr:p}","parent":null,"#
+                )));
+                assert_eq!(
+                    json.matches(&format!(r#""parent":"{root_ptr:p}""#)).count(),
+                    2
+                );
+            }
+
+            #[test]
+            fn io_writers() {
+                let mut btree = BPlusTree::new(4);
+                for k in 0..16 {
+                    btree.insert(k, k);
+                }
+
+                let mut expected = String::new();
+                btree
+                    .write_dot(&mut expected, DebugOptions::default())
+                    .unwrap();
+                let mut bytes = vec![];
+                btree
+                    .write_dot_io(&mut bytes, DebugOptions::default())
+                    .unwrap();
+                assert_eq!(String::from_utf8(bytes).unwrap(), expected);
+
+                let mut expected = String::new();
+                btree
+                    .write_json(&mut expected, DebugOptions::default())
+                    .unwrap();
+                let mut bytes = vec![];
+                btree
+                    .write_json_io(&mut bytes, DebugOptions::default())
+                    .unwrap();
+                assert_eq!(String::from_utf8(bytes).unwrap(), expected);
+
+                // Errors of the writer are passed on
+                let mut full = [0u8; 8];
+                let error = btree
+                    .write_json_io(&mut full.as_mut_slice(), DebugOptions::default())
+                    .unwrap_err();
+                assert_eq!(error.kind(), std::io::ErrorKind::WriteZero);
+            }
+        }
+        mod extract {
+            use crate::bplustree::BPlusTree;
+            use crate::bplustree::aggregate::Aggregate;
+            use crate::bplustree::debug::{verify, verify_summaries};
+            use rand::rngs::StdRng;
+            use rand::{Rng, SeedableRng};
+            use std::collections::BTreeMap;
+            use std::ops::Bound;
+            use std::panic::{AssertUnwindSafe, catch_unwind};
+
+            #[derive(Debug, Clone)]
+            struct Sum;
+
+            impl Aggregate<i32, i32> for Sum {
+                type Summary = i64;
+
+                fn identity(&self) -> Self::Summary {
+                    0
+                }
+
+                fn summarize_entry(&self, _: &i32, v: &i32) -> Self::Summary {
+                    *v as i64
+                }
+
+                fn combine(&self, left: &Self::Summary, right: &Self::Summary) -> Self::Summary {
+                    left + right
+                }
+            }
+
+            fn check(btree: &BPlusTree<i32, i32>, expected: &BTreeMap<i32, i32>) {
+                verify(btree);
+                if let Err(violation) = btree.check_invariants() {
+                    panic!("{violation}");
+                }
+                assert_eq!(btree.size(), expected.len());
+                assert!(btree.iter().eq(expected.iter()));
+                assert!(btree.iter().rev().eq(expected.iter().rev()));
+            }
+
+            #[test]
+            fn retain_every_other() {
+                let mut btree = BPlusTree::new(4);
+                let mut expected = BTreeMap::new();
+                for k in 0..1000 {
+                    btree.insert(k, k);
+                    expected.insert(k, k);
+                }
+
+                btree.retain(|k, _| k % 2 == 0);
+                expected.retain(|k, _| k % 2 == 0);
+                check(&btree, &expected);
+            }
+
+            #[test]
+            fn retain_almost_nothing() {
+                for order in 3..8 {
+                    let mut btree = BPlusTree::new(order);
+                    let mut expected = BTreeMap::new();
+                    for k in 0..500 {
+                        btree.insert(k, k);
+                        expected.insert(k, k);
+                    }
+
+                    btree.retain(|k, _| k % 97 == 13);
+                    expected.retain(|k, _| k % 97 == 13);
+                    check(&btree, &expected);
+
+                    btree.retain(|_, _| false);
+                    assert!(btree.root.is_none());
+                    assert_eq!(btree.size(), 0);
+                    assert_eq!(btree.iter().next(), None);
+                }
+            }
+
+            #[test]
+            fn retain_on_empty_and_single_leaf() {
+                let mut btree: BPlusTree<i32, i32> = BPlusTree::new(4);
+                btree.retain(|_, _| false);
+                assert!(btree.root.is_none());
+
+                btree.insert(1, 1);
+                btree.insert(2, 2);
+                btree.retain(|k, _| *k == 2);
+                check(&btree, &BTreeMap::from([(2, 2)]));
+            }
+
+            #[test]
+            fn retain_changes_values() {
+                let mut btree = BPlusTree::with_aggregate(4, Sum);
+                for k in 0..100 {
+                    btree.insert(k, k);
+                }
+
+                btree.retain(|k, v| {
+                    *v *= 2;
+                    k % 3 != 0
+                });
+                verify_summaries(&btree);
+                let expected: i64 = (0..100).filter(|k| k % 3 != 0).map(|k| 2 * k as i64).sum();
+                assert_eq!(btree.aggregate(..), expected);
+                assert_eq!(btree.find(&50), Some(&100));
+            }
+
+            #[test]
+            fn extract_if_range() {
+                let mut btree = BPlusTree::new(4);
+                let mut expected = BTreeMap::new();
+                for k in 0..300 {
+                    btree.insert(k, k);
+                    expected.insert(k, k);
+                }
+
+                let extracted = btree
+                    .extract_if(100..200, |k, _| k % 3 != 0)
+                    .collect::<Vec<_>>();
+                let expected_extracted = expected
+                    .extract_if(100..200, |k, _| k % 3 != 0)
+                    .collect::<Vec<_>>();
+                assert_eq!(extracted, expected_extracted);
+                check(&btree, &expected);
+
+                let extracted = btree.extract_if(..=50, |_, _| true).collect::<Vec<_>>();
+                assert_eq!(extracted, (0..=50).map(|k| (k, k)).collect::<Vec<_>>());
+                expected.retain(|k, _| *k > 50);
+                check(&btree, &expected);
+
+                let extracted = btree.extract_if(290.., |_, _| true).count();
+                assert_eq!(extracted, 10);
+                expected.retain(|k, _| *k < 290);
+                check(&btree, &expected);
+            }
+
+            #[test]
+            fn extract_if_borrowed_range() {
+                let mut btree = BPlusTree::new(4);
+                for k in 0..100 {
+                    btree.insert(format!("{k:02}"), k);
+                }
+
+                let range = (Bound::Included("20"), Bound::Excluded("40"));
+                let extracted = btree
+                    .extract_if::<str, _, _>(range, |_, v| *v % 2 == 0)
+                    .map(|(_, v)| v)
+                    .collect::<Vec<_>>();
+                assert_eq!(extracted, (20..40).step_by(2).collect::<Vec<_>>());
+                verify(&btree);
+                assert_eq!(btree.size(), 90);
+                assert_eq!(btree.count_range::<str, _>(range), 10);
+            }
+
+            #[test]
+            fn extract_if_is_lazy() {
+                let mut btree = BPlusTree::new(4);
+                for k in 0..100 {
+                    btree.insert(k, k);
+                }
+
+                let mut seen = vec![];
+                let mut iter = btree.extract_if(.., |k, _| {
+                    seen.push(*k);
+                    true
+                });
+                assert_eq!(iter.next(), Some((0, 0)));
+                drop(iter);
+                // Only the first leaf was handed to the predicate
+                assert_eq!(seen, vec![0, 1]);
+
+                let mut expected = (2..100).map(|k| (k, k)).collect::<BTreeMap<_, _>>();
+                check(&btree, &expected);
+
+                let first = btree.extract_if(.., |k, _| k % 10 == 0).take(3);
+                assert_eq!(
+                    first.collect::<Vec<_>>(),
+                    vec![(10, 10), (20, 20), (30, 30)]
+                );
+                expected.retain(|k, _| ![10, 20, 30].contains(k));
+                check(&btree, &expected);
+            }
+
+            #[test]
+            fn extract_if_survives_panicking_pred() {
+                let mut btree = BPlusTree::with_aggregate(4, Sum);
+                for k in 0..100 {
+                    btree.insert(k, k);
+                }
+
+                let result = catch_unwind(AssertUnwindSafe(|| {
+                    btree
+                        .extract_if(.., |k, v| {
+                            *v += 1;
+                            assert_ne!(*k, 41);
+                            k % 2 == 0
+                        })
+                        .for_each(drop);
+                }));
+                assert!(result.is_err());
+
+                // Leaves before the panic were handled, the one it happened in kept every entry
+                verify_summaries(&btree);
+                if let Err(violation) = btree.check_invariants() {
+                    panic!("{violation}");
+                }
+                let entries = btree.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>();
+                assert_eq!(btree.size(), entries.len());
+                assert!((1..41).step_by(2).all(|k| btree.find(&k) == Some(&(k + 1))));
+                assert_eq!(btree.find(&0), None);
+                assert!(
+                    entries
+                        .iter()
+                        .filter(|(k, _)| *k > 41)
+                        .eq((42..100).map(|k| (k, k)).collect::<Vec<_>>().iter())
+                );
+                let sum: i64 = entries.iter().map(|(_, v)| *v as i64).sum();
+                assert_eq!(btree.aggregate(..), sum);
+            }
+
+            #[test]
+            fn extract_at_random() {
+                let mut rng = StdRng::seed_from_u64(18);
+                for _ in 0..20 {
+                    let order = rng.random_range(3..10);
+                    let mut btree = BPlusTree::new(order);
+                    let mut expected = BTreeMap::new();
+                    for _ in 0..rng.random_range(0..2000) {
+                        let k = rng.random_range(-1000..1000);
+                        btree.insert(k, k);
+                        expected.insert(k, k);
+                    }
+
+                    let start = rng.random_range(-1100..1100);
+                    let end = rng.random_range(start..1100);
+                    let modulo = rng.random_range(1..5);
+                    let extracted = btree
+                        .extract_if(start..end, |k, _| k % modulo != 0)
+                        .collect::<Vec<_>>();
+                    let expected_extracted = expected
+                        .extract_if(start..end, |k, _| k % modulo != 0)
+                        .collect::<Vec<_>>();
+                    assert_eq!(extracted, expected_extracted);
+                    check(&btree, &expected);
+                }
+            }
+        }
+        mod traits {
+            use crate::bplustree::aggregate::Aggregate;
+            use crate::bplustree::debug::{DebugOptions, verify, verify_summaries};
+            use crate::bplustree::tests::tree;
+            use crate::bplustree::{BPlusTree, DEFAULT_ORDER};
+            use std::collections::BTreeMap;
+            use std::collections::hash_map::DefaultHasher;
+            use std::hash::{Hash, Hasher};
+            use std::thread;
+
+            #[derive(Debug, Clone, Default)]
+            struct Sum;
+
+            impl Aggregate<i32, i32> for Sum {
+                type Summary = i64;
+
+                fn identity(&self) -> Self::Summary {
+                    0
+                }
+
+                fn summarize_entry(&self, _: &i32, v: &i32) -> Self::Summary {
+                    *v as i64
+                }
+
+                fn combine(&self, left: &Self::Summary, right: &Self::Summary) -> Self::Summary {
+                    left + right
+                }
+            }
+
+            fn hash<T: Hash>(value: &T) -> u64 {
+                let mut hasher = DefaultHasher::new();
+                value.hash(&mut hasher);
+                hasher.finish()
+            }
+
+            #[test]
+            fn clone_copies_every_node() {
+                let mut btree = tree(4, 0..100);
+                btree.remove(&50);
+                let clone = btree.clone();
+
+                verify(&clone);
+                let (stats, clone_stats) = (btree.stats(), clone.check_invariants().unwrap());
+                assert_eq!(clone_stats.fill_histogram, stats.fill_histogram);
+                assert_eq!(clone_stats.heap.nodes, stats.heap.nodes);
+                let options = DebugOptions::default();
+                assert_eq!(
+                    clone.display(options).to_string(),
+                    btree.display(options).to_string()
+                );
+                assert_ne!(clone.root, btree.root);
+                assert!(clone.iter().rev().eq(btree.iter().rev()));
+
+                drop(btree);
+                assert_eq!(clone.size(), 99);
+                assert_eq!(clone.find(&99), Some(&99));
+            }
+
+            #[test]
+            fn clone_keeps_summaries() {
+                let mut btree = BPlusTree::with_aggregate(4, Sum);
+                for k in 0..100 {
+                    btree.insert(k, k);
+                }
+
+                let mut clone = btree.clone();
+                verify_summaries(&clone);
+                assert_eq!(clone.aggregate(10..20), btree.aggregate(10..20));
+
+                clone.insert(1000, 1000);
+                assert_eq!(clone.aggregate(..), btree.aggregate(..) + 1000);
+            }
+
+            #[test]
+            fn equality_ignores_shape() {
+                let a = tree(4, 0..100);
+                let b = tree(7, (0..100).rev());
+                assert_eq!(a, b);
+                assert_eq!(hash(&a), hash(&b));
+
+                let mut c = tree(4, 0..100);
+                c.insert(50, 0);
+                assert_ne!(a, c);
+
+                c.insert(50, 50);
+                c.insert(100, 100);
+                assert_ne!(a, c);
+            }
+
+            #[test]
+            fn ordering_matches_btree_map() {
+                let trees = [
+                    vec![],
+                    vec![(1, 1)],
+                    vec![(1, 2)],
+                    vec![(1, 1), (2, 2)],
+                    vec![(2, 0)],
+                ];
+
+                for a in &trees {
+                    for b in &trees {
+                        let tree_a = a.iter().copied().collect::<BPlusTree<_, _>>();
+                        let tree_b = b.iter().copied().collect::<BPlusTree<_, _>>();
+                        let map_a = a.iter().copied().collect::<BTreeMap<_, _>>();
+                        let map_b = b.iter().copied().collect::<BTreeMap<_, _>>();
+                        assert_eq!(tree_a.cmp(&tree_b), map_a.cmp(&map_b));
+                        assert_eq!(tree_a.partial_cmp(&tree_b), map_a.partial_cmp(&map_b));
+                    }
+                }
+            }
+
+            #[test]
+            fn default_extend_and_index() {
+                let mut btree: BPlusTree<i32, i32> = BPlusTree::default();
+                assert_eq!(btree.max_node_size(), DEFAULT_ORDER);
+                assert_eq!(btree.size(), 0);
+
+                btree.extend((0..50).map(|k| (k, k * 2)));
+                btree.extend([(&100, &200), (&0, &1)]);
+                verify(&btree);
+                assert_eq!(btree.size(), 51);
+                assert_eq!(btree[&0], 1);
+                assert_eq!(btree[&49], 98);
+                assert_eq!(btree[&100], 200);
+
+                let mut strings = BPlusTree::<String, i32>::default();
+                strings.insert("a".to_string(), 1);
+                assert_eq!(strings["a"], 1);
+
+                let mut summed = BPlusTree::<i32, i32, Sum>::default();
+                summed.extend((0..10).map(|k| (k, k)));
+                assert_eq!(summed.aggregate(..), 45);
+            }
+
+            #[test]
+            #[should_panic(expected = "no entry found for key")]
+            fn index_missing_key() {
+                let btree = tree(4, 0..10);
+                let _ = btree[&10];
+            }
+
+            #[test]
+            fn debug_prints_entries() {
+                let mut btree = BPlusTree::new(4);
+                assert_eq!(format!("{btree:?}"), "{}");
+
+                btree.insert(2, "b");
+                btree.insert(1, "a");
+                assert_eq!(format!("{btree:?}"), r#"{1: "a", 2: "b"}"#);
+            }
+
+            #[test]
+            fn derive_on_containing_struct() {
+                #[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+                struct Index {
+                    name: String,
+                    entries: BPlusTree<String, usize>,
+                }
+
+                let mut index = Index::default();
+                index.entries.insert("a".to_string(), 1);
+                let clone = index.clone();
+                assert_eq!(index, clone);
+                assert_eq!(hash(&index), hash(&clone));
+                assert_eq!(
+                    format!("{index:?}"),
+                    r#"Index { name: "", entries: {"a": 1} }"#
+                );
+            }
+
+            #[test]
+            fn send_and_sync() {
+                fn assert_send_sync<T: Send + Sync>() {}
+                assert_send_sync::<BPlusTree<String, Vec<u8>>>();
+                assert_send_sync::<BPlusTree<i32, i32, Sum>>();
+
+                let btree = tree(4, 0..1000);
+                let sums = thread::scope(|scope| {
+                    let handles = (0..4)
+                        .map(|i| {
+                            let btree = &btree;
+                            scope.spawn(move || btree.range(i * 250..(i + 1) * 250).count())
+                        })
+                        .collect::<Vec<_>>();
+                    handles
+                        .into_iter()
+                        .map(|handle| handle.join().unwrap())
+                        .collect::<Vec<_>>()
+                });
+                assert_eq!(sums, vec![250; 4]);
+
+                let btree = thread::spawn(move || {
+                    let mut btree = btree;
+                    btree.remove_range(..500);
+                    btree
+                })
+                .join()
+                .unwrap();
+                verify(&btree);
+                assert_eq!(btree.size(), 500);
+            }
+        }
+        mod paged {
+            use crate::bplustree::paged::codec::{BinaryCodec, Codec, DecodeError};
+            use crate::bplustree::paged::pager::Pager;
+            use crate::bplustree::paged::pool::{BufferPool, Eviction};
+            use crate::bplustree::paged::{PageId, PagedBPlusTree, PagedError, PagedOptions};
+            use rand::rngs::StdRng;
+            use rand::{Rng, SeedableRng};
+            use std::collections::BTreeMap;
+            use std::fs::{self, OpenOptions};
+            use std::mem;
+            use std::ops::Bound;
+            use std::path::PathBuf;
+            use uuid::Uuid;
+
+            /// Path in the temp directory that is removed when the test ends
+            struct TempFile(PathBuf);
+
+            impl TempFile {
+                fn new() -> Self {
+                    Self(std::env::temp_dir().join(format!("bplustree-{}.db", Uuid::new_v4())))
+                }
+
+                fn wal(&self) -> PathBuf {
+                    self.0.with_extension("db.wal")
+                }
+            }
+
+            impl Drop for TempFile {
+                fn drop(&mut self) {
+                    let _ = fs::remove_file(&self.0);
+                    let _ = fs::remove_file(self.wal());
+                }
+            }
+
+            fn small_options() -> PagedOptions {
+                PagedOptions::default().order(4).page_size(256)
+            }
+
+            fn open(file: &TempFile) -> PagedBPlusTree<u32, u64> {
+                PagedBPlusTree::open(&file.0, small_options(), BinaryCodec).unwrap()
+            }
+
+            fn entries<K, V, D>(tree: &PagedBPlusTree<K, V, D>) -> Vec<(K, V)>
+            where
+                K: Ord + Clone,
+                D: Codec<K> + Codec<V>,
+            {
+                tree.iter().unwrap().collect::<Result<_, _>>().unwrap()
+            }
+
+            #[test]
+            fn insert_find_remove() {
+                let file = TempFile::new();
+                let mut tree = open(&file);
+                assert!(tree.is_empty());
+                assert_eq!(tree.find(&1).unwrap(), None);
+                assert_eq!(tree.remove(&1).unwrap(), None);
+
+                for i in 0..100 {
+                    assert_eq!(tree.insert(i, i as u64 * 10).unwrap(), None);
+                }
+                tree.verify().unwrap();
+                assert_eq!(tree.size(), 100);
+                assert_eq!(tree.insert(7, 0).unwrap(), Some(70));
+                assert_eq!(tree.find(&7).unwrap(), Some(0));
+                assert_eq!(tree.insert(7, 70).unwrap(), Some(0));
+                assert_eq!(tree.find(&99).unwrap(), Some(990));
+                assert_eq!(tree.find(&100).unwrap(), None);
+
+                for i in (0..100).step_by(2) {
+                    assert!(tree.remove(&i).unwrap().is_some());
+                    tree.verify().unwrap();
+                }
+                assert_eq!(tree.size(), 50);
+                assert!(!tree.contains(&10).unwrap());
+                assert!(tree.contains(&11).unwrap());
+
+                for i in (1..100).step_by(2) {
+                    assert_eq!(tree.remove(&i).unwrap(), Some(i as u64 * 10));
+                }
+                tree.verify().unwrap();
+                assert!(tree.is_empty());
+                assert_eq!(entries(&tree), vec![]);
+            }
+
+            #[test]
+            fn random_against_btreemap() {
+                let mut rng = StdRng::seed_from_u64(21);
+                let file = TempFile::new();
+                let mut tree = open(&file);
+                let mut expected = BTreeMap::new();
+
+                for i in 0..3000 {
+                    let k = rng.random_range(0..500);
+                    if rng.random_range(0..3) == 0 {
+                        assert_eq!(tree.remove(&k).unwrap(), expected.remove(&k));
+                    } else {
+                        assert_eq!(tree.insert(k, i).unwrap(), expected.insert(k, i));
+                    }
+
+                    if i % 500 == 0 {
+                        tree.verify().unwrap();
+                    }
+                }
+
+                tree.verify().unwrap();
+                assert_eq!(tree.size(), expected.len());
+                assert_eq!(entries(&tree), expected.into_iter().collect::<Vec<_>>());
+            }
+
+            #[test]
+            fn range() {
+                let file = TempFile::new();
+                let mut tree = open(&file);
+                let mut expected = BTreeMap::new();
+                for i in (0..200).step_by(3) {
+                    tree.insert(i, i as u64).unwrap();
+                    expected.insert(i, i as u64);
+                }
+
+                let bounds = [
+                    (Bound::Unbounded, Bound::Unbounded),
+                    (Bound::Included(30), Bound::Excluded(90)),
+                    (Bound::Excluded(30), Bound::Included(90)),
+                    (Bound::Included(31), Bound::Included(31)),
+                    (Bound::Included(31), Bound::Unbounded),
+                    (Bound::Unbounded, Bound::Excluded(1)),
+                    (Bound::Included(198), Bound::Unbounded),
+                    (Bound::Excluded(198), Bound::Unbounded),
+                    (Bound::Included(500), Bound::Included(600)),
+                ];
+                for range in bounds {
+                    let found = tree
+                        .range(range)
+                        .unwrap()
+                        .collect::<Result<Vec<_>, _>>()
+                        .unwrap();
+                    let expected = expected
+                        .range(range)
+                        .map(|(k, v)| (*k, *v))
+                        .collect::<Vec<_>>();
+                    assert_eq!(found, expected, "{range:?}");
+                }
+            }
+
+            #[test]
+            #[should_panic(expected = "range start is greater than range end in BPlusTree")]
+            fn range_start_after_end() {
+                let file = TempFile::new();
+                let tree = open(&file);
+                let _ = tree.range((Bound::Included(5), Bound::Included(1)));
+            }
+
+            #[test]
+            fn reopen_restores_tree() {
+                let mut rng = StdRng::seed_from_u64(21);
+                let file = TempFile::new();
+                let mut expected = BTreeMap::new();
+                {
+                    let mut tree = open(&file);
+                    for i in 0..1000 {
+                        let k = rng.random_range(0..2000);
+                        tree.insert(k, i).unwrap();
+                        expected.insert(k, i);
+                    }
+                    tree.flush().unwrap();
+                }
+
+                let mut tree = open(&file);
+                tree.verify().unwrap();
+                assert_eq!(tree.size(), expected.len());
+                assert_eq!(
+                    entries(&tree),
+                    expected.clone().into_iter().collect::<Vec<_>>()
+                );
+
+                let keys = expected.keys().copied().step_by(2).collect::<Vec<_>>();
+                for k in keys {
+                    assert_eq!(tree.remove(&k).unwrap(), expected.remove(&k));
+                }
+                drop(tree);
+
+                let tree = open(&file);
+                tree.verify().unwrap();
+                assert_eq!(entries(&tree), expected.into_iter().collect::<Vec<_>>());
+            }
+
+            #[test]
+            fn reopen_keeps_order_and_page_size() {
+                let file = TempFile::new();
+                {
+                    let mut tree = open(&file);
+                    tree.insert(1, 1).unwrap();
+                }
+
+                let options = PagedOptions::default().order(8).page_size(4096);
+                let tree = PagedBPlusTree::<u32, u64>::open(&file.0, options, BinaryCodec).unwrap();
+                assert_eq!(tree.order(), 4);
+                assert_eq!(tree.page_size(), 256);
+                assert_eq!(tree.find(&1).unwrap(), Some(1));
+            }
+
+            #[test]
+            fn freed_pages_are_reused() {
+                let file = TempFile::new();
+                let mut tree = open(&file);
+                for i in 0..500 {
+                    tree.insert(i, 0).unwrap();
+                }
+                tree.flush().unwrap();
+                let len = fs::metadata(&file.0).unwrap().len();
+
+                for i in 0..500 {
+                    tree.remove(&i).unwrap();
+                }
+                tree.verify().unwrap();
+                for i in (0..500).rev() {
+                    tree.insert(i, 0).unwrap();
+                }
+                tree.verify().unwrap();
+                tree.flush().unwrap();
+                assert!(fs::metadata(&file.0).unwrap().len() <= len * 2);
+
+                for _ in 0..3 {
+                    for i in 0..500 {
+                        tree.remove(&i).unwrap();
+                    }
+                    for i in 0..500 {
+                        tree.insert(i, 0).unwrap();
+                    }
+                }
+                tree.verify().unwrap();
+                tree.flush().unwrap();
+                assert!(fs::metadata(&file.0).unwrap().len() <= len * 2);
+            }
+
+            #[test]
+            fn string_keys() {
+                let file = TempFile::new();
+                let mut tree = PagedBPlusTree::<String, (u32, String)>::open(
+                    &file.0,
+                    PagedOptions::default(),
+                    BinaryCodec,
+                )
+                .unwrap();
+                let mut expected = BTreeMap::new();
+                for i in 0..500u32 {
+                    let k = format!("key-{}", i * 7 % 500);
+                    let v = (i, "v".repeat(i as usize % 20));
+                    tree.insert(k.clone(), v.clone()).unwrap();
+                    expected.insert(k, v);
+                }
+                tree.verify().unwrap();
+                assert_eq!(
+                    tree.find("key-42").unwrap(),
+                    expected.get("key-42").cloned()
+                );
+                assert_eq!(tree.remove("key-42").unwrap(), expected.remove("key-42"));
+                drop(tree);
+
+                let tree = PagedBPlusTree::<String, (u32, String)>::open(
+                    &file.0,
+                    PagedOptions::default(),
+                    BinaryCodec,
+                )
+                .unwrap();
+                assert_eq!(entries(&tree), expected.into_iter().collect::<Vec<_>>());
+            }
+
+            #[test]
+            fn entry_too_large() {
+                let file = TempFile::new();
+                let mut tree =
+                    PagedBPlusTree::<u32, Vec<u8>>::open(&file.0, small_options(), BinaryCodec)
+                        .unwrap();
+                assert_eq!(tree.max_entry_size(), 60);
+                tree.insert(1, vec![0; 52]).unwrap();
+
+                let error = tree.insert(2, vec![0; 53]).unwrap_err();
+                assert!(matches!(
+                    error,
+                    PagedError::EntryTooLarge { size: 61, max: 60 }
+                ));
+                assert_eq!(tree.size(), 1);
+                assert_eq!(tree.find(&2).unwrap(), None);
+            }
+
+            #[test]
+            fn invalid_options_and_files() {
+                let file = TempFile::new();
+                let error = PagedBPlusTree::<u32, u64>::open(
+                    &file.0,
+                    PagedOptions::default().order(2),
+                    BinaryCodec,
+                );
+                assert!(matches!(error, Err(PagedError::Options(_))));
+
+                let error = PagedBPlusTree::<u32, u64>::open(
+                    &file.0,
+                    PagedOptions::default().order(64).page_size(512),
+                    BinaryCodec,
+                );
+                assert!(matches!(error, Err(PagedError::Options(_))));
+
+                fs::write(&file.0, b"definitely not a tree, just some bytes in a file").unwrap();
+                let error = PagedBPlusTree::<u32, u64>::open(&file.0, small_options(), BinaryCodec);
+                assert!(matches!(error, Err(PagedError::Corrupt(_))));
+            }
+
+            #[test]
+            fn oversized_counts_and_page_sizes() {
+                let error = PagedBPlusTree::<u32, u64>::open(
+                    &TempFile::new().0,
+                    PagedOptions::default().page_size(1 << 30),
+                    BinaryCodec,
+                );
+                assert!(matches!(error, Err(PagedError::Options(_))));
+
+                let file = TempFile::new();
+                {
+                    let mut tree = open(&file);
+                    for i in 0..100 {
+                        tree.insert(i, 0).unwrap();
+                    }
+                }
+
+                // The entry count of the root page, right after its kind
+                let mut bytes = fs::read(&file.0).unwrap();
+                let root = u64::from_le_bytes(bytes[24..32].try_into().unwrap()) as usize;
+                bytes[root * 256 + 1..root * 256 + 5].copy_from_slice(&u32::MAX.to_le_bytes());
+                fs::write(&file.0, &bytes).unwrap();
+                let tree = open(&file);
+                assert!(matches!(tree.find(&1), Err(PagedError::Corrupt(_))));
+                drop(tree);
+
+                // The page size in the header, right after the magic
+                bytes[8..16].copy_from_slice(&u64::MAX.to_le_bytes());
+                fs::write(&file.0, &bytes).unwrap();
+                let error = PagedBPlusTree::<u32, u64>::open(&file.0, small_options(), BinaryCodec);
+                assert!(matches!(error, Err(PagedError::Corrupt(_))));
+            }
+
+            #[test]
+            fn hot_lookups_hit_the_pool() {
+                let file = TempFile::new();
+                let options = small_options().pool_capacity(16);
+                let mut tree =
+                    PagedBPlusTree::<u32, u64>::open(&file.0, options, BinaryCodec).unwrap();
+                for i in 0..5000 {
+                    tree.insert(i, i as u64).unwrap();
+                }
+                tree.verify().unwrap();
+                assert!(tree.cached_pages() <= 16);
+                assert!(tree.pool_stats().evictions > 0);
+
+                let before = tree.pool_stats();
+                for _ in 0..100 {
+                    assert_eq!(tree.find(&4242).unwrap(), Some(4242));
+                }
+                let after = tree.pool_stats();
+                assert!(after.misses - before.misses <= 16);
+                assert!(after.hits - before.hits >= 100 * 5);
+                assert_eq!(
+                    after.evictions - before.evictions,
+                    after.misses - before.misses
+                );
+            }
+
+            #[test]
+            fn small_pools_against_btreemap() {
+                let mut rng = StdRng::seed_from_u64(22);
+                for eviction in [Eviction::Lru, Eviction::Clock] {
+                    let file = TempFile::new();
+                    let options = small_options().pool_capacity(3).eviction(eviction);
+                    let mut expected = BTreeMap::new();
+                    {
+                        let mut tree =
+                            PagedBPlusTree::<u32, u64>::open(&file.0, options, BinaryCodec)
+                                .unwrap();
+                        assert_eq!(tree.eviction(), eviction);
+                        for i in 0..2000 {
+                            let k = rng.random_range(0..400);
+                            if rng.random_range(0..3) == 0 {
+                                assert_eq!(tree.remove(&k).unwrap(), expected.remove(&k));
+                            } else {
+                                assert_eq!(tree.insert(k, i).unwrap(), expected.insert(k, i));
+                            }
+                        }
+                        tree.verify().unwrap();
+                        assert_eq!(
+                            entries(&tree),
+                            expected.clone().into_iter().collect::<Vec<_>>()
+                        );
+                    }
+
+                    let tree =
+                        PagedBPlusTree::<u32, u64>::open(&file.0, options, BinaryCodec).unwrap();
+                    tree.verify().unwrap();
+                    assert_eq!(entries(&tree), expected.into_iter().collect::<Vec<_>>());
+                }
+            }
+
+            fn pool(file: &TempFile, capacity: usize, eviction: Eviction) -> BufferPool {
+                let file = OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .truncate(false)
+                    .open(&file.0)
+                    .unwrap();
+                BufferPool::new(Pager::new(file, 64), 64, capacity, eviction)
+            }
+
+            /// Writes `byte` over the whole page
+            fn fill(pool: &mut BufferPool, id: PageId, byte: u8) {
+                pool.pin_for_write(id).unwrap().fill(byte);
+                pool.unpin(id, true);
+            }
+
+            /// Pins and unpins the page, returning its first byte
+            fn touch(pool: &mut BufferPool, id: PageId) -> u8 {
+                let byte = pool.pin(id).unwrap()[0];
+                pool.unpin(id, false);
+                byte
+            }
+
+            #[test]
+            fn lru_evicts_least_recently_used() {
+                let file = TempFile::new();
+                let mut pool = pool(&file, 3, Eviction::Lru);
+                for id in 0..3 {
+                    fill(&mut pool, id, id as u8);
+                }
+
+                assert_eq!(touch(&mut pool, 0), 0);
+                fill(&mut pool, 3, 3);
+                assert_eq!(pool.stats().evictions, 1);
+
+                let before = pool.stats();
+                assert_eq!(touch(&mut pool, 0), 0);
+                assert_eq!(touch(&mut pool, 2), 2);
+                assert_eq!(touch(&mut pool, 3), 3);
+                assert_eq!(pool.stats().hits - before.hits, 3);
+                assert_eq!(pool.stats().misses, before.misses);
+
+                // Page 1 was evicted, its dirty bytes were written back first
+                assert_eq!(touch(&mut pool, 1), 1);
+                assert_eq!(pool.stats().misses - before.misses, 1);
+                assert_eq!(pool.len(), 3);
+            }
+
+            #[test]
+            fn clock_gives_a_second_chance() {
+                let file = TempFile::new();
+                let mut pool = pool(&file, 3, Eviction::Clock);
+                for id in 0..3 {
+                    fill(&mut pool, id, id as u8);
+                }
+
+                // The first sweep clears every reference bit and takes page 0
+                fill(&mut pool, 3, 3);
+                // Page 1 is used again, so the hand passes over it and takes page 2
+                touch(&mut pool, 1);
+                fill(&mut pool, 4, 4);
+                assert_eq!(pool.stats().evictions, 2);
+
+                let before = pool.stats();
+                touch(&mut pool, 1);
+                touch(&mut pool, 3);
+                touch(&mut pool, 4);
+                assert_eq!(pool.stats().misses, before.misses);
+                assert_eq!(touch(&mut pool, 2), 2);
+                assert_eq!(pool.stats().misses - before.misses, 1);
+            }
+
+            #[test]
+            fn pinned_pages_are_not_evicted() {
+                let file = TempFile::new();
+                for eviction in [Eviction::Lru, Eviction::Clock] {
+                    let mut pool = pool(&file, 2, eviction);
+                    pool.pin_for_write(0).unwrap().fill(7);
+                    pool.pin_for_write(1).unwrap().fill(8);
+                    assert!(matches!(pool.pin_for_write(2), Err(PagedError::PoolFull)));
+
+                    pool.unpin(1, true);
+                    fill(&mut pool, 2, 9);
+                    assert_eq!(pool.pin(0).unwrap()[0], 7);
+                    pool.unpin(0, true);
+                    pool.unpin(0, true);
+
+                    pool.flush().unwrap();
+                    assert_eq!(touch(&mut pool, 1), 8);
+                }
+            }
+
+            #[test]
+            fn dirty_pages_without_steal() {
+                let file = TempFile::new();
+                let mut pool = pool(&file, 2, Eviction::Lru);
+                pool.set_steal(false);
+                fill(&mut pool, 0, 1);
+                fill(&mut pool, 1, 2);
+                assert_eq!(pool.dirty_count(), 2);
+
+                // Neither dirty page can go, and the pool doesn't grow past its capacity
+                assert!(matches!(pool.pin_for_write(2), Err(PagedError::PoolFull)));
+                assert_eq!(pool.len(), 2);
+                assert_eq!(pool.stats().evictions, 0);
+
+                pool.flush().unwrap();
+                assert_eq!(pool.dirty_count(), 0);
+
+                // Flushed pages are evictable again, the least recently used one first
+                touch(&mut pool, 0);
+                fill(&mut pool, 3, 4);
+                assert_eq!(pool.stats().evictions, 1);
+                assert_eq!(pool.dirty_count(), 1);
+                let before = pool.stats();
+                assert_eq!(touch(&mut pool, 0), 1);
+                assert_eq!(pool.stats().hits - before.hits, 1);
+                assert_eq!(touch(&mut pool, 1), 2);
+                assert_eq!(pool.stats().misses - before.misses, 1);
+            }
+
+            #[test]
+            #[should_panic(expected = "Page 0 is unpinned more often than pinned")]
+            fn unpin_without_pin() {
+                let file = TempFile::new();
+                let mut pool = pool(&file, 2, Eviction::Lru);
+                fill(&mut pool, 0, 1);
+                pool.unpin(0, false);
+            }
+
+            /// Runs random inserts and removes on a tree with a WAL, returning the WAL length and
+            /// the expected entries after every operation
+            fn logged_operations(
+                rng: &mut StdRng,
+                tree: &mut PagedBPlusTree<u32, u64>,
+                expected: &mut BTreeMap<u32, u64>,
+                n: u64,
+            ) -> Vec<(u64, Vec<(u32, u64)>)> {
+                let mut states = vec![(tree.wal_len(), expected.clone().into_iter().collect())];
+                for i in 0..n {
+                    let k = rng.random_range(0..40);
+                    if rng.random_range(0..3) == 0 {
+                        assert_eq!(tree.remove(&k).unwrap(), expected.remove(&k));
+                    } else {
+                        assert_eq!(tree.insert(k, i).unwrap(), expected.insert(k, i));
+                    }
+                    states.push((tree.wal_len(), expected.clone().into_iter().collect()));
+                }
+                assert!(
+                    states.is_sorted_by_key(|(len, _)| *len),
+                    "no checkpoint in between"
+                );
+                states
+            }
+
+            /// Opens a copy of the crashed files, with the WAL cut after `offset` bytes
+            fn recover(data: &[u8], wal: &[u8], offset: usize) -> Vec<(u32, u64)> {
+                let crashed = TempFile::new();
+                fs::write(&crashed.0, data).unwrap();
+                fs::write(crashed.wal(), &wal[..offset]).unwrap();
+
+                let tree = PagedBPlusTree::<u32, u64>::open(
+                    &crashed.0,
+                    small_options().wal(true),
+                    BinaryCodec,
+                )
+                .unwrap();
+                tree.verify().unwrap();
+                assert_eq!(tree.wal_len(), 0);
+                entries(&tree)
+            }
+
+            #[test]
+            fn wal_recovers_the_last_committed_state() {
+                let mut rng = StdRng::seed_from_u64(23);
+                let file = TempFile::new();
+                let mut tree =
+                    PagedBPlusTree::open(&file.0, small_options().wal(true), BinaryCodec).unwrap();
+                let mut expected = BTreeMap::new();
+                for i in 0..100 {
+                    tree.insert(i * 2, 0).unwrap();
+                    expected.insert(i * 2, 0);
+                }
+                tree.checkpoint().unwrap();
+                assert_eq!(tree.wal_len(), 0);
+
+                let states = logged_operations(&mut rng, &mut tree, &mut expected, 60);
+                let data = fs::read(&file.0).unwrap();
+                let wal = fs::read(file.wal()).unwrap();
+                mem::forget(tree);
+
+                for offset in 0..=wal.len() {
+                    let (_, committed) = states
+                        .iter()
+                        .rev()
+                        .find(|(len, _)| *len <= offset as u64)
+                        .unwrap();
+                    assert_eq!(&recover(&data, &wal, offset), committed, "offset {offset}");
+                }
+            }
+
+            #[test]
+            fn wal_recovers_a_crashed_checkpoint() {
+                let mut rng = StdRng::seed_from_u64(23);
+                let file = TempFile::new();
+                let mut tree =
+                    PagedBPlusTree::open(&file.0, small_options().wal(true), BinaryCodec).unwrap();
+                let mut expected = BTreeMap::new();
+                let states = logged_operations(&mut rng, &mut tree, &mut expected, 80);
+                let (logged, committed) = states.last().unwrap().clone();
+
+                // The pages are logged, but the crash comes before they're written to the file
+                let data = fs::read(&file.0).unwrap();
+                tree.log_checkpoint().unwrap();
+                let wal = fs::read(file.wal()).unwrap();
+                assert!(wal.len() as u64 > logged);
+                mem::forget(tree);
+
+                // The pages make a long log, so only every few offsets are cut
+                let offsets = (logged as usize..wal.len()).step_by(11);
+                for offset in offsets.chain([wal.len() - 1, wal.len()]) {
+                    assert_eq!(recover(&data, &wal, offset), committed, "offset {offset}");
+                }
+
+                // Pages that were half written to the file are written again from the log
+                let mut torn = data.clone();
+                torn.resize(torn.len().max(256 * 4), 0);
+                torn[256..256 * 4].fill(0xAB);
+                assert_eq!(recover(&torn, &wal, wal.len()), committed);
+            }
+
+            #[test]
+            fn wal_keeps_the_pool_within_capacity() {
+                let mut rng = StdRng::seed_from_u64(26);
+                let file = TempFile::new();
+                let options = small_options().pool_capacity(16).wal(true);
+                let mut expected = BTreeMap::new();
+                {
+                    let mut tree = PagedBPlusTree::open(&file.0, options, BinaryCodec).unwrap();
+                    for i in 0..2000 {
+                        let k = rng.random_range(0..1000);
+                        if rng.random_bool(0.7) {
+                            tree.insert(k, i).unwrap();
+                            expected.insert(k, i);
+                        } else {
+                            assert_eq!(tree.remove(&k).unwrap(), expected.remove(&k));
+                        }
+                        assert!(tree.cached_pages() <= 16);
+                    }
+                    tree.verify().unwrap();
+                    mem::forget(tree);
+                }
+
+                // Replaying the log needs no more pages than writing it did
+                let tree = PagedBPlusTree::<u32, u64>::open(&file.0, options, BinaryCodec).unwrap();
+                tree.verify().unwrap();
+                assert!(tree.cached_pages() <= 16);
+                assert_eq!(entries(&tree), expected.into_iter().collect::<Vec<_>>());
+            }
+
+            #[test]
+            fn too_small_pool_fails_before_logging() {
+                let file = TempFile::new();
+                let options = small_options().pool_capacity(4).wal(true);
+                let mut tree = PagedBPlusTree::open(&file.0, options, BinaryCodec).unwrap();
+                let mut inserted = 0;
+                let error = loop {
+                    match tree.insert(inserted, 0) {
+                        Ok(_) => inserted += 1,
+                        Err(error) => break error,
+                    }
+                };
+
+                // A tree of two levels can change 5 pages, more than the pool holds
+                assert!(matches!(error, PagedError::PoolFull));
+                let wal_len = tree.wal_len();
+                assert!(matches!(
+                    tree.insert(inserted, 0),
+                    Err(PagedError::PoolFull)
+                ));
+                assert_eq!(tree.wal_len(), wal_len);
+                assert_eq!(tree.size(), inserted as usize);
+                tree.verify().unwrap();
+            }
+
+            #[test]
+            fn checkpoints_truncate_the_wal() {
+                let mut rng = StdRng::seed_from_u64(23);
+                let file = TempFile::new();
+                let options = small_options().pool_capacity(16).wal(true);
+                let mut expected = BTreeMap::new();
+                {
+                    let mut tree = PagedBPlusTree::open(&file.0, options, BinaryCodec).unwrap();
+                    for i in 0..1000 {
+                        let k = rng.random_range(0..300);
+                        tree.insert(k, i).unwrap();
+                        expected.insert(k, i);
+                        assert!(tree.wal_len() <= 16 * 256 + 64);
+                    }
+                    tree.verify().unwrap();
+                    assert!(tree.wal_len() > 0);
+                    mem::forget(tree);
+                }
+
+                let mut tree =
+                    PagedBPlusTree::<u32, u64>::open(&file.0, options, BinaryCodec).unwrap();
+                tree.verify().unwrap();
+                assert_eq!(
+                    entries(&tree),
+                    expected.clone().into_iter().collect::<Vec<_>>()
+                );
+
+                tree.insert(1000, 1).unwrap();
+                assert!(tree.wal_len() > 0);
+                tree.checkpoint().unwrap();
+                assert_eq!(tree.wal_len(), 0);
+                assert_eq!(fs::metadata(file.wal()).unwrap().len(), 0);
+            }
+
+            #[test]
+            fn leftover_wal_is_replayed_without_wal() {
+                let mut rng = StdRng::seed_from_u64(23);
+                let file = TempFile::new();
+                let mut tree =
+                    PagedBPlusTree::open(&file.0, small_options().wal(true), BinaryCodec).unwrap();
+                let mut expected = BTreeMap::new();
+                logged_operations(&mut rng, &mut tree, &mut expected, 100);
+                mem::forget(tree);
+
+                let tree = open(&file);
+                assert_eq!(tree.wal_len(), 0);
+                assert!(!file.wal().exists());
+                tree.verify().unwrap();
+                assert_eq!(entries(&tree), expected.into_iter().collect::<Vec<_>>());
+            }
+
+            #[test]
+            fn binary_codec() {
+                let codec = BinaryCodec;
+                let mut out = Vec::new();
+                codec.encode(&(-5i32, String::from("abc")), &mut out);
+                codec.encode(&u128::MAX, &mut out);
+                assert_eq!(out.len(), 4 + 4 + 3 + 16);
+
+                let mut bytes = out.as_slice();
+                let pair: (i32, String) = codec.decode(&mut bytes).unwrap();
+                let n: u128 = codec.decode(&mut bytes).unwrap();
+                assert_eq!(pair, (-5, String::from("abc")));
+                assert_eq!(n, u128::MAX);
+                assert!(bytes.is_empty());
+
+                let mut short: &[u8] = &[1, 2];
+                let error: Result<u32, _> = codec.decode(&mut short);
+                assert_eq!(error, Err(DecodeError::UnexpectedEnd));
+
+                let mut invalid: &[u8] = &[2, 0, 0, 0, 0xff, 0xfe];
+                let error: Result<String, _> = codec.decode(&mut invalid);
+                assert!(matches!(error, Err(DecodeError::Invalid(_))));
+            }
+        }
+        mod transaction {
+            use crate::bplustree::BPlusTree;
+            use crate::bplustree::aggregate::Aggregate;
+            use crate::bplustree::debug::{DebugOptions, verify, verify_summaries};
+            use crate::bplustree::tests::tree;
+            use rand::rngs::StdRng;
+            use rand::{Rng, SeedableRng};
+            use std::collections::BTreeMap;
+            use std::mem;
+            use std::panic::{AssertUnwindSafe, catch_unwind};
+
+            #[derive(Debug, Clone)]
+            struct Sum;
+
+            impl Aggregate<i32, i32> for Sum {
+                type Summary = i64;
+
+                fn identity(&self) -> Self::Summary {
+                    0
+                }
+
+                fn summarize_entry(&self, _: &i32, v: &i32) -> Self::Summary {
+                    *v as i64
+                }
+
+                fn combine(&self, left: &Self::Summary, right: &Self::Summary) -> Self::Summary {
+                    left + right
+                }
+            }
+
+            /// Every node with its address, so a rollback has to put back the very same nodes
+            fn shape(btree: &BPlusTree<i32, i32>) -> String {
+                let options = DebugOptions::default().all_address().all_values();
+                btree.display(options).to_string()
+            }
+
+            #[test]
+            fn commit_keeps_changes() {
+                let mut btree = tree(4, 0..50);
+                let mut transaction = btree.begin();
+                for k in 50..100 {
+                    transaction.insert(k, k);
+                }
+                for k in (0..50).step_by(2) {
+                    assert_eq!(transaction.remove(&k), Some(k));
+                }
+                transaction.commit();
+
+                verify(&btree);
+                assert_eq!(btree.size(), 75);
+                assert!(
+                    btree
+                        .iter()
+                        .map(|(k, _)| *k)
+                        .eq((1..50).step_by(2).chain(50..100))
+                );
+            }
+
+            #[test]
+            fn rollback_restores_contents_and_shape() {
+                let mut btree = tree(4, (0..200).step_by(2));
+                let before = shape(&btree);
+
+                let mut transaction = btree.begin();
+                for k in (1..200).step_by(2) {
+                    transaction.insert(k, k);
+                }
+                for k in 0..150 {
+                    transaction.remove(&k);
+                }
+                assert_eq!(transaction.size(), 50);
+                transaction.rollback();
+
+                verify(&btree);
+                assert_eq!(shape(&btree), before);
+                assert_eq!(btree.size(), 100);
+                assert!(btree.iter().map(|(k, _)| *k).eq((0..200).step_by(2)));
+            }
+
+            #[test]
+            fn transaction_sees_its_own_writes() {
+                let mut btree = tree(4, 0..10);
+                let mut transaction = btree.begin();
+                assert_eq!(transaction.insert(3, 30), Some(3));
+                assert_eq!(transaction.get(&3), Some(&30));
+                assert_eq!(transaction.insert(20, 20), None);
+                assert!(transaction.contains(&20));
+                assert_eq!(transaction.remove(&5), Some(5));
+                assert_eq!(transaction.get(&5), None);
+                assert_eq!(transaction.remove(&5), None);
+                assert_eq!(transaction.size(), 10);
+                assert_eq!(transaction.tree().iter().count(), 10);
+                drop(transaction);
+
+                assert_eq!(btree.find(&3), Some(&3));
+                assert_eq!(btree.find(&5), Some(&5));
+                assert!(!btree.contains(&20));
+            }
+
+            #[test]
+            fn dropped_transaction_rolls_back() {
+                let mut btree = tree(3, 0..30);
+                let before = shape(&btree);
+                {
+                    let mut transaction = btree.begin();
+                    for k in 0..30 {
+                        transaction.remove(&k);
+                    }
+                    assert_eq!(transaction.size(), 0);
+                }
+
+                verify(&btree);
+                assert_eq!(shape(&btree), before);
+            }
+
+            #[test]
+            fn bulk_removals_retire_nodes_while_open() {
+                let mut btree = tree(3, 0..200);
+                let stats = btree.stats();
+                let nodes = stats.internal_nodes + stats.leaf_nodes;
+                btree.retired = Some(Vec::new());
+
+                btree.retain(|k, _| k % 50 == 0);
+                let right = btree.split_off(&100);
+                btree.retain(|_, _| false);
+                verify(&right);
+                assert!(btree.root.is_none());
+
+                // Every node that didn't end up in right is kept, and only freed once the tree is dropped
+                let retired = btree.retired.as_ref().unwrap().len();
+                let stats = right.stats();
+                assert!(retired + stats.internal_nodes + stats.leaf_nodes >= nodes);
+            }
+
+            #[test]
+            fn leaked_transaction_keeps_its_changes() {
+                let mut btree = tree(3, 0..30);
+                let mut transaction = btree.begin();
+                for k in 0..20 {
+                    transaction.remove(&k);
+                }
+                mem::forget(transaction);
+
+                verify(&btree);
+                assert!(btree.iter().map(|(k, _)| *k).eq(20..30));
+                assert!(!btree.retired.as_ref().unwrap().is_empty());
+
+                // The next transaction frees what the leaked one retired
+                btree.begin().commit();
+                assert!(btree.retired.is_none());
+                btree.remove(&25);
+                assert!(btree.retired.is_none());
+                verify(&btree);
+            }
+
+            #[test]
+            fn transaction_on_empty_tree() {
+                let mut btree: BPlusTree<i32, i32> = BPlusTree::new(3);
+                let mut transaction = btree.begin();
+                for k in 0..20 {
+                    transaction.insert(k, k);
+                }
+                transaction.rollback();
+                assert_eq!(btree.size(), 0);
+                assert!(btree.root.is_none());
+
+                let mut transaction = btree.begin();
+                transaction.insert(1, 1);
+                transaction.remove(&1);
+                transaction.insert(2, 2);
+                transaction.commit();
+                verify(&btree);
+                assert_eq!(btree.find(&2), Some(&2));
+            }
+
+            #[test]
+            fn closure_commits_on_ok_and_rolls_back_on_err() {
+                let mut btree = tree(4, 0..20);
+                let before = shape(&btree);
+
+                let result: Result<(), &str> = btree.transaction(|transaction| {
+                    for k in 0..20 {
+                        transaction.remove(&k);
+                    }
+                    Err("abort")
+                });
+                assert_eq!(result, Err("abort"));
+                assert_eq!(shape(&btree), before);
+
+                let result: Result<i32, ()> = btree.transaction(|transaction| {
+                    transaction.insert(20, 20);
+                    Ok(transaction.remove(&0).unwrap())
+                });
+                assert_eq!(result, Ok(0));
+                verify(&btree);
+                assert!(btree.iter().map(|(k, _)| *k).eq(1..21));
+            }
+
+            #[test]
+            fn panic_rolls_back() {
+                let mut btree = tree(4, 0..40);
+                let before = shape(&btree);
+
+                let result = catch_unwind(AssertUnwindSafe(|| {
+                    btree.transaction(|transaction| -> Result<(), ()> {
+                        for k in 40..80 {
+                            transaction.insert(k, k);
+                        }
+                        panic!("transaction panicked");
+                    })
+                }));
+                assert!(result.is_err());
+
+                verify(&btree);
+                assert_eq!(shape(&btree), before);
+            }
+
+            #[test]
+            fn rollback_restores_aggregates() {
+                let mut btree = BPlusTree::with_aggregate(3, Sum);
+                for k in 0..100 {
+                    btree.insert(k, k);
+                }
+
+                let mut transaction = btree.begin();
+                for k in (0..100).step_by(3) {
+                    transaction.remove(&k);
+                }
+                for k in 100..150 {
+                    transaction.insert(k, k);
+                }
+                assert_eq!(
+                    transaction.tree().aggregate(..),
+                    (0..150).filter(|k| k % 3 != 0 || *k >= 100).sum::<i64>()
+                );
+                transaction.rollback();
+
+                verify_summaries(&btree);
+                assert_eq!(btree.aggregate(..), (0..100).sum::<i64>());
+                assert_eq!(btree.aggregate(10..20), (10..20).sum::<i64>());
+            }
+
+            #[test]
+            fn random_transactions() {
+                let mut rng = StdRng::seed_from_u64(24);
+                for order in 3..=6 {
+                    let mut btree = BPlusTree::new(order);
+                    let mut map = BTreeMap::new();
+                    for _ in 0..50 {
+                        let before = shape(&btree);
+                        let mut expected = map.clone();
+
+                        let mut transaction = btree.begin();
+                        for _ in 0..rng.random_range(1..100) {
+                            let k = rng.random_range(0..200);
+                            if rng.random_bool(0.5) {
+                                assert_eq!(transaction.insert(k, k), expected.insert(k, k));
+                            } else {
+                                assert_eq!(transaction.remove(&k), expected.remove(&k));
+                            }
+                            assert_eq!(transaction.get(&k), expected.get(&k));
+                        }
+                        assert_eq!(transaction.size(), expected.len());
+                        verify(transaction.tree());
+
+                        if rng.random_bool(0.5) {
+                            transaction.commit();
+                            map = expected;
+                        } else {
+                            transaction.rollback();
+                            assert_eq!(shape(&btree), before);
+                        }
+
+                        verify(&btree);
+                        assert!(btree.iter().eq(map.iter()));
+                    }
+                }
+            }
+        }
+        mod persistent {
+            use crate::bplustree::persistent::{PersistentBPlusTree, SharedNode, Snapshot};
+            use crate::bplustree::tests::filled;
+            use rand::rngs::StdRng;
+            use rand::{Rng, SeedableRng};
+            use std::collections::{BTreeMap, HashSet};
+            use std::ops::Bound;
+            use std::sync::Arc;
+            use std::thread;
+
+            /// Addresses of every node, and the height of the tree
+            fn nodes(
+                snapshot: &Snapshot<i32, i32>,
+            ) -> (HashSet<*const SharedNode<i32, i32>>, usize) {
+                let mut nodes = HashSet::new();
+                let mut height = 0;
+                let mut level: Vec<_> = snapshot.root.iter().cloned().collect();
+                while !level.is_empty() {
+                    height += 1;
+                    let mut below = Vec::new();
+                    for node in level {
+                        nodes.insert(Arc::as_ptr(&node));
+                        if let SharedNode::Internal(links) = &*node {
+                            below.extend(links.iter().map(|(_, child)| child.clone()));
+                        }
+                    }
+                    level = below;
+                }
+                (nodes, height)
+            }
+
+            #[test]
+            fn insert_find_remove() {
+                let mut rng = StdRng::seed_from_u64(25);
+                for order in 3..=6 {
+                    let mut btree = PersistentBPlusTree::new(order);
+                    let mut map = BTreeMap::new();
+                    for _ in 0..2000 {
+                        let k = rng.random_range(0..300);
+                        if rng.random_bool(0.6) {
+                            assert_eq!(btree.insert(k, k * 10), map.insert(k, k * 10));
+                        } else {
+                            assert_eq!(btree.remove(&k), map.remove(&k));
+                        }
+                        assert_eq!(btree.find(&k), map.get(&k));
+                    }
+
+                    btree.verify();
+                    assert_eq!(btree.size(), map.len());
+                    assert!(btree.iter().eq(map.iter()));
+
+                    for k in 0..300 {
+                        assert_eq!(btree.remove(&k), map.remove(&k));
+                    }
+                    btree.verify();
+                    assert!(btree.is_empty());
+                    assert!(btree.current.root.is_none());
+                }
+            }
+
+            #[test]
+            fn snapshots_stay_unchanged() {
+                let mut rng = StdRng::seed_from_u64(25);
+                let mut btree = PersistentBPlusTree::new(4);
+                let mut map = BTreeMap::new();
+                let mut snapshots = vec![(btree.snapshot(), map.clone())];
+                for round in 0..3000 {
+                    let k = rng.random_range(0..500);
+                    if rng.random_bool(0.55) {
+                        btree.insert(k, round);
+                        map.insert(k, round);
+                    } else {
+                        btree.remove(&k);
+                        map.remove(&k);
+                    }
+
+                    if round % 100 == 0 {
+                        snapshots.push((btree.snapshot(), map.clone()));
+                    }
+                }
+
+                for (snapshot, map) in &snapshots {
+                    snapshot.verify();
+                    assert_eq!(snapshot.size(), map.len());
+                    assert!(snapshot.iter().eq(map.iter()));
+                }
+
+                drop(btree);
+                let (snapshot, map) = snapshots.last().unwrap();
+                assert!(snapshot.iter().eq(map.iter()));
+            }
+
+            #[test]
+            fn snapshot_shares_the_root() {
+                let btree = filled(PersistentBPlusTree::new(4), 0..100);
+                let snapshot = btree.snapshot();
+                let (root, snapshot_root) = (btree.current.root.as_ref(), snapshot.root.as_ref());
+                assert!(Arc::ptr_eq(root.unwrap(), snapshot_root.unwrap()));
+                assert!(snapshot.clone().iter().eq(btree.iter()));
+            }
+
+            #[test]
+            fn insert_copies_only_the_path() {
+                // Sequential inserts leave every leaf half full, 501 fits without a split
+                let mut btree = filled(PersistentBPlusTree::new(4), (0..1000).step_by(2));
+                let snapshot = btree.snapshot();
+                btree.insert(501, 501);
+
+                let (before, height) = nodes(&snapshot);
+                let (after, _) = nodes(&btree.current);
+                assert_eq!(after.len(), before.len());
+                assert_eq!(after.difference(&before).count(), height);
+                assert_eq!(snapshot.find(&501), None);
+                assert_eq!(btree.find(&501), Some(&501));
+            }
+
+            #[test]
+            fn remove_copies_the_path_and_siblings() {
+                let mut btree = filled(PersistentBPlusTree::new(4), 0..1000);
+                for k in (0..1000).step_by(7) {
+                    let snapshot = btree.snapshot();
+                    btree.remove(&k);
+
+                    let (before, height) = nodes(&snapshot);
+                    let (after, _) = nodes(&btree.current);
+                    let copied = after.difference(&before).count();
+                    assert!(
+                        copied <= 2 * height,
+                        "{copied} nodes copied at height {height}"
+                    );
+                    assert!(snapshot.contains(&k));
+                    assert!(!btree.contains(&k));
+                }
+                btree.verify();
+            }
+
+            #[test]
+            fn writes_without_snapshots_change_nodes_in_place() {
+                let mut btree = filled(PersistentBPlusTree::new(4), (0..1000).step_by(2));
+                let (before, _) = nodes(&btree.current);
+                btree.insert(501, 501);
+                btree.remove(&500);
+                let (after, _) = nodes(&btree.current);
+                assert!(after.is_subset(&before));
+            }
+
+            #[test]
+            fn remove_missing_key_copies_nothing() {
+                let mut btree = filled(PersistentBPlusTree::new(4), (0..100).step_by(2));
+                let snapshot = btree.snapshot();
+                assert_eq!(btree.remove(&51), None);
+                assert_eq!(nodes(&btree.current).0, nodes(&snapshot).0);
+            }
+
+            #[test]
+            fn empty_tree() {
+                let mut btree: PersistentBPlusTree<i32, i32> = PersistentBPlusTree::new(3);
+                let snapshot = btree.snapshot();
+                assert_eq!(btree.remove(&1), None);
+                assert_eq!(btree.iter().next(), None);
+                btree.insert(1, 1);
+                assert_eq!(btree.remove(&1), Some(1));
+                assert!(btree.is_empty());
+                assert!(snapshot.is_empty());
+                assert_eq!(snapshot.iter().count(), 0);
+                snapshot.verify();
+            }
+
+            #[test]
+            fn range() {
+                let mut rng = StdRng::seed_from_u64(25);
+                let btree = filled(PersistentBPlusTree::new(3), (0..300).step_by(3));
+                let map: BTreeMap<_, _> = (0..300).step_by(3).map(|k| (k, k)).collect();
+                for _ in 0..200 {
+                    let (a, b) = (rng.random_range(-10..310), rng.random_range(-10..310));
+                    let (start, end) = (a.min(b), a.max(b));
+                    let bounds = match rng.random_range(0..4) {
+                        0 => (Bound::Included(start), Bound::Included(end)),
+                        1 => (Bound::Included(start), Bound::Excluded(end + 1)),
+                        2 => (Bound::Excluded(start), Bound::Unbounded),
+                        _ => (Bound::Unbounded, Bound::Included(end)),
+                    };
+                    assert!(btree.range(bounds).eq(map.range(bounds)));
+                }
+
+                let mut range = btree.range(10..20);
+                assert_eq!(range.by_ref().count(), 3);
+                assert_eq!(range.next(), None);
+            }
+
+            #[test]
+            fn snapshots_are_read_while_writing() {
+                let mut btree = filled(PersistentBPlusTree::new(8), 0..5000);
+                let snapshot = btree.snapshot();
+                let reader = thread::spawn(move || {
+                    for _ in 0..5 {
+                        assert!(
+                            snapshot
+                                .iter()
+                                .map(|(k, v)| (*k, *v))
+                                .eq((0..5000).map(|k| (k, k)))
+                        );
+                    }
+                    snapshot
+                });
+
+                for k in 0..5000 {
+                    if k % 2 == 0 {
+                        btree.remove(&k);
+                    } else {
+                        btree.insert(k, -k);
+                    }
+                }
+
+                let snapshot = reader.join().unwrap();
+                snapshot.verify();
+                btree.verify();
+                assert_eq!(snapshot.size(), 5000);
+                assert!(
+                    btree
+                        .iter()
+                        .map(|(k, v)| (*k, *v))
+                        .eq((1..5000).step_by(2).map(|k| (k, -k)))
+                );
+            }
         }
     }
 
     mod internal {
         use crate::bplustree::Internal;
+        use crate::bplustree::comparator::OrdComparator;
         use crate::bplustree::debug::{cleanup_leaf, create_leaf};
 
         #[test]
@@ -2174,9 +6785,11 @@ mod tests {
                     ((12345, 20), leaf5),
                     ((12345, 25), leaf6),
                 ],
+                counts: vec![1; 6],
+                summaries: vec![(); 6],
             };
 
-            let (_, node) = internal.find_entry_less_or_equal_to(&(12345, 8));
+            let (_, node) = internal.find_entry_less_or_equal_to(&(12345, 8), &OrdComparator);
             assert_eq!(*node, leaf2);
 
             unsafe {
@@ -2208,17 +6821,19 @@ mod tests {
                     (20, leaf5),
                     (25, leaf6),
                 ],
+                counts: vec![1; 6],
+                summaries: vec![(); 6],
             };
 
-            assert_eq!(internal.left(&-1), None);
-            assert_eq!(internal.left(&0), None);
-            assert_eq!(internal.left(&2), Some(&0));
-            assert_eq!(internal.left(&3), Some(&0));
-            assert_eq!(internal.left(&4), Some(&0));
-            assert_eq!(internal.left(&5), Some(&0));
-            assert_eq!(internal.left(&10), Some(&5));
-            assert_eq!(internal.left(&25), Some(&20));
-            assert_eq!(internal.left(&30), Some(&25));
+            assert_eq!(internal.left(&-1, &OrdComparator), None);
+            assert_eq!(internal.left(&0, &OrdComparator), None);
+            assert_eq!(internal.left(&2, &OrdComparator), Some(&0));
+            assert_eq!(internal.left(&3, &OrdComparator), Some(&0));
+            assert_eq!(internal.left(&4, &OrdComparator), Some(&0));
+            assert_eq!(internal.left(&5, &OrdComparator), Some(&0));
+            assert_eq!(internal.left(&10, &OrdComparator), Some(&5));
+            assert_eq!(internal.left(&25, &OrdComparator), Some(&20));
+            assert_eq!(internal.left(&30, &OrdComparator), Some(&25));
 
             unsafe {
                 cleanup_leaf(leaf1);
@@ -2249,17 +6864,19 @@ mod tests {
                     (20, leaf5),
                     (25, leaf6),
                 ],
+                counts: vec![1; 6],
+                summaries: vec![(); 6],
             };
 
-            assert_eq!(internal.right(&-1), Some(&0));
-            assert_eq!(internal.right(&0), Some(&5));
-            assert_eq!(internal.right(&2), Some(&5));
-            assert_eq!(internal.right(&3), Some(&5));
-            assert_eq!(internal.right(&4), Some(&5));
-            assert_eq!(internal.right(&5), Some(&10));
-            assert_eq!(internal.right(&10), Some(&15));
-            assert_eq!(internal.right(&25), None);
-            assert_eq!(internal.right(&30), None);
+            assert_eq!(internal.right(&-1, &OrdComparator), Some(&0));
+            assert_eq!(internal.right(&0, &OrdComparator), Some(&5));
+            assert_eq!(internal.right(&2, &OrdComparator), Some(&5));
+            assert_eq!(internal.right(&3, &OrdComparator), Some(&5));
+            assert_eq!(internal.right(&4, &OrdComparator), Some(&5));
+            assert_eq!(internal.right(&5, &OrdComparator), Some(&10));
+            assert_eq!(internal.right(&10, &OrdComparator), Some(&15));
+            assert_eq!(internal.right(&25, &OrdComparator), None);
+            assert_eq!(internal.right(&30, &OrdComparator), None);
 
             unsafe {
                 cleanup_leaf(leaf1);
@@ -2273,20 +6890,21 @@ mod tests {
     }
 
     mod leaf {
+        use crate::bplustree::comparator::OrdComparator;
         use crate::bplustree::debug::{cleanup_leaf, print_node_ptr};
         use crate::bplustree::leaf::Leaf;
 
         #[test]
         fn split_1() {
-            let mut leaf = Leaf::new();
-            leaf.insert((12345, 0), 0);
-            leaf.insert((12345, 5), 1);
-            leaf.insert((12345, 10), 2);
-            leaf.insert((12345, 15), 3);
-            leaf.insert((12345, 20), 4);
-            leaf.insert((12345, 25), 5);
-            leaf.insert((12345, 30), 6);
-            leaf.insert((12345, 35), 7);
+            let mut leaf: Leaf<_, _> = Leaf::new();
+            leaf.insert((12345, 0), 0, &OrdComparator);
+            leaf.insert((12345, 5), 1, &OrdComparator);
+            leaf.insert((12345, 10), 2, &OrdComparator);
+            leaf.insert((12345, 15), 3, &OrdComparator);
+            leaf.insert((12345, 20), 4, &OrdComparator);
+            leaf.insert((12345, 25), 5, &OrdComparator);
+            leaf.insert((12345, 30), 6, &OrdComparator);
+            leaf.insert((12345, 35), 7, &OrdComparator);
             let new_leaf = leaf.split();
             assert_eq!(leaf.size(), 4);
             unsafe {
@@ -0,0 +1,41 @@
+use std::fmt::Debug;
+
+/// A summary that can be combined over a range of entries, like a sum, minimum or maximum.
+///
+/// `combine` has to be associative and `identity` has to be neutral to it, so summaries of whole
+/// subtrees can be combined in any grouping. Summaries are combined in key order, so `combine` does
+/// not need to be commutative.
+pub trait Aggregate<K, V> {
+    type Summary: Clone + Debug;
+
+    fn identity(&self) -> Self::Summary;
+
+    fn summarize_entry(&self, k: &K, v: &V) -> Self::Summary;
+
+    fn combine(&self, left: &Self::Summary, right: &Self::Summary) -> Self::Summary;
+
+    /// Summary of consecutive entries, in key order
+    fn summarize<'a, I>(&self, entries: I) -> Self::Summary
+    where
+        K: 'a,
+        V: 'a,
+        I: IntoIterator<Item = &'a (K, V)>,
+    {
+        entries
+            .into_iter()
+            .fold(self.identity(), |summary, (k, v)| {
+                self.combine(&summary, &self.summarize_entry(k, v))
+            })
+    }
+}
+
+/// No summary, the default for trees that don't need one
+impl<K, V> Aggregate<K, V> for () {
+    type Summary = ();
+
+    fn identity(&self) -> Self::Summary {}
+
+    fn summarize_entry(&self, _: &K, _: &V) -> Self::Summary {}
+
+    fn combine(&self, _: &Self::Summary, _: &Self::Summary) -> Self::Summary {}
+}
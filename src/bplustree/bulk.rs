@@ -0,0 +1,214 @@
+use crate::bplustree::aggregate::Aggregate;
+use crate::bplustree::comparator::Comparator;
+use crate::bplustree::internal::Internal;
+use crate::bplustree::leaf::Leaf;
+use crate::bplustree::node::Node;
+use crate::bplustree::{BPlusTree, DEFAULT_ORDER};
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::ptr::NonNull;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BulkLoadError {
+    /// The key at `index` is equal to the key before it
+    DuplicateKey { index: usize },
+    /// The key at `index` is smaller than the key before it
+    UnsortedKey { index: usize },
+}
+
+impl Display for BulkLoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BulkLoadError::DuplicateKey { index } => {
+                write!(f, "key at index {index} is a duplicate of the previous key")
+            }
+            BulkLoadError::UnsortedKey { index } => {
+                write!(f, "key at index {index} is smaller than the previous key")
+            }
+        }
+    }
+}
+
+impl Error for BulkLoadError {}
+
+impl<K, V> BPlusTree<K, V>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    /// Builds the tree bottom-up from entries sorted by key, filling every node completely.
+    pub fn from_sorted_iter<I>(order: usize, iter: I) -> Result<Self, BulkLoadError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        Self::from_sorted_iter_with_fill_factor(order, 1.0, iter)
+    }
+
+    /// Builds the tree bottom-up from entries sorted by key. Nodes are filled up to
+    /// `fill_factor * order` entries, but never below `min_node_size()`, so leaving some room
+    /// makes the following inserts split less often.
+    pub fn from_sorted_iter_with_fill_factor<I>(
+        order: usize,
+        fill_factor: f64,
+        iter: I,
+    ) -> Result<Self, BulkLoadError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        Self::new(order).load_sorted(fill_factor, iter)
+    }
+}
+
+impl<K, V, A, C> BPlusTree<K, V, A, C>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+    /// Fills an empty tree bottom-up, see [`BPlusTree::from_sorted_iter_with_fill_factor`]
+    pub(crate) fn load_sorted<I>(mut self, fill_factor: f64, iter: I) -> Result<Self, BulkLoadError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        assert!(
+            fill_factor > 0.0 && fill_factor <= 1.0,
+            "Fill factor must be in (0, 1]"
+        );
+        assert!(self.root.is_none(), "Only an empty tree can be bulk loaded");
+
+        let order = self.order;
+        let target = ((order as f64 * fill_factor).round() as usize)
+            .clamp(self.min_node_size(), self.max_node_size());
+
+        let mut leaves: Vec<Vec<(K, V)>> = vec![];
+        let mut current: Vec<(K, V)> = Vec::with_capacity(target);
+        let mut size = 0;
+        for (index, (k, v)) in iter.into_iter().enumerate() {
+            let previous = current
+                .last()
+                .or_else(|| leaves.last().and_then(|l| l.last()));
+            if let Some((previous, _)) = previous {
+                match self.comparator.compare(previous, &k) {
+                    Ordering::Less => {}
+                    Ordering::Equal => return Err(BulkLoadError::DuplicateKey { index }),
+                    Ordering::Greater => return Err(BulkLoadError::UnsortedKey { index }),
+                }
+            }
+
+            current.push((k, v));
+            size += 1;
+            if current.len() == target {
+                leaves.push(current);
+                current = Vec::with_capacity(target);
+            }
+        }
+
+        if !current.is_empty() {
+            leaves.push(current);
+        }
+
+        if leaves.is_empty() {
+            return Ok(self);
+        }
+
+        self.rebalance_last_two(&mut leaves);
+
+        let mut level = Vec::with_capacity(leaves.len());
+        let mut prev: Option<NonNull<Node<K, V, A>>> = None;
+        for data in leaves {
+            let leaf = Node::Leaf(Leaf {
+                parent: None,
+                prev,
+                next: None,
+                data,
+            });
+            let ptr = unsafe { NonNull::new_unchecked(Box::into_raw(Box::new(leaf))) };
+            if let Some(mut prev_ptr) = prev {
+                unsafe { prev_ptr.as_mut().as_leaf_mut().next = Some(ptr) };
+            }
+
+            prev = Some(ptr);
+            level.push(ptr);
+        }
+
+        while level.len() > 1 {
+            let mut groups: Vec<Vec<NonNull<Node<K, V, A>>>> = vec![];
+            let mut iter = level.into_iter().peekable();
+            while iter.peek().is_some() {
+                groups.push(iter.by_ref().take(target).collect());
+            }
+
+            self.rebalance_last_two(&mut groups);
+
+            level = groups
+                .into_iter()
+                .map(|children| unsafe { self.new_internal(children) })
+                .collect();
+        }
+
+        self.root = level.pop();
+        self.size = size;
+        Ok(self)
+    }
+
+    /// The last group can come out smaller than min_node_size, even it out with the one before it
+    fn rebalance_last_two<T>(&self, groups: &mut Vec<Vec<T>>) {
+        let [.., _, last] = groups.as_slice() else {
+            return;
+        };
+
+        if last.len() >= self.min_node_size() {
+            return;
+        }
+
+        let mut last = groups.pop().unwrap();
+        let second_last = groups.last_mut().unwrap();
+        second_last.append(&mut last);
+
+        let total = second_last.len();
+        if total > self.max_node_size() {
+            let right = second_last.split_off(total / 2);
+            groups.push(right);
+        }
+    }
+
+    /// SAFETY: children MUST be valid, parentless nodes sorted by their keys
+    unsafe fn new_internal(&self, children: Vec<NonNull<Node<K, V, A>>>) -> NonNull<Node<K, V, A>> {
+        let links = children
+            .into_iter()
+            .map(|ptr| (unsafe { ptr.as_ref() }.smallest_key().clone(), ptr))
+            .collect::<Vec<_>>();
+
+        let internal = Node::Internal(Internal::new(links, &self.aggregate));
+        let ptr = unsafe { NonNull::new_unchecked(Box::into_raw(Box::new(internal))) };
+
+        for (_, child_ptr) in &unsafe { ptr.as_ref() }.as_internal().links {
+            let mut child_ptr = *child_ptr;
+            unsafe { child_ptr.as_mut().set_parent(Some(ptr)) };
+        }
+
+        ptr
+    }
+}
+
+impl<K, V> FromIterator<(K, V)> for BPlusTree<K, V>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    /// Sorts the entries before loading them, for duplicate keys the last value wins.
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        let mut entries = iter.into_iter().collect::<Vec<_>>();
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut deduplicated: Vec<(K, V)> = Vec::with_capacity(entries.len());
+        for (k, v) in entries {
+            match deduplicated.last_mut() {
+                Some((last, value)) if *last == k => *value = v,
+                _ => deduplicated.push((k, v)),
+            }
+        }
+
+        Self::from_sorted_iter(DEFAULT_ORDER, deduplicated)
+            .expect("Entries are sorted and deduplicated")
+    }
+}
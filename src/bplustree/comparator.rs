@@ -0,0 +1,33 @@
+use std::cmp::Ordering;
+
+/// Decides the order of the keys of a tree, so keys can be ordered differently than by their [`Ord`]
+/// implementation, or don't need one at all.
+///
+/// Lookups with a borrowed form `Q` of the key need a comparator for `Q` as well, which
+/// [`OrdComparator`] provides for every `Q: Ord`.
+pub trait Comparator<T: ?Sized> {
+    fn compare(&self, a: &T, b: &T) -> Ordering;
+}
+
+/// Orders keys by their [`Ord`] implementation, the default for every tree
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OrdComparator;
+
+impl<T> Comparator<T> for OrdComparator
+where
+    T: ?Sized + Ord,
+{
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        a.cmp(b)
+    }
+}
+
+impl<T, F> Comparator<T> for F
+where
+    T: ?Sized,
+    F: Fn(&T, &T) -> Ordering,
+{
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        self(a, b)
+    }
+}
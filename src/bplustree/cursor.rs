@@ -0,0 +1,370 @@
+use crate::bplustree::BPlusTree;
+use crate::bplustree::aggregate::Aggregate;
+use crate::bplustree::comparator::{Comparator, OrdComparator};
+use crate::bplustree::iter::{entry_mut, entry_ref};
+use crate::bplustree::node::Position;
+use std::borrow::Borrow;
+use std::ops::Bound;
+
+/// Points at an entry of the tree, or at the "ghost" position past the last and before the first
+/// entry. Moving past either end lands on the ghost, moving again wraps around.
+pub struct Cursor<'a, K, V, A = (), C = OrdComparator>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    pub(crate) tree: &'a BPlusTree<K, V, A, C>,
+    pub(crate) current: Option<Position<K, V, A>>,
+}
+
+/// Like [`Cursor`], but can modify the tree at the current position.
+pub struct CursorMut<'a, K, V, A = (), C = OrdComparator>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    pub(crate) tree: &'a mut BPlusTree<K, V, A, C>,
+    pub(crate) current: Option<Position<K, V, A>>,
+}
+
+impl<K, V, A, C> BPlusTree<K, V, A, C>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+    /// Cursor at the first entry above the bound, or at the ghost position if there is none
+    pub fn lower_bound<Q>(&self, bound: Bound<&Q>) -> Cursor<'_, K, V, A, C>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let current = self.lower_position(bound).and_then(at_or_after);
+        Cursor {
+            tree: self,
+            current,
+        }
+    }
+
+    /// Cursor at the last entry below the bound, or at the ghost position if there is none
+    pub fn upper_bound<Q>(&self, bound: Bound<&Q>) -> Cursor<'_, K, V, A, C>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let current = self.upper_position(bound).and_then(before);
+        Cursor {
+            tree: self,
+            current,
+        }
+    }
+
+    pub fn lower_bound_mut<Q>(&mut self, bound: Bound<&Q>) -> CursorMut<'_, K, V, A, C>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let current = self.lower_position(bound).and_then(at_or_after);
+        CursorMut {
+            tree: self,
+            current,
+        }
+    }
+
+    pub fn upper_bound_mut<Q>(&mut self, bound: Bound<&Q>) -> CursorMut<'_, K, V, A, C>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let current = self.upper_position(bound).and_then(before);
+        CursorMut {
+            tree: self,
+            current,
+        }
+    }
+}
+
+/// Turns a position in between entries into the entry right after it
+fn at_or_after<K, V, A>((leaf_ptr, index): Position<K, V, A>) -> Option<Position<K, V, A>>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    let leaf = unsafe { leaf_ptr.as_ref().as_leaf() };
+    if index < leaf.size() {
+        return Some((leaf_ptr, index));
+    }
+
+    leaf.next.map(|next_ptr| (next_ptr, 0))
+}
+
+/// Turns a position in between entries into the entry right before it
+fn before<K, V, A>((leaf_ptr, index): Position<K, V, A>) -> Option<Position<K, V, A>>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    if index > 0 {
+        return Some((leaf_ptr, index - 1));
+    }
+
+    let leaf = unsafe { leaf_ptr.as_ref().as_leaf() };
+    leaf.prev
+        .map(|prev_ptr| (prev_ptr, unsafe { prev_ptr.as_ref() }.size() - 1))
+}
+
+fn next_position<K, V, A, C>(
+    tree: &BPlusTree<K, V, A, C>,
+    current: Option<Position<K, V, A>>,
+) -> Option<Position<K, V, A>>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+    match current {
+        Some((leaf_ptr, index)) => at_or_after((leaf_ptr, index + 1)),
+        None => tree.first_leaf_raw().map(|leaf_ptr| (leaf_ptr, 0)),
+    }
+}
+
+fn prev_position<K, V, A, C>(
+    tree: &BPlusTree<K, V, A, C>,
+    current: Option<Position<K, V, A>>,
+) -> Option<Position<K, V, A>>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+    match current {
+        Some(position) => before(position),
+        None => tree
+            .last_leaf_raw()
+            .map(|leaf_ptr| (leaf_ptr, unsafe { leaf_ptr.as_ref() }.size() - 1)),
+    }
+}
+
+impl<K, V, A, C> Clone for Cursor<'_, K, V, A, C>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            tree: self.tree,
+            current: self.current,
+        }
+    }
+}
+
+impl<'a, K, V, A, C> Cursor<'a, K, V, A, C>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+    pub fn key(&self) -> Option<&'a K> {
+        self.key_value().map(|(k, _)| k)
+    }
+
+    pub fn value(&self) -> Option<&'a V> {
+        self.key_value().map(|(_, v)| v)
+    }
+
+    pub fn key_value(&self) -> Option<(&'a K, &'a V)> {
+        self.current.map(|position| unsafe { entry_ref(position) })
+    }
+
+    pub fn move_next(&mut self) {
+        self.current = next_position(self.tree, self.current);
+    }
+
+    pub fn move_prev(&mut self) {
+        self.current = prev_position(self.tree, self.current);
+    }
+
+    pub fn peek_next(&self) -> Option<(&'a K, &'a V)> {
+        next_position(self.tree, self.current).map(|position| unsafe { entry_ref(position) })
+    }
+
+    pub fn peek_prev(&self) -> Option<(&'a K, &'a V)> {
+        prev_position(self.tree, self.current).map(|position| unsafe { entry_ref(position) })
+    }
+}
+
+impl<'a, K, V, A, C> CursorMut<'a, K, V, A, C>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+    pub fn key(&self) -> Option<&K> {
+        self.key_value().map(|(k, _)| k)
+    }
+
+    pub fn value(&self) -> Option<&V> {
+        self.key_value().map(|(_, v)| v)
+    }
+
+    pub fn key_value(&self) -> Option<(&K, &V)> {
+        self.current.map(|position| unsafe { entry_ref(position) })
+    }
+
+    pub fn move_next(&mut self) {
+        self.current = next_position(self.tree, self.current);
+    }
+
+    pub fn move_prev(&mut self) {
+        self.current = prev_position(self.tree, self.current);
+    }
+
+    /// Read-only cursor at the same position
+    pub fn as_cursor(&self) -> Cursor<'_, K, V, A, C> {
+        Cursor {
+            tree: self.tree,
+            current: self.current,
+        }
+    }
+
+    /// Inserts the entry right after the current one, or at the front of the tree if the cursor is
+    /// at the ghost position. The cursor doesn't move.
+    ///
+    /// Panics if the key is not ordered in between the current and the next entry.
+    pub fn insert_after(&mut self, k: K, v: V) {
+        if let Some(current) = self.key() {
+            assert!(
+                self.tree.comparator.compare(current, &k).is_lt(),
+                "key must be ordered above the current element"
+            );
+        }
+
+        if let Some((next, _)) = self.as_cursor().peek_next() {
+            assert!(
+                self.tree.comparator.compare(&k, next).is_lt(),
+                "key must be ordered below the next element"
+            );
+        }
+
+        let Some((leaf_ptr, index)) = self.current else {
+            match self.tree.first_leaf_raw() {
+                Some(leaf_ptr) => unsafe { self.tree.insert_at(leaf_ptr, 0, k, v) },
+                None => (self.tree.insert_into_empty(k, v), 0),
+            };
+            return;
+        };
+
+        let inserted = unsafe { self.tree.insert_at(leaf_ptr, index + 1, k, v) };
+        self.current = before(inserted);
+    }
+
+    /// Inserts the entry right before the current one, or at the back of the tree if the cursor is
+    /// at the ghost position. The cursor doesn't move.
+    ///
+    /// Panics if the key is not ordered in between the previous and the current entry.
+    pub fn insert_before(&mut self, k: K, v: V) {
+        if let Some(current) = self.key() {
+            assert!(
+                self.tree.comparator.compare(&k, current).is_lt(),
+                "key must be ordered below the current element"
+            );
+        }
+
+        if let Some((prev, _)) = self.as_cursor().peek_prev() {
+            assert!(
+                self.tree.comparator.compare(prev, &k).is_lt(),
+                "key must be ordered above the previous element"
+            );
+        }
+
+        // Only the first leaf can take a new smallest key, otherwise the entry goes to the end of
+        // the previous leaf, so none of the separators change
+        let position = match self.current {
+            Some((leaf_ptr, 0)) => {
+                let leaf = unsafe { leaf_ptr.as_ref().as_leaf() };
+                match leaf.prev {
+                    Some(prev_ptr) => Some((prev_ptr, unsafe { prev_ptr.as_ref() }.size())),
+                    None => Some((leaf_ptr, 0)),
+                }
+            }
+            Some(position) => Some(position),
+            None => self
+                .tree
+                .last_leaf_raw()
+                .map(|leaf_ptr| (leaf_ptr, unsafe { leaf_ptr.as_ref() }.size())),
+        };
+
+        let Some((leaf_ptr, index)) = position else {
+            self.tree.insert_into_empty(k, v);
+            return;
+        };
+
+        let inserted = unsafe { self.tree.insert_at(leaf_ptr, index, k, v) };
+        if self.current.is_some() {
+            self.current = at_or_after((inserted.0, inserted.1 + 1));
+        }
+    }
+
+    /// Removes the current entry and moves the cursor to the next one. Returns None if the cursor
+    /// is at the ghost position.
+    pub fn remove_current(&mut self) -> Option<(K, V)> {
+        let (leaf_ptr, index) = self.current?;
+
+        let leaf = unsafe { leaf_ptr.as_ref().as_leaf() };
+        let rebalances = !leaf.is_root() && leaf.size() <= self.tree.min_node_size();
+        if !rebalances {
+            // The leaf stays where it is, the next entry simply shifts into the current index
+            let entry = unsafe { self.tree.remove_at(leaf_ptr, index) };
+            self.current = match self.tree.root {
+                Some(_) => at_or_after((leaf_ptr, index)),
+                None => None,
+            };
+            return Some(entry);
+        }
+
+        // Transfers and merges move entries between leaves and can free this one, so the next
+        // entry is looked up again by its key
+        let next_key = next_position(self.tree, self.current)
+            .map(|position| unsafe { entry_ref::<K, V, A>(position) }.0.clone());
+        let entry = unsafe { self.tree.remove_at(leaf_ptr, index) };
+
+        self.current = next_key.map(|k| {
+            let leaf_ptr = self
+                .tree
+                .find_leaf_node_raw(&k)
+                .expect("The next entry MUST still be in the tree");
+            let leaf = unsafe { leaf_ptr.as_ref().as_leaf() };
+            let index = leaf
+                .find_index(&k, &self.tree.comparator)
+                .expect("The next entry MUST still be in the tree");
+            (leaf_ptr, index)
+        });
+
+        Some(entry)
+    }
+}
+
+// Like the mutable accessors of the tree, values can only be changed in place without an aggregate
+impl<K, V, C> CursorMut<'_, K, V, (), C>
+where
+    K: Clone,
+    C: Comparator<K>,
+{
+    pub fn value_mut(&mut self) -> Option<&mut V> {
+        self.current
+            .map(|position| unsafe { entry_mut(position) }.1)
+    }
+
+    pub fn peek_next(&mut self) -> Option<(&K, &mut V)> {
+        next_position(self.tree, self.current).map(|position| unsafe { entry_mut(position) })
+    }
+
+    pub fn peek_prev(&mut self) -> Option<(&K, &mut V)> {
+        prev_position(self.tree, self.current).map(|position| unsafe { entry_mut(position) })
+    }
+}
@@ -1,14 +1,18 @@
 use crate::bplustree::BPlusTree;
+use crate::bplustree::aggregate::Aggregate;
+use crate::bplustree::comparator::Comparator;
 use crate::bplustree::internal::Internal;
 use crate::bplustree::leaf::Leaf;
 use crate::bplustree::node::Node;
 use std::collections::VecDeque;
-use std::fmt::Debug;
+use std::fmt::{self, Debug, Display, Formatter, Write};
 use std::ptr::NonNull;
 
 pub(crate) fn create_leaf<K, V>(k: K, v: V) -> NonNull<Node<K, V>> {
     let leaf = Node::Leaf(Leaf {
         parent: None,
+        prev: None,
+        next: None,
         data: vec![(k, v)],
     });
     unsafe { NonNull::new_unchecked(Box::into_raw(Box::new(leaf))) }
@@ -20,21 +24,66 @@ pub(crate) unsafe fn cleanup_leaf<K, V>(ptr: NonNull<Node<K, V>>) {
     }
 }
 
-pub fn print_bplustree<K, V>(tree: &BPlusTree<K, V>, options: DebugOptions)
+pub fn print_bplustree<K, V, A, C>(tree: &BPlusTree<K, V, A, C>, options: DebugOptions)
 where
-    K: Ord + PartialOrd + Clone + Debug,
+    K: Clone + Debug,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
     V: Debug,
 {
-    let Some(root) = tree.root else {
-        println!("Empty");
-        return;
-    };
+    if tree.root.is_some() {
+        let _ = tree
+            .largest_key()
+            .expect("If a tree is not empty, it's guaranteed to have at least a single value");
+    }
+
+    println!("{}", tree.display(options));
+}
+
+impl<K, V, A, C> BPlusTree<K, V, A, C>
+where
+    K: Clone + Debug,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+    V: Debug,
+{
+    /// Renders the tree like [`print_bplustree`] does, so it can go into a `format!`, a log or an
+    /// assertion instead of stdout
+    pub fn display(&self, options: DebugOptions) -> TreeDisplay<'_, K, V, A, C> {
+        TreeDisplay {
+            tree: self,
+            options,
+        }
+    }
+}
 
-    let _ = tree
-        .largest_key()
-        .expect("If a tree is not empty, it's guaranteed to have at least a single value");
+/// Renders a tree with the given [`DebugOptions`], see [`BPlusTree::display`]
+pub struct TreeDisplay<'a, K, V, A, C>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    tree: &'a BPlusTree<K, V, A, C>,
+    options: DebugOptions,
+}
+
+impl<K, V, A, C> Display for TreeDisplay<'_, K, V, A, C>
+where
+    K: Clone + Debug,
+    A: Aggregate<K, V>,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let Some(root) = self.tree.root else {
+            return write!(f, "Empty");
+        };
 
-    unsafe { print_node(root, options) };
+        if self.options.compact {
+            unsafe { write_node_compact(f, root, self.options) }
+        } else {
+            unsafe { write_node(f, root, self.options) }
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone, Default)]
@@ -49,15 +98,16 @@ impl PtrDebugOptions {
 }
 
 #[derive(Debug, Copy, Clone, Default)]
-struct ShowParent {
-    internal: Option<PtrDebugOptions>,
-    leaf: Option<PtrDebugOptions>,
+pub(crate) struct ShowParent {
+    pub(crate) internal: Option<PtrDebugOptions>,
+    pub(crate) leaf: Option<PtrDebugOptions>,
 }
 
 #[derive(Debug, Copy, Clone, Default)]
 pub struct DebugOptions {
-    show_parent: ShowParent,
+    pub(crate) show_parent: ShowParent,
     override_padding: Option<usize>,
+    compact: bool,
 }
 
 impl DebugOptions {
@@ -117,11 +167,24 @@ impl DebugOptions {
         self.override_padding = Some(padding);
         self
     }
+
+    /// Renders the whole tree on a single line, like `(0 -> [0: 0, 1: 1], 2 -> [2: 2, 3: 3])`.
+    /// Keys are only padded when the padding is overridden.
+    pub fn compact(mut self) -> Self {
+        self.compact = true;
+        self
+    }
 }
 
-pub(crate) unsafe fn print_node<K, V>(root: NonNull<Node<K, V>>, options: DebugOptions)
+pub(crate) unsafe fn write_node<W, K, V, A>(
+    out: &mut W,
+    root: NonNull<Node<K, V, A>>,
+    options: DebugOptions,
+) -> fmt::Result
 where
-    K: Ord + PartialOrd + Clone + Debug,
+    W: Write,
+    K: Clone + Debug,
+    A: Aggregate<K, V>,
     V: Debug,
 {
     let key_length = if let Some(padding) = options.override_padding {
@@ -129,6 +192,8 @@ where
     } else {
         4
     };
+    // Line breaks are only written once something follows them, so the output doesn't end in one
+    let mut new_lines = 0;
     let mut stack = VecDeque::from([(None, 0, false, root, -1)]);
     while let Some((pair, mut offset, ignore_offset, current_ptr, lvl)) = stack.pop_front() {
         let current = unsafe { current_ptr.as_ref() };
@@ -145,7 +210,8 @@ where
             if ignore_offset {
                 offset = 0;
             }
-            print!("{:>offset$}", line);
+            write_new_lines(out, &mut new_lines)?;
+            write!(out, "{:>offset$}", line)?;
         }
 
         let mut should_print_new_line = false;
@@ -183,7 +249,9 @@ where
                         offset = 0;
                         first = false;
                     }
-                    println!("{line:>offset$}");
+                    write_new_lines(out, &mut new_lines)?;
+                    write!(out, "{line:>offset$}")?;
+                    new_lines += 1;
                 }
 
                 should_print_new_line = true;
@@ -191,7 +259,66 @@ where
         }
 
         if should_print_new_line && !stack.is_empty() {
-            println!()
+            new_lines += 1;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_new_lines<W: Write>(out: &mut W, new_lines: &mut usize) -> fmt::Result {
+    for _ in 0..*new_lines {
+        writeln!(out)?;
+    }
+    *new_lines = 0;
+    Ok(())
+}
+
+pub(crate) unsafe fn write_node_compact<W, K, V, A>(
+    out: &mut W,
+    node_ptr: NonNull<Node<K, V, A>>,
+    options: DebugOptions,
+) -> fmt::Result
+where
+    W: Write,
+    K: Clone + Debug,
+    A: Aggregate<K, V>,
+    V: Debug,
+{
+    let key_length = options.override_padding.unwrap_or(0);
+    match unsafe { node_ptr.as_ref() } {
+        Node::Internal(internal) => {
+            if let Some(ptr_debug_options) = options.show_parent.internal {
+                write!(out, "{} ", unsafe {
+                    format_node_ptr(node_ptr, ptr_debug_options)
+                })?;
+            }
+
+            write!(out, "(")?;
+            for (index, (k, child_ptr)) in internal.links.iter().enumerate() {
+                if index > 0 {
+                    write!(out, ", ")?;
+                }
+                write!(out, "{k:key_length$?} -> ")?;
+                unsafe { write_node_compact(out, *child_ptr, options)? };
+            }
+            write!(out, ")")
+        }
+        Node::Leaf(leaf) => {
+            if let Some(ptr_debug_options) = options.show_parent.leaf {
+                write!(out, "{} ", unsafe {
+                    format_node_ptr(node_ptr, ptr_debug_options)
+                })?;
+            }
+
+            write!(out, "[")?;
+            for (index, (k, v)) in leaf.data.iter().enumerate() {
+                if index > 0 {
+                    write!(out, ", ")?;
+                }
+                write!(out, "{k:key_length$?}: {v:key_length$?}")?;
+            }
+            write!(out, "]")
         }
     }
 }
@@ -206,12 +333,13 @@ where
                                               (123456, 35)
 */
 
-pub(crate) unsafe fn format_node_ptr<K, V>(
-    ptr: NonNull<Node<K, V>>,
+pub(crate) unsafe fn format_node_ptr<K, V, A>(
+    ptr: NonNull<Node<K, V, A>>,
     ptr_debug_options: PtrDebugOptions,
 ) -> String
 where
-    K: Ord + PartialOrd + Clone + Debug,
+    K: Clone + Debug,
+    A: Aggregate<K, V>,
 {
     let n = unsafe { &*ptr.as_ptr() };
     let parent_ptr = n.parent_raw();
@@ -231,66 +359,141 @@ where
     }
 }
 
-pub(crate) unsafe fn print_node_ptr<K, V>(ptr: NonNull<Node<K, V>>)
+pub(crate) unsafe fn print_node_ptr<K, V, A>(ptr: NonNull<Node<K, V, A>>)
 where
-    K: Ord + PartialOrd + Clone + Debug,
+    K: Clone + Debug,
+    A: Aggregate<K, V>,
     V: Debug,
 {
-    unsafe {
-        println!(
-            "{}",
-            format_node_ptr(ptr, PtrDebugOptions::default().values())
-        );
-    }
+    let mut out = String::new();
+    unsafe { write_node_ptr(&mut out, ptr) }.expect("Writing to a String can't fail");
+    println!("{out}");
+}
+
+pub(crate) unsafe fn write_node_ptr<W, K, V, A>(
+    out: &mut W,
+    ptr: NonNull<Node<K, V, A>>,
+) -> fmt::Result
+where
+    W: Write,
+    K: Clone + Debug,
+    A: Aggregate<K, V>,
+    V: Debug,
+{
+    write!(out, "{}", unsafe {
+        format_node_ptr(ptr, PtrDebugOptions::default().values())
+    })
 }
 
-pub fn verify<K: Ord + PartialOrd + Clone, V>(btree: &BPlusTree<K, V>) {
+pub fn verify<K: Clone, V, A, C>(btree: &BPlusTree<K, V, A, C>)
+where
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
     let Some(root_ptr) = btree.root else {
         return;
     };
 
     let root = unsafe { root_ptr.as_ref() };
-    match root {
+    let size = match root {
         Node::Internal(root) => {
-            for (k, child) in &root.links {
-                unsafe {
-                    verify_internal(*child, root_ptr);
-                }
+            assert_eq!(root.links.len(), root.counts.len());
+            assert_eq!(root.links.len(), root.summaries.len());
+            let mut size = 0;
+            for ((k, child), count) in root.links.iter().zip(&root.counts) {
+                let child_size = unsafe { verify_internal(*child, root_ptr) };
+                assert_eq!(*count, child_size);
+                size += child_size;
             }
+            size
         }
         Node::Leaf(root) => {
-            assert!(root.parent.is_none())
+            assert!(root.parent.is_none());
+            root.size()
         }
-    }
+    };
+
+    assert_eq!(size, btree.size());
 }
 
-unsafe fn verify_internal<K, V>(node_ptr: NonNull<Node<K, V>>, parent_ptr: NonNull<Node<K, V>>)
+/// Returns the number of entries in the subtree
+unsafe fn verify_internal<K, V, A>(
+    node_ptr: NonNull<Node<K, V, A>>,
+    parent_ptr: NonNull<Node<K, V, A>>,
+) -> usize
 where
-    K: Ord + PartialOrd + Clone,
+    K: Clone,
+    A: Aggregate<K, V>,
 {
     let node = unsafe { node_ptr.as_ref() };
     let node_parent_ptr = node.parent_raw().unwrap();
     assert_eq!(node_parent_ptr, parent_ptr);
 
-    if let Node::Internal(node) = node {
-        for (k, child) in &node.links {
-            unsafe {
-                verify_internal(*child, node_ptr);
+    match node {
+        Node::Internal(node) => {
+            assert_eq!(node.links.len(), node.counts.len());
+            assert_eq!(node.links.len(), node.summaries.len());
+            let mut size = 0;
+            for ((k, child), count) in node.links.iter().zip(&node.counts) {
+                let child_size = unsafe { verify_internal(*child, node_ptr) };
+                assert_eq!(*count, child_size);
+                size += child_size;
+            }
+            size
+        }
+        Node::Leaf(leaf) => leaf.size(),
+    }
+}
+
+/// Checks that the summary of every link matches the entries below it, on top of [`verify`]
+pub fn verify_summaries<K: Clone, V, A, C>(btree: &BPlusTree<K, V, A, C>)
+where
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+    A::Summary: PartialEq + Debug,
+{
+    verify(btree);
+    if let Some(root_ptr) = btree.root {
+        unsafe { summarize(root_ptr, &btree.aggregate) };
+    }
+}
+
+/// Returns the summary of the subtree
+unsafe fn summarize<K, V, A>(node_ptr: NonNull<Node<K, V, A>>, aggregate: &A) -> A::Summary
+where
+    A: Aggregate<K, V>,
+    A::Summary: PartialEq + Debug,
+{
+    match unsafe { node_ptr.as_ref() } {
+        Node::Internal(node) => {
+            let mut summary = aggregate.identity();
+            for ((_, child), child_summary) in node.links.iter().zip(&node.summaries) {
+                assert_eq!(*child_summary, unsafe { summarize(*child, aggregate) });
+                summary = aggregate.combine(&summary, child_summary);
             }
+            summary
         }
+        Node::Leaf(leaf) => aggregate.summarize(&leaf.data),
     }
 }
 
 #[cfg(test)]
 mod test {
+    use crate::bplustree::BPlusTree;
     use crate::bplustree::debug::{
-        DebugOptions, PtrDebugOptions, cleanup_leaf, create_leaf, format_node_ptr, print_node,
-        print_node_ptr,
+        DebugOptions, PtrDebugOptions, cleanup_leaf, create_leaf, format_node_ptr, print_node_ptr,
+        write_node, write_node_ptr,
     };
     use crate::bplustree::internal::Internal;
     use crate::bplustree::node::Node;
     use std::ptr::NonNull;
 
+    unsafe fn print_node(root: NonNull<Node<i32, i32>>, options: DebugOptions) {
+        let mut out = String::new();
+        unsafe { write_node(&mut out, root, options).unwrap() };
+        println!("{out}");
+    }
+
     #[test]
     fn print_1() {
         let mut leaf1 = create_leaf(0, 0);
@@ -301,6 +504,8 @@ mod test {
             NonNull::new_unchecked(Box::into_raw(Box::new(Node::Internal(Internal {
                 parent: Some(leaf1),
                 links: vec![(0, leaf1), (5, leaf2), (10, leaf3)],
+                counts: vec![1, 1, 1],
+                summaries: vec![(); 3],
             }))))
         };
 
@@ -333,4 +538,101 @@ mod test {
             cleanup_leaf(leaf3);
         }
     }
+
+    #[test]
+    fn display_levels() {
+        let mut btree = BPlusTree::new(4);
+        for k in 0..5 {
+            btree.insert(k, k * 10);
+        }
+
+        let expected = "   0  ->     0:    0\n\
+                        \x20            1:   10\n\
+                        \n\
+                        \x20  2  ->     2:   20\n\
+                        \x20            3:   30\n\
+                        \x20            4:   40";
+        assert_eq!(btree.display(DebugOptions::default()).to_string(), expected);
+
+        let expected = " 0  ->   0:  0\n\
+                        \x20        1: 10\n\
+                        \n\
+                        \x202  ->   2: 20\n\
+                        \x20        3: 30\n\
+                        \x20        4: 40";
+        let options = DebugOptions::default().override_padding(2);
+        assert_eq!(btree.display(options).to_string(), expected);
+    }
+
+    #[test]
+    fn display_empty() {
+        let btree: BPlusTree<i32, i32> = BPlusTree::new(4);
+        assert_eq!(btree.display(DebugOptions::default()).to_string(), "Empty");
+        let options = DebugOptions::default().compact();
+        assert_eq!(btree.display(options).to_string(), "Empty");
+    }
+
+    #[test]
+    fn display_compact() {
+        let mut btree = BPlusTree::new(4);
+        btree.insert(0, 0);
+        let options = DebugOptions::default().compact();
+        assert_eq!(btree.display(options).to_string(), "[0: 0]");
+
+        for k in 1..10 {
+            btree.insert(k, k * 10);
+        }
+        assert_eq!(
+            btree.display(options).to_string(),
+            "(0 -> [0: 0, 1: 10], 2 -> [2: 20, 3: 30], 4 -> [4: 40, 5: 50], \
+             6 -> [6: 60, 7: 70, 8: 80, 9: 90])"
+        );
+
+        let options = options.override_padding(2);
+        assert!(
+            btree
+                .display(options)
+                .to_string()
+                .starts_with("( 0 -> [ 0:  0,  1: 10]")
+        );
+    }
+
+    #[test]
+    fn display_addresses() {
+        let mut btree = BPlusTree::new(4);
+        for k in 0..5 {
+            btree.insert(k, k);
+        }
+
+        let root_ptr = btree.root.unwrap();
+        let (_, leaf_ptr) = unsafe { root_ptr.as_ref() }.as_internal().links[0];
+
+        let output = btree
+            .display(DebugOptions::default().all_address())
+            .to_string();
+        assert!(output.starts_with(&format!("[{root_ptr:p} | parent: None]    0  ->  ")));
+        assert!(output.contains(&format!(
+            "[{leaf_ptr:p} | parent: Some({root_ptr:p})]    0:    0"
+        )));
+
+        let output = btree
+            .display(DebugOptions::default().compact().all_values())
+            .to_string();
+        assert_eq!(
+            output.lines().count(),
+            1,
+            "Compact output MUST fit on a single line"
+        );
+        assert!(output.starts_with(&format!("[{root_ptr:p} | parent: None] (0 -> ")));
+        assert!(output.contains(&format!(
+            "[{leaf_ptr:p} | parent: Some({root_ptr:p}): [0, 2]] [0: 0, 1: 1]"
+        )));
+
+        let mut out = String::new();
+        unsafe { write_node_ptr(&mut out, leaf_ptr).unwrap() };
+        assert_eq!(
+            out,
+            format!("[{leaf_ptr:p} | parent: Some({root_ptr:p}): [0, 2]]")
+        );
+    }
 }
@@ -0,0 +1,163 @@
+use crate::bplustree::BPlusTree;
+use crate::bplustree::aggregate::Aggregate;
+use crate::bplustree::comparator::{Comparator, OrdComparator};
+use crate::bplustree::node::{Node, Position};
+use std::mem::replace;
+use std::ptr::NonNull;
+
+pub enum Entry<'a, K, V, A = (), C = OrdComparator>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    Vacant(VacantEntry<'a, K, V, A, C>),
+    Occupied(OccupiedEntry<'a, K, V, A, C>),
+}
+
+pub struct VacantEntry<'a, K, V, A = (), C = OrdComparator>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    pub(crate) key: K,
+    pub(crate) tree: &'a mut BPlusTree<K, V, A, C>,
+    /// Leaf and index the key would be inserted at, None if the tree is empty
+    pub(crate) position: Option<Position<K, V, A>>,
+}
+
+pub struct OccupiedEntry<'a, K, V, A = (), C = OrdComparator>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    pub(crate) tree: &'a mut BPlusTree<K, V, A, C>,
+    pub(crate) leaf_ptr: NonNull<Node<K, V, A>>,
+    pub(crate) index: usize,
+}
+
+impl<'a, K, V, A, C> Entry<'a, K, V, A, C>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Vacant(entry) => entry.key(),
+            Entry::Occupied(entry) => entry.key(),
+        }
+    }
+
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Entry::Vacant(entry) => entry.insert(default),
+            Entry::Occupied(entry) => entry.into_mut(),
+        }
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Vacant(entry) => entry.insert(default()),
+            Entry::Occupied(entry) => entry.into_mut(),
+        }
+    }
+
+    pub fn or_insert_with_key<F: FnOnce(&K) -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Vacant(entry) => {
+                let value = default(entry.key());
+                entry.insert(value)
+            }
+            Entry::Occupied(entry) => entry.into_mut(),
+        }
+    }
+
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+        match self {
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            }
+        }
+    }
+
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(Default::default)
+    }
+}
+
+impl<'a, K, V, A, C> VacantEntry<'a, K, V, A, C>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Inserts the value using the position found by [`BPlusTree::entry`], the leaf is split and
+    /// the parents are updated exactly like in [`BPlusTree::insert`].
+    pub fn insert(self, value: V) -> &'a mut V {
+        let (mut leaf_ptr, index) = match self.position {
+            Some((leaf_ptr, index)) => unsafe {
+                self.tree.insert_at(leaf_ptr, index, self.key, value)
+            },
+            None => (self.tree.insert_into_empty(self.key, value), 0),
+        };
+
+        let leaf = unsafe { leaf_ptr.as_mut().as_leaf_mut() };
+        &mut leaf.data[index].1
+    }
+}
+
+impl<'a, K, V, A, C> OccupiedEntry<'a, K, V, A, C>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+    fn entry(&self) -> &(K, V) {
+        let leaf = unsafe { self.leaf_ptr.as_ref().as_leaf() };
+        &leaf.data[self.index]
+    }
+
+    pub fn key(&self) -> &K {
+        &self.entry().0
+    }
+
+    pub fn get(&self) -> &V {
+        &self.entry().1
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        let leaf = unsafe { self.leaf_ptr.as_mut().as_leaf_mut() };
+        &mut leaf.data[self.index].1
+    }
+
+    pub fn into_mut(mut self) -> &'a mut V {
+        let leaf = unsafe { self.leaf_ptr.as_mut().as_leaf_mut() };
+        &mut leaf.data[self.index].1
+    }
+
+    pub fn insert(&mut self, value: V) -> V {
+        replace(self.get_mut(), value)
+    }
+
+    /// Removes the entry, rebalancing the tree exactly like [`BPlusTree::remove`].
+    pub fn remove_entry(self) -> (K, V) {
+        unsafe { self.tree.remove_at(self.leaf_ptr, self.index) }
+    }
+
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+}
@@ -0,0 +1,265 @@
+use crate::bplustree::BPlusTree;
+use crate::bplustree::aggregate::Aggregate;
+use crate::bplustree::comparator::Comparator;
+use crate::bplustree::debug::{DebugOptions, format_node_ptr};
+use crate::bplustree::node::Node;
+use std::fmt::{self, Debug};
+use std::io;
+use std::ptr::NonNull;
+
+impl<K, V, A, C> BPlusTree<K, V, A, C>
+where
+    K: Clone + Debug,
+    V: Debug,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+    /// Writes the tree as a Graphviz digraph. Every node is a record with one field per key, child
+    /// edges leave from the field of their separator and dashed edges point back to the parent.
+    /// Addresses are added to the records like [`DebugOptions::all_address`] prints them.
+    pub fn write_dot<W>(&self, out: &mut W, options: DebugOptions) -> fmt::Result
+    where
+        W: fmt::Write,
+    {
+        writeln!(out, "digraph BPlusTree {{")?;
+        writeln!(out, "    node [shape=record];")?;
+        if let Some(root_ptr) = self.root {
+            unsafe { write_dot_node(out, root_ptr, None, &mut 0, options)? };
+        }
+        writeln!(out, "}}")
+    }
+
+    /// Like [`BPlusTree::write_dot`], but for an [`io::Write`] like a file
+    pub fn write_dot_io<W>(&self, out: &mut W, options: DebugOptions) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        write_io(out, |out| self.write_dot(out, options))
+    }
+
+    /// Writes the tree as a single JSON object. Keys and values are written as strings holding
+    /// their Debug output, nodes get an `address` and `parent` when `options` show addresses.
+    pub fn write_json<W>(&self, out: &mut W, options: DebugOptions) -> fmt::Result
+    where
+        W: fmt::Write,
+    {
+        write!(
+            out,
+            "{{\"order\":{},\"size\":{},\"root\":",
+            self.order, self.size
+        )?;
+        match self.root {
+            Some(root_ptr) => unsafe { write_json_node(out, root_ptr, options)? },
+            None => write!(out, "null")?,
+        }
+        write!(out, "}}")
+    }
+
+    /// Like [`BPlusTree::write_json`], but for an [`io::Write`] like a file
+    pub fn write_json_io<W>(&self, out: &mut W, options: DebugOptions) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        write_io(out, |out| self.write_json(out, options))
+    }
+}
+
+/// Runs a writer for fmt::Write on top of an io::Write, keeping the io::Error that made it fail
+fn write_io<W, F>(out: &mut W, write: F) -> io::Result<()>
+where
+    W: io::Write,
+    F: FnOnce(&mut IoAdapter<'_, W>) -> fmt::Result,
+{
+    let mut adapter = IoAdapter {
+        inner: out,
+        error: Ok(()),
+    };
+
+    match write(&mut adapter) {
+        Ok(()) => Ok(()),
+        Err(fmt::Error) => match adapter.error {
+            Err(error) => Err(error),
+            Ok(()) => Err(io::Error::other("formatter error")),
+        },
+    }
+}
+
+struct IoAdapter<'a, W> {
+    inner: &'a mut W,
+    error: io::Result<()>,
+}
+
+impl<W> fmt::Write for IoAdapter<'_, W>
+where
+    W: io::Write,
+{
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|error| {
+            self.error = Err(error);
+            fmt::Error
+        })
+    }
+}
+
+/// Writes the node and its subtree, returns the id the node was written with
+unsafe fn write_dot_node<W, K, V, A>(
+    out: &mut W,
+    node_ptr: NonNull<Node<K, V, A>>,
+    parent_id: Option<usize>,
+    next_id: &mut usize,
+    options: DebugOptions,
+) -> Result<usize, fmt::Error>
+where
+    W: fmt::Write,
+    K: Clone + Debug,
+    V: Debug,
+    A: Aggregate<K, V>,
+{
+    let id = *next_id;
+    *next_id += 1;
+
+    let node = unsafe { node_ptr.as_ref() };
+    let (show_address, fields) = match node {
+        Node::Internal(internal) => (
+            options.show_parent.internal,
+            internal
+                .links
+                .iter()
+                .enumerate()
+                .map(|(index, (k, _))| format!("<f{index}> {}", escape_record(&format!("{k:?}"))))
+                .collect::<Vec<_>>(),
+        ),
+        Node::Leaf(leaf) => (
+            options.show_parent.leaf,
+            leaf.data
+                .iter()
+                .map(|(k, v)| escape_record(&format!("{k:?}: {v:?}")))
+                .collect::<Vec<_>>(),
+        ),
+    };
+
+    let mut label = fields.join("|");
+    if let Some(ptr_debug_options) = show_address {
+        let address = unsafe { format_node_ptr(node_ptr, ptr_debug_options) };
+        label = format!("{}|{label}", escape_record(&address));
+    }
+    writeln!(out, "    n{id} [label=\"{label}\"];")?;
+
+    if let Some(parent_id) = parent_id {
+        writeln!(
+            out,
+            "    n{id} -> n{parent_id} [style=dashed, constraint=false];"
+        )?;
+    }
+
+    if let Node::Internal(internal) = node {
+        for (index, (_, child_ptr)) in internal.links.iter().enumerate() {
+            let child_id = unsafe { write_dot_node(out, *child_ptr, Some(id), next_id, options)? };
+            writeln!(out, "    n{id}:f{index} -> n{child_id};")?;
+        }
+    }
+
+    Ok(id)
+}
+
+unsafe fn write_json_node<W, K, V, A>(
+    out: &mut W,
+    node_ptr: NonNull<Node<K, V, A>>,
+    options: DebugOptions,
+) -> fmt::Result
+where
+    W: fmt::Write,
+    K: Clone + Debug,
+    V: Debug,
+    A: Aggregate<K, V>,
+{
+    let node = unsafe { node_ptr.as_ref() };
+    let (kind, show_address) = match node {
+        Node::Internal(_) => ("internal", options.show_parent.internal),
+        Node::Leaf(_) => ("leaf", options.show_parent.leaf),
+    };
+
+    write!(out, "{{\"type\":\"{kind}\"")?;
+    if show_address.is_some() {
+        write!(out, ",\"address\":\"{node_ptr:p}\",\"parent\":")?;
+        match node.parent_raw() {
+            Some(parent_ptr) => write!(out, "\"{parent_ptr:p}\"")?,
+            None => write!(out, "null")?,
+        }
+    }
+
+    match node {
+        Node::Internal(internal) => {
+            write!(out, ",\"keys\":")?;
+            write_json_strings(out, internal.links.iter().map(|(k, _)| k))?;
+            write!(out, ",\"counts\":[")?;
+            for (index, count) in internal.counts.iter().enumerate() {
+                if index > 0 {
+                    write!(out, ",")?;
+                }
+                write!(out, "{count}")?;
+            }
+            write!(out, "],\"children\":[")?;
+            for (index, (_, child_ptr)) in internal.links.iter().enumerate() {
+                if index > 0 {
+                    write!(out, ",")?;
+                }
+                unsafe { write_json_node(out, *child_ptr, options)? };
+            }
+            write!(out, "]")?;
+        }
+        Node::Leaf(leaf) => {
+            write!(out, ",\"keys\":")?;
+            write_json_strings(out, leaf.data.iter().map(|(k, _)| k))?;
+            write!(out, ",\"values\":")?;
+            write_json_strings(out, leaf.data.iter().map(|(_, v)| v))?;
+        }
+    }
+
+    write!(out, "}}")
+}
+
+/// Writes a JSON array holding the Debug output of every item
+fn write_json_strings<'a, W, T, I>(out: &mut W, items: I) -> fmt::Result
+where
+    W: fmt::Write,
+    T: Debug + 'a,
+    I: Iterator<Item = &'a T>,
+{
+    write!(out, "[")?;
+    for (index, item) in items.enumerate() {
+        if index > 0 {
+            write!(out, ",")?;
+        }
+        write!(out, "\"{}\"", escape_json(&format!("{item:?}")))?;
+    }
+    write!(out, "]")
+}
+
+/// Escapes the characters that structure a Graphviz record label
+fn escape_record(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '{' | '}' | '|' | '<' | '>' | '"' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn escape_json(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,254 @@
+use crate::bplustree::BPlusTree;
+use crate::bplustree::aggregate::Aggregate;
+use crate::bplustree::comparator::Comparator;
+use crate::bplustree::node::Node;
+use std::borrow::Borrow;
+use std::collections::VecDeque;
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::{Bound, RangeBounds};
+use std::ptr::NonNull;
+
+/// Removes and yields the entries in a range that match a predicate, see [`BPlusTree::extract_if`]
+pub struct ExtractIf<'a, K, V, A, C, Q, R, F>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+    Q: ?Sized,
+{
+    tree: &'a mut BPlusTree<K, V, A, C>,
+    range: R,
+    pred: F,
+    /// Largest key handed to pred so far, the next leaf is looked up right after it
+    last_key: Option<K>,
+    extracted: VecDeque<(K, V)>,
+    done: bool,
+    _marker: PhantomData<fn(&Q)>,
+}
+
+/// Brings the links above a leaf up to date if pred or the comparator panic while the leaf still
+/// holds every entry, the values pred changed in place need their summaries to follow
+struct RefreshOnUnwind<'a, K, V, A, C>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+    tree: &'a BPlusTree<K, V, A, C>,
+    leaf_ptr: NonNull<Node<K, V, A>>,
+}
+
+impl<K, V, A, C> Drop for RefreshOnUnwind<'_, K, V, A, C>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+    fn drop(&mut self) {
+        unsafe { self.tree.refresh_links_above(self.leaf_ptr) };
+    }
+}
+
+impl<K, V, A, C> BPlusTree<K, V, A, C>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+    /// Keeps only the entries for which `f` returns true. Entries are removed a leaf at a time and
+    /// each leaf is rebalanced once, instead of walking down from the root for every removed key.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        self.extract_if(.., |k, v| !f(k, v)).for_each(drop);
+    }
+
+    /// Lazily removes and yields the entries in `range` for which `pred` returns true, in key order.
+    /// Whenever the extracted entries run out, `pred` is called on the rest of the next leaf.
+    /// Dropping the iterator early keeps every entry that `pred` hasn't seen yet.
+    pub fn extract_if<Q, R, F>(&mut self, range: R, pred: F) -> ExtractIf<'_, K, V, A, C, Q, R, F>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+        R: RangeBounds<Q>,
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        ExtractIf {
+            tree: self,
+            range,
+            pred,
+            last_key: None,
+            extracted: VecDeque::new(),
+            done: false,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Calls pred on the entries of the leaf from `index` on, until one is past the end of the range,
+    /// and moves the entries it accepts into `extracted`. Returns whether the range was exhausted.
+    ///
+    /// SAFETY: leaf_ptr MUST point to a Leaf of this tree, index MUST be within its entries
+    unsafe fn extract_from_leaf<Q, R, F>(
+        &mut self,
+        mut leaf_ptr: NonNull<Node<K, V, A>>,
+        index: usize,
+        range: &R,
+        pred: &mut F,
+        extracted: &mut VecDeque<(K, V)>,
+        last_key: &mut Option<K>,
+    ) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+        R: RangeBounds<Q>,
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        let past_end = |k: &K| match range.end_bound() {
+            Bound::Included(end) => self.comparator.compare(k.borrow(), end).is_gt(),
+            Bound::Excluded(end) => self.comparator.compare(k.borrow(), end).is_ge(),
+            Bound::Unbounded => false,
+        };
+
+        let leaf = unsafe { leaf_ptr.as_mut().as_leaf_mut() };
+
+        // pred and the comparator run while every entry is still in the leaf, so a panic in either
+        // leaves the tree whole
+        let guard = RefreshOnUnwind {
+            tree: self,
+            leaf_ptr,
+        };
+        let mut decisions = Vec::with_capacity(leaf.size() - index);
+        let mut reached_end = false;
+        for (k, v) in &mut leaf.data[index..] {
+            reached_end = reached_end || past_end(k);
+            decisions.push(!reached_end && pred(k, v));
+        }
+        mem::forget(guard);
+
+        let before = extracted.len();
+        let tail = leaf.data.split_off(index);
+        for ((k, v), extract) in tail.into_iter().zip(decisions) {
+            if extract {
+                extracted.push_back((k, v));
+            } else {
+                leaf.data.push((k, v));
+            }
+        }
+
+        let exhausted = reached_end || leaf.next.is_none();
+        if !exhausted {
+            // Every entry from index on was seen, the largest of them is either kept or extracted
+            let largest = match (leaf.data.last(), extracted.back()) {
+                (Some((kept, _)), Some((taken, _))) => {
+                    if self.comparator.compare(kept, taken).is_gt() {
+                        kept
+                    } else {
+                        taken
+                    }
+                }
+                (Some((k, _)), None) | (None, Some((k, _))) => k,
+                (None, None) => unreachable!("index MUST be within the entries of the leaf"),
+            };
+            *last_key = Some(largest.clone());
+        }
+
+        let removed = extracted.len() - before;
+        if removed == 0 {
+            // Values may have been changed in place, their summaries have to follow
+            unsafe { self.refresh_links_above(leaf_ptr) };
+        } else {
+            self.size -= removed;
+            unsafe { self.fix_trimmed_leaf(leaf_ptr) };
+        }
+
+        exhausted
+    }
+}
+
+impl<K, V, A, C, Q, R, F> ExtractIf<'_, K, V, A, C, Q, R, F>
+where
+    K: Clone + Borrow<Q>,
+    A: Aggregate<K, V>,
+    Q: ?Sized,
+    C: Comparator<K> + Comparator<Q>,
+    R: RangeBounds<Q>,
+    F: FnMut(&K, &mut V) -> bool,
+{
+    /// Runs pred over the next leaf with entries left in the range, returns false once there are none
+    fn extract_next_leaf(&mut self) -> bool {
+        let start = match &self.last_key {
+            Some(k) => self.tree.lower_position(Bound::Excluded(k.borrow())),
+            None => self.tree.lower_position(self.range.start_bound()),
+        };
+
+        let Some((mut leaf_ptr, mut index)) = start else {
+            return false;
+        };
+
+        let leaf = unsafe { leaf_ptr.as_ref().as_leaf() };
+        if index == leaf.size() {
+            let Some(next_ptr) = leaf.next else {
+                return false;
+            };
+            (leaf_ptr, index) = (next_ptr, 0);
+        }
+
+        let exhausted = unsafe {
+            self.tree.extract_from_leaf(
+                leaf_ptr,
+                index,
+                &self.range,
+                &mut self.pred,
+                &mut self.extracted,
+                &mut self.last_key,
+            )
+        };
+        !exhausted
+    }
+}
+
+impl<K, V, A, C, Q, R, F> Iterator for ExtractIf<'_, K, V, A, C, Q, R, F>
+where
+    K: Clone + Borrow<Q>,
+    A: Aggregate<K, V>,
+    Q: ?Sized,
+    C: Comparator<K> + Comparator<Q>,
+    R: RangeBounds<Q>,
+    F: FnMut(&K, &mut V) -> bool,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.extracted.pop_front() {
+                return Some(entry);
+            }
+
+            if self.done {
+                return None;
+            }
+
+            self.done = !self.extract_next_leaf();
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = if self.done { 0 } else { self.tree.size() };
+        (self.extracted.len(), Some(self.extracted.len() + remaining))
+    }
+}
+
+impl<K, V, A, C, Q, R, F> FusedIterator for ExtractIf<'_, K, V, A, C, Q, R, F>
+where
+    K: Clone + Borrow<Q>,
+    A: Aggregate<K, V>,
+    Q: ?Sized,
+    C: Comparator<K> + Comparator<Q>,
+    R: RangeBounds<Q>,
+    F: FnMut(&K, &mut V) -> bool,
+{
+}
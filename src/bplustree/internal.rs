@@ -1,26 +1,63 @@
+use crate::bplustree::aggregate::Aggregate;
+use crate::bplustree::comparator::Comparator;
 use crate::bplustree::node::Node;
+use std::borrow::Borrow;
 use std::fmt::Debug;
 use std::mem::swap;
 use std::ptr::NonNull;
 
+/// A separator and the child holding the keys from it up to the next separator
+pub(crate) type Link<K, V, A = ()> = (K, NonNull<Node<K, V, A>>);
+
 #[derive(Debug)]
-pub(crate) struct Internal<K, V> {
-    pub(crate) parent: Option<NonNull<Node<K, V>>>,
-    pub(crate) links: Vec<(K, NonNull<Node<K, V>>)>,
+pub(crate) struct Internal<K, V, A = ()>
+where
+    A: Aggregate<K, V>,
+{
+    pub(crate) parent: Option<NonNull<Node<K, V, A>>>,
+    pub(crate) links: Vec<Link<K, V, A>>,
+    /// Number of entries in the subtree behind each link
+    pub(crate) counts: Vec<usize>,
+    /// Aggregate summary of the subtree behind each link
+    pub(crate) summaries: Vec<A::Summary>,
 }
 
-impl<K, V> Internal<K, V>
+impl<K, V, A> Internal<K, V, A>
 where
-    K: Ord + PartialOrd + Clone,
+    K: Clone,
+    A: Aggregate<K, V>,
 {
-    pub fn split(&mut self) -> NonNull<Node<K, V>> {
+    /// Creates a parentless node, counting and summarizing the entries of every child
+    pub fn new(links: Vec<Link<K, V, A>>, aggregate: &A) -> Self {
+        let counts = links
+            .iter()
+            .map(|(_, ptr)| unsafe { ptr.as_ref() }.subtree_size())
+            .collect();
+        let summaries = links
+            .iter()
+            .map(|(_, ptr)| unsafe { ptr.as_ref() }.summary(aggregate))
+            .collect();
+
+        Self {
+            parent: None,
+            links,
+            counts,
+            summaries,
+        }
+    }
+
+    pub fn split(&mut self) -> NonNull<Node<K, V, A>> {
         let right = self.links.split_off(self.links.len() / 2);
+        let right_counts = self.counts.split_off(self.counts.len() / 2);
+        let right_summaries = self.summaries.split_off(self.summaries.len() / 2);
         assert!(self.links.len() <= right.len());
 
         unsafe {
             NonNull::new_unchecked(Box::into_raw(Box::new(Node::Internal(Internal {
                 parent: None,
                 links: right,
+                counts: right_counts,
+                summaries: right_summaries,
             }))))
         }
     }
@@ -29,11 +66,40 @@ where
         self.links.len()
     }
 
+    /// Number of entries in all leaves below this node
+    pub fn subtree_size(&self) -> usize {
+        self.counts.iter().sum()
+    }
+
+    pub fn index_of(&self, child_ptr: NonNull<Node<K, V, A>>) -> usize {
+        self.links
+            .iter()
+            .position(|(_, ptr)| *ptr == child_ptr)
+            .expect("A node MUST be linked from its parent")
+    }
+
+    /// Summary of all entries in the leaves below this node
+    pub fn summary(&self, aggregate: &A) -> A::Summary {
+        self.summaries
+            .iter()
+            .fold(aggregate.identity(), |summary, child| {
+                aggregate.combine(&summary, child)
+            })
+    }
+
+    /// Recounts and resummarizes the entries behind the link to child_ptr after its subtree changed
+    pub fn refresh_link(&mut self, child_ptr: NonNull<Node<K, V, A>>, aggregate: &A) {
+        let index = self.index_of(child_ptr);
+        let child = unsafe { child_ptr.as_ref() };
+        self.counts[index] = child.subtree_size();
+        self.summaries[index] = child.summary(aggregate);
+    }
+
     pub fn keys(&self) -> Vec<&K> {
         self.links.iter().map(|(k, _)| k).collect::<Vec<_>>()
     }
 
-    fn smallest_entry(&self) -> &(K, NonNull<Node<K, V>>) {
+    fn smallest_entry(&self) -> &Link<K, V, A> {
         self.links.first().unwrap()
     }
 
@@ -41,27 +107,37 @@ where
         &self.smallest_entry().0
     }
 
-    pub fn smallest_value(&self) -> NonNull<Node<K, V>> {
+    pub fn smallest_value(&self) -> NonNull<Node<K, V, A>> {
         self.smallest_entry().1
     }
 
-    pub fn insert_smallest_entry(&mut self, e: (K, NonNull<Node<K, V>>)) {
+    pub fn insert_smallest_entry(&mut self, e: Link<K, V, A>, aggregate: &A) {
+        let child = unsafe { e.1.as_ref() };
+        self.counts.insert(0, child.subtree_size());
+        self.summaries.insert(0, child.summary(aggregate));
         self.links.insert(0, e);
     }
 
-    pub fn remove_smallest_entry(&mut self) -> (K, NonNull<Node<K, V>>) {
+    pub fn remove_smallest_entry(&mut self) -> Link<K, V, A> {
+        self.counts.remove(0);
+        self.summaries.remove(0);
         self.links.remove(0)
     }
 
-    pub fn insert_largest_entry(&mut self, e: (K, NonNull<Node<K, V>>)) {
+    pub fn insert_largest_entry(&mut self, e: Link<K, V, A>, aggregate: &A) {
+        let child = unsafe { e.1.as_ref() };
+        self.counts.push(child.subtree_size());
+        self.summaries.push(child.summary(aggregate));
         self.links.push(e);
     }
 
-    pub fn remove_largest_entry(&mut self) -> (K, NonNull<Node<K, V>>) {
+    pub fn remove_largest_entry(&mut self) -> Link<K, V, A> {
+        self.counts.pop().unwrap();
+        self.summaries.pop().unwrap();
         self.links.pop().unwrap()
     }
 
-    pub fn lmerge_into(&mut self, other: &mut Internal<K, V>) {
+    pub fn lmerge_into(&mut self, other: &mut Internal<K, V, A>) {
         if let Some(new_parent) = unsafe { other.smallest_value().as_ref() }.parent_raw() {
             for (_, ptr) in self.links.iter_mut() {
                 unsafe {
@@ -72,9 +148,13 @@ where
 
         self.links.append(&mut other.links); // TODO: Should just use a VecDeque
         swap(&mut self.links, &mut other.links);
+        self.counts.append(&mut other.counts);
+        swap(&mut self.counts, &mut other.counts);
+        self.summaries.append(&mut other.summaries);
+        swap(&mut self.summaries, &mut other.summaries);
     }
 
-    pub fn rmerge_into(&mut self, other: &mut Internal<K, V>) {
+    pub fn rmerge_into(&mut self, other: &mut Internal<K, V, A>) {
         if let Some(new_parent) = unsafe { other.smallest_value().as_ref() }.parent_raw() {
             for (_, ptr) in self.links.iter_mut() {
                 unsafe {
@@ -84,27 +164,36 @@ where
         }
 
         other.links.append(&mut self.links);
+        other.counts.append(&mut self.counts);
+        other.summaries.append(&mut self.summaries);
     }
 
     pub fn is_root(&self) -> bool {
         self.parent.is_none()
     }
 
-    pub unsafe fn new_with_children(
-        mut child1_ptr: NonNull<Node<K, V>>,
-        mut child2_ptr: NonNull<Node<K, V>>,
-    ) -> NonNull<Node<K, V>> {
+    pub unsafe fn new_with_children<C>(
+        mut child1_ptr: NonNull<Node<K, V, A>>,
+        mut child2_ptr: NonNull<Node<K, V, A>>,
+        aggregate: &A,
+        cmp: &C,
+    ) -> NonNull<Node<K, V, A>>
+    where
+        C: Comparator<K>,
+    {
         let child1 = unsafe { child1_ptr.as_mut() };
         let child2 = unsafe { child2_ptr.as_mut() };
 
         let key1 = child1.smallest_key();
         let key2 = child2.smallest_key();
 
-        debug_assert!(key1 <= key2);
+        debug_assert!(cmp.compare(key1, key2).is_le());
 
         let internal_node = Node::Internal(Internal {
             parent: None,
             links: vec![(key1.clone(), child1_ptr), (key2.clone(), child2_ptr)],
+            counts: vec![child1.subtree_size(), child2.subtree_size()],
+            summaries: vec![child1.summary(aggregate), child2.summary(aggregate)],
         });
 
         let internal_ptr =
@@ -118,92 +207,142 @@ where
         internal_ptr
     }
 
-    pub fn remove(&mut self, k: &K) -> Option<NonNull<Node<K, V>>> {
-        let result = self.links.binary_search_by(|(key, _)| key.cmp(k));
+    pub fn remove<C>(&mut self, k: &K, cmp: &C) -> Option<NonNull<Node<K, V, A>>>
+    where
+        C: Comparator<K>,
+    {
+        let result = self.links.binary_search_by(|(key, _)| cmp.compare(key, k));
         match result {
             Ok(index) => {
                 let (k, v) = self.links.remove(index);
+                self.counts.remove(index);
+                self.summaries.remove(index);
                 Some(v)
             }
             Err(index) => None,
         }
     }
 
-    pub unsafe fn insert_or_replace(
+    pub unsafe fn insert_or_replace<C>(
         &mut self,
         k: K,
-        ptr: NonNull<Node<K, V>>,
-    ) -> Option<NonNull<Node<K, V>>> {
+        ptr: NonNull<Node<K, V, A>>,
+        aggregate: &A,
+        cmp: &C,
+    ) -> Option<NonNull<Node<K, V, A>>>
+    where
+        C: Comparator<K>,
+    {
         let insert = self.links.is_empty();
-        let index = self.less_or_equal_to_index(&k);
+        let index = self.less_or_equal_to_index(&k, cmp);
+        let count = unsafe { ptr.as_ref() }.subtree_size();
+        let summary = unsafe { ptr.as_ref() }.summary(aggregate);
         if insert {
             self.links.insert(index, (k, ptr));
+            self.counts.insert(index, count);
+            self.summaries.insert(index, summary);
             None
         } else {
             let out = self.links[index].1;
             self.links[index].1 = ptr;
+            self.counts[index] = count;
+            self.summaries[index] = summary;
             Some(out)
         }
     }
 
-    pub fn find_value_less_or_equal_to(&self, k: &K) -> NonNull<Node<K, V>> {
-        self.find_entry_less_or_equal_to(k).1
+    pub fn find_value_less_or_equal_to<Q, C>(&self, k: &Q, cmp: &C) -> NonNull<Node<K, V, A>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        self.find_entry_less_or_equal_to(k, cmp).1
     }
 
-    pub fn find_key_less_or_equal_to(&self, k: &K) -> &K {
-        &self.find_entry_less_or_equal_to(k).0
+    pub fn find_key_less_or_equal_to<C>(&self, k: &K, cmp: &C) -> &K
+    where
+        C: Comparator<K>,
+    {
+        &self.find_entry_less_or_equal_to(k, cmp).0
     }
 
-    pub fn find_key_mut_less_or_equal_to(&mut self, k: &K) -> &mut K {
-        &mut self.find_entry_mut_less_or_equal_to(k).0
+    pub fn find_key_mut_less_or_equal_to<C>(&mut self, k: &K, cmp: &C) -> &mut K
+    where
+        C: Comparator<K>,
+    {
+        &mut self.find_entry_mut_less_or_equal_to(k, cmp).0
     }
 
-    pub fn find_entry_less_or_equal_to(&self, k: &K) -> &(K, NonNull<Node<K, V>>) {
+    pub fn find_entry_less_or_equal_to<Q, C>(&self, k: &Q, cmp: &C) -> &Link<K, V, A>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
         debug_assert!(
             !self.links.is_empty(),
             "An internal Node must have children"
         );
 
-        let index = self.less_or_equal_to_index(k);
+        let index = self.less_or_equal_to_index(k, cmp);
         &self.links[index]
     }
 
-    pub fn find_entry_mut_less_or_equal_to(&mut self, k: &K) -> &mut (K, NonNull<Node<K, V>>) {
+    pub fn find_entry_mut_less_or_equal_to<C>(&mut self, k: &K, cmp: &C) -> &mut Link<K, V, A>
+    where
+        C: Comparator<K>,
+    {
         debug_assert!(
             !self.links.is_empty(),
             "An internal Node must have children"
         );
 
-        let index = self.less_or_equal_to_index(k);
+        let index = self.less_or_equal_to_index(k, cmp);
         &mut self.links[index]
     }
 
-    pub fn find_entry(&self, k: &K) -> Option<&(K, NonNull<Node<K, V>>)> {
+    pub fn find_entry<C>(&self, k: &K, cmp: &C) -> Option<&Link<K, V, A>>
+    where
+        C: Comparator<K>,
+    {
         debug_assert!(
             !self.links.is_empty(),
             "An internal Node must have children"
         );
 
-        let index = self.links.binary_search_by(|(key, _)| key.cmp(k)).ok()?;
+        let index = self
+            .links
+            .binary_search_by(|(key, _)| cmp.compare(key, k))
+            .ok()?;
 
         Some(&self.links[index])
     }
 
-    pub fn find_entry_mut(&mut self, k: &K) -> Option<&mut (K, NonNull<Node<K, V>>)> {
+    pub fn find_entry_mut<C>(&mut self, k: &K, cmp: &C) -> Option<&mut Link<K, V, A>>
+    where
+        C: Comparator<K>,
+    {
         debug_assert!(
             !self.links.is_empty(),
             "An internal Node must have children"
         );
 
-        let index = self.links.binary_search_by(|(key, _)| key.cmp(k)).ok()?;
+        let index = self
+            .links
+            .binary_search_by(|(key, _)| cmp.compare(key, k))
+            .ok()?;
 
         Some(&mut self.links[index])
     }
 
-    pub fn left_index(&self, k: &K) -> Option<usize> {
+    pub fn left_index<C>(&self, k: &K, cmp: &C) -> Option<usize>
+    where
+        C: Comparator<K>,
+    {
         let mut index = self
             .links
-            .binary_search_by(|(key, _)| key.cmp(k))
+            .binary_search_by(|(key, _)| cmp.compare(key, k))
             .unwrap_or_else(|index| index);
 
         if index == 0 {
@@ -215,28 +354,43 @@ where
         Some(index)
     }
 
-    pub fn left(&self, k: &K) -> Option<&K> {
-        let (k, _) = self.left_entry(k)?;
+    pub fn left<C>(&self, k: &K, cmp: &C) -> Option<&K>
+    where
+        C: Comparator<K>,
+    {
+        let (k, _) = self.left_entry(k, cmp)?;
         Some(k)
     }
 
-    pub fn left_mut(&mut self, k: &K) -> Option<&mut K> {
-        let (k, _) = self.left_entry_mut(k)?;
+    pub fn left_mut<C>(&mut self, k: &K, cmp: &C) -> Option<&mut K>
+    where
+        C: Comparator<K>,
+    {
+        let (k, _) = self.left_entry_mut(k, cmp)?;
         Some(k)
     }
 
-    pub fn left_entry(&self, k: &K) -> Option<&(K, NonNull<Node<K, V>>)> {
-        let index = self.left_index(k)?;
+    pub fn left_entry<C>(&self, k: &K, cmp: &C) -> Option<&Link<K, V, A>>
+    where
+        C: Comparator<K>,
+    {
+        let index = self.left_index(k, cmp)?;
         Some(&self.links[index])
     }
 
-    pub fn left_entry_mut(&mut self, k: &K) -> Option<&mut (K, NonNull<Node<K, V>>)> {
-        let index = self.left_index(k)?;
+    pub fn left_entry_mut<C>(&mut self, k: &K, cmp: &C) -> Option<&mut Link<K, V, A>>
+    where
+        C: Comparator<K>,
+    {
+        let index = self.left_index(k, cmp)?;
         Some(&mut self.links[index])
     }
 
-    pub fn right_index(&self, k: &K) -> Option<usize> {
-        let index = self.links.binary_search_by(|(key, _)| key.cmp(k));
+    pub fn right_index<C>(&self, k: &K, cmp: &C) -> Option<usize>
+    where
+        C: Comparator<K>,
+    {
+        let index = self.links.binary_search_by(|(key, _)| cmp.compare(key, k));
         if let Ok(index) = index {
             if index >= (self.links.len() - 1) {
                 return None;
@@ -257,41 +411,58 @@ where
         Some(index)
     }
 
-    pub fn right(&self, k: &K) -> Option<&K> {
-        let (k, _) = self.right_entry(k)?;
+    pub fn right<C>(&self, k: &K, cmp: &C) -> Option<&K>
+    where
+        C: Comparator<K>,
+    {
+        let (k, _) = self.right_entry(k, cmp)?;
         Some(k)
     }
 
-    pub fn right_mut(&mut self, k: &K) -> Option<&mut K> {
-        let (k, _) = self.right_entry_mut(k)?;
+    pub fn right_mut<C>(&mut self, k: &K, cmp: &C) -> Option<&mut K>
+    where
+        C: Comparator<K>,
+    {
+        let (k, _) = self.right_entry_mut(k, cmp)?;
         Some(k)
     }
 
-    pub fn right_entry(&self, k: &K) -> Option<&(K, NonNull<Node<K, V>>)> {
-        let index = self.right_index(k)?;
+    pub fn right_entry<C>(&self, k: &K, cmp: &C) -> Option<&Link<K, V, A>>
+    where
+        C: Comparator<K>,
+    {
+        let index = self.right_index(k, cmp)?;
         Some(&self.links[index])
     }
 
-    pub fn right_entry_mut(&mut self, k: &K) -> Option<&mut (K, NonNull<Node<K, V>>)> {
-        let index = self.right_index(k)?;
+    pub fn right_entry_mut<C>(&mut self, k: &K, cmp: &C) -> Option<&mut Link<K, V, A>>
+    where
+        C: Comparator<K>,
+    {
+        let index = self.right_index(k, cmp)?;
         Some(&mut self.links[index])
     }
 
-    pub fn less_or_equal_to_index(&self, k: &K) -> usize {
+    pub fn less_or_equal_to_index<Q, C>(&self, k: &Q, cmp: &C) -> usize
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
         self.links
-            .binary_search_by(|(key, _)| key.cmp(k))
+            .binary_search_by(|(key, _)| cmp.compare(key.borrow(), k))
             .unwrap_or_else(|index| if index == 0 { index } else { index - 1 })
     }
 
-    pub fn parent_raw(&self) -> Option<NonNull<Node<K, V>>> {
+    pub fn parent_raw(&self) -> Option<NonNull<Node<K, V, A>>> {
         self.parent
     }
 
-    pub fn parent(&self) -> Option<&Internal<K, V>> {
+    pub fn parent(&self) -> Option<&Internal<K, V, A>> {
         unsafe { Some(self.parent_raw()?.as_ref().as_internal()) }
     }
 
-    pub fn parent_mut(&mut self) -> Option<&mut Internal<K, V>> {
+    pub fn parent_mut(&mut self) -> Option<&mut Internal<K, V, A>> {
         unsafe { Some(self.parent_raw()?.as_mut().as_internal_mut()) }
     }
 }
@@ -0,0 +1,295 @@
+use crate::bplustree::BPlusTree;
+use crate::bplustree::aggregate::Aggregate;
+use crate::bplustree::comparator::Comparator;
+use crate::bplustree::node::Node;
+use crate::bplustree::stats::TreeStats;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::ptr::NonNull;
+
+/// The first broken invariant found by [`BPlusTree::check_invariants`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvariantViolation {
+    /// Child indices leading from the root to the offending node, empty for the root itself
+    pub path: Vec<usize>,
+    pub kind: ViolationKind,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// The key at `index` is not greater than the key before it
+    UnsortedKey { index: usize },
+    /// The separator at `index` is not the smallest key of its child
+    SeparatorMismatch { index: usize },
+    /// A node other than the root has fewer than `min_node_size()` entries
+    Underfull { size: usize, min: usize },
+    /// The node has more than `max_node_size()` entries
+    Overfull { size: usize, max: usize },
+    /// The root is an empty Leaf or an Internal node with a single child
+    DegenerateRoot { size: usize },
+    /// The leaf is not as deep as the leaves before it
+    LeafDepth { depth: usize, expected: usize },
+    /// The node doesn't point back to the node linking to it
+    WrongParent,
+    /// The count stored for the link at `index` doesn't match the entries below it
+    CountMismatch {
+        index: usize,
+        stored: usize,
+        counted: usize,
+    },
+    /// The summary stored for the link at `index` doesn't match the entries below it
+    SummaryMismatch { index: usize },
+    /// The leaf isn't linked to the leaves before and after it in key order
+    BrokenLeafChain,
+    /// The size of the tree doesn't match the number of entries in its leaves
+    SizeMismatch { size: usize, counted: usize },
+}
+
+impl Display for ViolationKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ViolationKind::UnsortedKey { index } => {
+                write!(
+                    f,
+                    "key at index {index} is not greater than the previous key"
+                )
+            }
+            ViolationKind::SeparatorMismatch { index } => {
+                write!(
+                    f,
+                    "separator at index {index} is not the smallest key of its child"
+                )
+            }
+            ViolationKind::Underfull { size, min } => {
+                write!(f, "node has {size} entries, expected at least {min}")
+            }
+            ViolationKind::Overfull { size, max } => {
+                write!(f, "node has {size} entries, expected at most {max}")
+            }
+            ViolationKind::DegenerateRoot { size } => {
+                write!(f, "root has {size} entries")
+            }
+            ViolationKind::LeafDepth { depth, expected } => {
+                write!(f, "leaf is at depth {depth}, expected {expected}")
+            }
+            ViolationKind::WrongParent => {
+                write!(f, "node doesn't point back to its parent")
+            }
+            ViolationKind::CountMismatch {
+                index,
+                stored,
+                counted,
+            } => {
+                write!(
+                    f,
+                    "link at index {index} counts {stored} entries, but its subtree has {counted}"
+                )
+            }
+            ViolationKind::SummaryMismatch { index } => {
+                write!(
+                    f,
+                    "summary of the link at index {index} doesn't match its subtree"
+                )
+            }
+            ViolationKind::BrokenLeafChain => {
+                write!(f, "leaf is not linked to its neighbours in key order")
+            }
+            ViolationKind::SizeMismatch { size, counted } => {
+                write!(
+                    f,
+                    "tree size is {size}, but its leaves hold {counted} entries"
+                )
+            }
+        }
+    }
+}
+
+impl Display for InvariantViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "node {:?}: {}", self.path, self.kind)
+    }
+}
+
+impl Error for InvariantViolation {}
+
+/// State carried through the walk, leaves are visited in key order
+struct Walk<K, V, A>
+where
+    A: Aggregate<K, V>,
+{
+    path: Vec<usize>,
+    leaf_depth: Option<usize>,
+    previous_leaf: Option<NonNull<Node<K, V, A>>>,
+    previous_leaf_path: Vec<usize>,
+}
+
+impl<K, V, A> Walk<K, V, A>
+where
+    A: Aggregate<K, V>,
+{
+    fn violation(&self, kind: ViolationKind) -> InvariantViolation {
+        InvariantViolation {
+            path: self.path.clone(),
+            kind,
+        }
+    }
+}
+
+impl<K, V, A, C> BPlusTree<K, V, A, C>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+    /// Walks the whole tree and checks that keys are sorted, separators match the smallest key of
+    /// their child, nodes are neither under- nor overfull, all leaves are equally deep, parent
+    /// pointers, link counts, link summaries and the leaf chain are consistent and the size matches
+    /// the entries. Returns the first violation in key order, or the [`BPlusTree::stats`] of the
+    /// tree.
+    pub fn check_invariants(&self) -> Result<TreeStats, InvariantViolation>
+    where
+        A::Summary: PartialEq,
+    {
+        let mut walk = Walk {
+            path: vec![],
+            leaf_depth: None,
+            previous_leaf: None,
+            previous_leaf_path: vec![],
+        };
+
+        let Some(root_ptr) = self.root else {
+            if self.size != 0 {
+                return Err(walk.violation(ViolationKind::SizeMismatch {
+                    size: self.size,
+                    counted: 0,
+                }));
+            }
+
+            return Ok(self.stats());
+        };
+
+        let root = unsafe { root_ptr.as_ref() };
+        let degenerate = match root {
+            Node::Internal(internal) => internal.size() < 2,
+            Node::Leaf(leaf) => leaf.size() == 0,
+        };
+        if degenerate {
+            return Err(walk.violation(ViolationKind::DegenerateRoot { size: root.size() }));
+        }
+
+        let (counted, _) = unsafe { self.check_node(root_ptr, None, 1, &mut walk)? };
+
+        let last_ptr = walk
+            .previous_leaf
+            .expect("A tree with a root MUST have a leaf");
+        if unsafe { last_ptr.as_ref() }.as_leaf().next.is_some() {
+            walk.path = walk.previous_leaf_path.clone();
+            return Err(walk.violation(ViolationKind::BrokenLeafChain));
+        }
+
+        if counted != self.size {
+            walk.path.clear();
+            return Err(walk.violation(ViolationKind::SizeMismatch {
+                size: self.size,
+                counted,
+            }));
+        }
+
+        Ok(self.stats())
+    }
+
+    /// Returns the number of entries in the subtree and their summary. On error walk.path points at
+    /// the offending node.
+    unsafe fn check_node(
+        &self,
+        node_ptr: NonNull<Node<K, V, A>>,
+        parent_ptr: Option<NonNull<Node<K, V, A>>>,
+        depth: usize,
+        walk: &mut Walk<K, V, A>,
+    ) -> Result<(usize, A::Summary), InvariantViolation>
+    where
+        A::Summary: PartialEq,
+    {
+        let node = unsafe { node_ptr.as_ref() };
+        if node.parent_raw() != parent_ptr {
+            return Err(walk.violation(ViolationKind::WrongParent));
+        }
+
+        let size = node.size();
+        if size > self.max_node_size() {
+            return Err(walk.violation(ViolationKind::Overfull {
+                size,
+                max: self.max_node_size(),
+            }));
+        }
+
+        if parent_ptr.is_some() && size < self.min_node_size() {
+            return Err(walk.violation(ViolationKind::Underfull {
+                size,
+                min: self.min_node_size(),
+            }));
+        }
+
+        let keys = node.keys();
+        if let Some(index) =
+            (1..keys.len()).find(|&i| !self.comparator.compare(keys[i - 1], keys[i]).is_lt())
+        {
+            return Err(walk.violation(ViolationKind::UnsortedKey { index }));
+        }
+
+        let internal = match node {
+            Node::Internal(internal) => internal,
+            Node::Leaf(leaf) => {
+                let expected = *walk.leaf_depth.get_or_insert(depth);
+                if depth != expected {
+                    return Err(walk.violation(ViolationKind::LeafDepth { depth, expected }));
+                }
+
+                let linked_after_previous = match walk.previous_leaf {
+                    Some(previous_ptr) => {
+                        unsafe { previous_ptr.as_ref() }.as_leaf().next == Some(node_ptr)
+                    }
+                    None => true,
+                };
+                if !linked_after_previous || leaf.prev != walk.previous_leaf {
+                    return Err(walk.violation(ViolationKind::BrokenLeafChain));
+                }
+
+                walk.previous_leaf = Some(node_ptr);
+                walk.previous_leaf_path.clone_from(&walk.path);
+                return Ok((size, self.aggregate.summarize(&leaf.data)));
+            }
+        };
+
+        let mut counted = 0;
+        let mut summary = self.aggregate.identity();
+        for (index, (k, child_ptr)) in internal.links.iter().enumerate() {
+            walk.path.push(index);
+            let (child_size, child_summary) =
+                unsafe { self.check_node(*child_ptr, Some(node_ptr), depth + 1, walk)? };
+            walk.path.pop();
+
+            let child = unsafe { child_ptr.as_ref() };
+            if !self.comparator.compare(k, child.smallest_key()).is_eq() {
+                return Err(walk.violation(ViolationKind::SeparatorMismatch { index }));
+            }
+
+            if internal.counts.get(index) != Some(&child_size) {
+                return Err(walk.violation(ViolationKind::CountMismatch {
+                    index,
+                    stored: internal.counts.get(index).copied().unwrap_or(0),
+                    counted: child_size,
+                }));
+            }
+
+            if internal.summaries.get(index) != Some(&child_summary) {
+                return Err(walk.violation(ViolationKind::SummaryMismatch { index }));
+            }
+
+            counted += child_size;
+            summary = self.aggregate.combine(&summary, &child_summary);
+        }
+
+        Ok((counted, summary))
+    }
+}
@@ -0,0 +1,861 @@
+use crate::bplustree::BPlusTree;
+use crate::bplustree::aggregate::Aggregate;
+use crate::bplustree::comparator::{Comparator, OrdComparator};
+use crate::bplustree::node::{Node, Position};
+use std::borrow::Borrow;
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+use std::ptr::NonNull;
+use std::vec;
+
+/// A pair of positions inside the leaf level, `front` points at the next entry to be yielded
+/// from the front, `back` points one past the next entry to be yielded from the back.
+pub(crate) struct LeafRange<K, V, A = ()>
+where
+    A: Aggregate<K, V>,
+{
+    front: Option<Position<K, V, A>>,
+    back: Option<Position<K, V, A>>,
+}
+
+impl<K, V, A> Clone for LeafRange<K, V, A>
+where
+    A: Aggregate<K, V>,
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<K, V, A> Copy for LeafRange<K, V, A> where A: Aggregate<K, V> {}
+
+impl<K, V, A> LeafRange<K, V, A>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    pub(crate) fn empty() -> Self {
+        Self {
+            front: None,
+            back: None,
+        }
+    }
+
+    /// SAFETY:
+    ///  * both positions MUST point into Leaf nodes of the same tree
+    ///  * front MUST NOT be positioned after back
+    pub(crate) unsafe fn new(front: Position<K, V, A>, back: Position<K, V, A>) -> Self {
+        Self {
+            front: Some(front),
+            back: Some(back),
+        }
+    }
+
+    pub(crate) fn all<C>(tree: &BPlusTree<K, V, A, C>) -> Self
+    where
+        C: Comparator<K>,
+    {
+        let (Some(first), Some(last)) = (tree.first_leaf_raw(), tree.last_leaf_raw()) else {
+            return Self::empty();
+        };
+
+        let last_size = unsafe { last.as_ref().size() };
+        unsafe { Self::new((first, 0), (last, last_size)) }
+    }
+
+    pub(crate) fn range<Q, R, C>(tree: &BPlusTree<K, V, A, C>, range: R) -> Self
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<K> + Comparator<Q>,
+        R: RangeBounds<Q>,
+    {
+        match (range.start_bound(), range.end_bound()) {
+            (Bound::Excluded(start), Bound::Excluded(end))
+                if tree.comparator.compare(start, end).is_eq() =>
+            {
+                panic!("range start and end are equal and excluded in BPlusTree")
+            }
+            (
+                Bound::Included(start) | Bound::Excluded(start),
+                Bound::Included(end) | Bound::Excluded(end),
+            ) if tree.comparator.compare(start, end).is_gt() => {
+                panic!("range start is greater than range end in BPlusTree")
+            }
+            _ => {}
+        }
+
+        let (Some(front), Some(back)) = (
+            tree.lower_position(range.start_bound()),
+            tree.upper_position(range.end_bound()),
+        ) else {
+            return Self::empty();
+        };
+
+        unsafe { Self::new(front, back) }
+    }
+
+    pub(crate) fn next_position(&mut self) -> Option<Position<K, V, A>> {
+        loop {
+            let (front, back) = (self.front?, self.back?);
+            if front == back {
+                self.front = None;
+                self.back = None;
+                return None;
+            }
+
+            let (leaf_ptr, index) = front;
+            let leaf = unsafe { leaf_ptr.as_ref().as_leaf() };
+            if index < leaf.size() {
+                self.front = Some((leaf_ptr, index + 1));
+                return Some(front);
+            }
+
+            let next = leaf
+                .next
+                .expect("Front position can't move past the back position");
+            self.front = Some((next, 0));
+        }
+    }
+
+    pub(crate) fn next_back_position(&mut self) -> Option<Position<K, V, A>> {
+        loop {
+            let (front, back) = (self.front?, self.back?);
+            if front == back {
+                self.front = None;
+                self.back = None;
+                return None;
+            }
+
+            let (leaf_ptr, index) = back;
+            if index > 0 {
+                self.back = Some((leaf_ptr, index - 1));
+                return self.back;
+            }
+
+            let leaf = unsafe { leaf_ptr.as_ref().as_leaf() };
+            let prev_ptr = leaf
+                .prev
+                .expect("Back position can't move past the front position");
+            let prev_size = unsafe { prev_ptr.as_ref().size() };
+            self.back = Some((prev_ptr, prev_size));
+        }
+    }
+}
+
+/// SAFETY: position MUST point at an existing entry of a Leaf node that outlives 'a
+pub(crate) unsafe fn entry_ref<'a, K, V, A>((leaf_ptr, index): Position<K, V, A>) -> (&'a K, &'a V)
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    let leaf = unsafe { leaf_ptr.as_ref().as_leaf() };
+    let (k, v) = unsafe { &*leaf.data.as_ptr().add(index) };
+    (k, v)
+}
+
+/// SAFETY:
+///  * position MUST point at an existing entry of a Leaf node that outlives 'a
+///  * no other reference to the same entry may be alive
+pub(crate) unsafe fn entry_mut<'a, K, V, A>(
+    (mut leaf_ptr, index): Position<K, V, A>,
+) -> (&'a K, &'a mut V)
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    let leaf = unsafe { leaf_ptr.as_mut().as_leaf_mut() };
+    let (k, v) = unsafe { &mut *leaf.data.as_mut_ptr().add(index) };
+    (k, v)
+}
+
+pub struct Iter<'a, K, V, A = ()>
+where
+    A: Aggregate<K, V>,
+{
+    range: LeafRange<K, V, A>,
+    length: usize,
+    _marker: PhantomData<&'a (K, V)>,
+}
+
+impl<'a, K, V, A> Iter<'a, K, V, A>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    pub(crate) fn new<C>(tree: &'a BPlusTree<K, V, A, C>) -> Self
+    where
+        C: Comparator<K>,
+    {
+        Self {
+            range: LeafRange::all(tree),
+            length: tree.size(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<K, V, A> Clone for Iter<'_, K, V, A>
+where
+    A: Aggregate<K, V>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            range: self.range,
+            length: self.length,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, K, V, A> Iterator for Iter<'a, K, V, A>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.length == 0 {
+            return None;
+        }
+
+        let position = self.range.next_position()?;
+        self.length -= 1;
+        Some(unsafe { entry_ref(position) })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.length, Some(self.length))
+    }
+}
+
+impl<K, V, A> DoubleEndedIterator for Iter<'_, K, V, A>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.length == 0 {
+            return None;
+        }
+
+        let position = self.range.next_back_position()?;
+        self.length -= 1;
+        Some(unsafe { entry_ref(position) })
+    }
+}
+
+impl<K, V, A> ExactSizeIterator for Iter<'_, K, V, A>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+}
+
+impl<K, V, A> FusedIterator for Iter<'_, K, V, A>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+}
+
+// SAFETY: the iterator only hands out shared references to the keys and values of the leaves it
+// walks, like the &'a (K, V) it stands for
+unsafe impl<K, V, A> Send for Iter<'_, K, V, A>
+where
+    K: Sync,
+    V: Sync,
+    A: Aggregate<K, V>,
+{
+}
+
+unsafe impl<K, V, A> Sync for Iter<'_, K, V, A>
+where
+    K: Sync,
+    V: Sync,
+    A: Aggregate<K, V>,
+{
+}
+
+pub struct IterMut<'a, K, V, A = ()>
+where
+    A: Aggregate<K, V>,
+{
+    range: LeafRange<K, V, A>,
+    length: usize,
+    _marker: PhantomData<&'a mut (K, V)>,
+}
+
+impl<'a, K, V, A> IterMut<'a, K, V, A>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    pub(crate) fn new<C>(tree: &'a mut BPlusTree<K, V, A, C>) -> Self
+    where
+        C: Comparator<K>,
+    {
+        Self {
+            range: LeafRange::all(tree),
+            length: tree.size(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, K, V, A> Iterator for IterMut<'a, K, V, A>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.length == 0 {
+            return None;
+        }
+
+        let position = self.range.next_position()?;
+        self.length -= 1;
+        Some(unsafe { entry_mut(position) })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.length, Some(self.length))
+    }
+}
+
+impl<K, V, A> DoubleEndedIterator for IterMut<'_, K, V, A>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.length == 0 {
+            return None;
+        }
+
+        let position = self.range.next_back_position()?;
+        self.length -= 1;
+        Some(unsafe { entry_mut(position) })
+    }
+}
+
+impl<K, V, A> ExactSizeIterator for IterMut<'_, K, V, A>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+}
+
+impl<K, V, A> FusedIterator for IterMut<'_, K, V, A>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+}
+
+// SAFETY: the iterator borrows the tree mutably, so it's the only way to reach the keys and values
+// of the leaves it walks, like the &'a mut (K, V) it stands for
+unsafe impl<K, V, A> Send for IterMut<'_, K, V, A>
+where
+    K: Send,
+    V: Send,
+    A: Aggregate<K, V>,
+{
+}
+
+unsafe impl<K, V, A> Sync for IterMut<'_, K, V, A>
+where
+    K: Sync,
+    V: Sync,
+    A: Aggregate<K, V>,
+{
+}
+
+pub struct Range<'a, K, V, A = ()>
+where
+    A: Aggregate<K, V>,
+{
+    range: LeafRange<K, V, A>,
+    _marker: PhantomData<&'a (K, V)>,
+}
+
+impl<'a, K, V, A> Range<'a, K, V, A>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    pub(crate) fn new<Q, R, C>(tree: &'a BPlusTree<K, V, A, C>, range: R) -> Self
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<K> + Comparator<Q>,
+        R: RangeBounds<Q>,
+    {
+        Self {
+            range: LeafRange::range(tree, range),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<K, V, A> Clone for Range<'_, K, V, A>
+where
+    A: Aggregate<K, V>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            range: self.range,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, K, V, A> Iterator for Range<'a, K, V, A>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let position = self.range.next_position()?;
+        Some(unsafe { entry_ref(position) })
+    }
+}
+
+impl<K, V, A> DoubleEndedIterator for Range<'_, K, V, A>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let position = self.range.next_back_position()?;
+        Some(unsafe { entry_ref(position) })
+    }
+}
+
+impl<K, V, A> FusedIterator for Range<'_, K, V, A>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+}
+
+// SAFETY: the iterator only hands out shared references to the keys and values of the leaves it
+// walks, like the &'a (K, V) it stands for
+unsafe impl<K, V, A> Send for Range<'_, K, V, A>
+where
+    K: Sync,
+    V: Sync,
+    A: Aggregate<K, V>,
+{
+}
+
+unsafe impl<K, V, A> Sync for Range<'_, K, V, A>
+where
+    K: Sync,
+    V: Sync,
+    A: Aggregate<K, V>,
+{
+}
+
+pub struct RangeMut<'a, K, V, A = ()>
+where
+    A: Aggregate<K, V>,
+{
+    range: LeafRange<K, V, A>,
+    _marker: PhantomData<&'a mut (K, V)>,
+}
+
+impl<'a, K, V, A> RangeMut<'a, K, V, A>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    pub(crate) fn new<Q, R, C>(tree: &'a mut BPlusTree<K, V, A, C>, range: R) -> Self
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<K> + Comparator<Q>,
+        R: RangeBounds<Q>,
+    {
+        Self {
+            range: LeafRange::range(tree, range),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, K, V, A> Iterator for RangeMut<'a, K, V, A>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let position = self.range.next_position()?;
+        Some(unsafe { entry_mut(position) })
+    }
+}
+
+impl<K, V, A> DoubleEndedIterator for RangeMut<'_, K, V, A>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let position = self.range.next_back_position()?;
+        Some(unsafe { entry_mut(position) })
+    }
+}
+
+impl<K, V, A> FusedIterator for RangeMut<'_, K, V, A>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+}
+
+// SAFETY: the iterator borrows the tree mutably, so it's the only way to reach the keys and values
+// of the leaves it walks, like the &'a mut (K, V) it stands for
+unsafe impl<K, V, A> Send for RangeMut<'_, K, V, A>
+where
+    K: Send,
+    V: Send,
+    A: Aggregate<K, V>,
+{
+}
+
+unsafe impl<K, V, A> Sync for RangeMut<'_, K, V, A>
+where
+    K: Sync,
+    V: Sync,
+    A: Aggregate<K, V>,
+{
+}
+
+pub struct Keys<'a, K, V, A = ()>
+where
+    A: Aggregate<K, V>,
+{
+    pub(crate) inner: Iter<'a, K, V, A>,
+}
+
+impl<K, V, A> Clone for Keys<'_, K, V, A>
+where
+    A: Aggregate<K, V>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'a, K, V, A> Iterator for Keys<'a, K, V, A>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(k, _)| k)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V, A> DoubleEndedIterator for Keys<'_, K, V, A>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(k, _)| k)
+    }
+}
+
+impl<K, V, A> ExactSizeIterator for Keys<'_, K, V, A>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+}
+
+impl<K, V, A> FusedIterator for Keys<'_, K, V, A>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+}
+
+pub struct Values<'a, K, V, A = ()>
+where
+    A: Aggregate<K, V>,
+{
+    pub(crate) inner: Iter<'a, K, V, A>,
+}
+
+impl<K, V, A> Clone for Values<'_, K, V, A>
+where
+    A: Aggregate<K, V>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'a, K, V, A> Iterator for Values<'a, K, V, A>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, v)| v)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V, A> DoubleEndedIterator for Values<'_, K, V, A>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(_, v)| v)
+    }
+}
+
+impl<K, V, A> ExactSizeIterator for Values<'_, K, V, A>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+}
+
+impl<K, V, A> FusedIterator for Values<'_, K, V, A>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+}
+
+pub struct ValuesMut<'a, K, V, A = ()>
+where
+    A: Aggregate<K, V>,
+{
+    pub(crate) inner: IterMut<'a, K, V, A>,
+}
+
+impl<'a, K, V, A> Iterator for ValuesMut<'a, K, V, A>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    type Item = &'a mut V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, v)| v)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V, A> DoubleEndedIterator for ValuesMut<'_, K, V, A>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(_, v)| v)
+    }
+}
+
+impl<K, V, A> ExactSizeIterator for ValuesMut<'_, K, V, A>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+}
+
+impl<K, V, A> FusedIterator for ValuesMut<'_, K, V, A>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+}
+
+/// Takes the entries out of the leaves one leaf at a time, the emptied nodes are freed together
+/// with the tree once the iterator is dropped. The tree size is used to count remaining entries.
+pub struct IntoIter<K, V, A = (), C = OrdComparator>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    tree: BPlusTree<K, V, A, C>,
+    front_leaf: Option<NonNull<Node<K, V, A>>>,
+    back_leaf: Option<NonNull<Node<K, V, A>>>,
+    front: vec::IntoIter<(K, V)>,
+    back: vec::IntoIter<(K, V)>,
+}
+
+impl<K, V, A, C> IntoIter<K, V, A, C>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+    pub(crate) fn new(tree: BPlusTree<K, V, A, C>) -> Self {
+        Self {
+            front_leaf: tree.first_leaf_raw(),
+            back_leaf: tree.last_leaf_raw(),
+            tree,
+            front: Vec::new().into_iter(),
+            back: Vec::new().into_iter(),
+        }
+    }
+
+    /// SAFETY: leaf_ptr MUST point to a Leaf node owned by self.tree
+    unsafe fn take_leaf_data(mut leaf_ptr: NonNull<Node<K, V, A>>) -> vec::IntoIter<(K, V)> {
+        let leaf = unsafe { leaf_ptr.as_mut().as_leaf_mut() };
+        std::mem::take(&mut leaf.data).into_iter()
+    }
+}
+
+impl<K, V, A, C> Iterator for IntoIter<K, V, A, C>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.tree.size == 0 {
+            return None;
+        }
+
+        loop {
+            if let Some(entry) = self.front.next() {
+                self.tree.size -= 1;
+                return Some(entry);
+            }
+
+            // Leaves already taken by the back half are empty, once we run out of leaves the
+            // remaining entries can only be in the back iterator.
+            let Some(leaf_ptr) = self.front_leaf else {
+                self.tree.size -= 1;
+                return self.back.next();
+            };
+
+            self.front_leaf = unsafe { leaf_ptr.as_ref().as_leaf().next };
+            self.front = unsafe { Self::take_leaf_data(leaf_ptr) };
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.tree.size, Some(self.tree.size))
+    }
+}
+
+impl<K, V, A, C> DoubleEndedIterator for IntoIter<K, V, A, C>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.tree.size == 0 {
+            return None;
+        }
+
+        loop {
+            if let Some(entry) = self.back.next_back() {
+                self.tree.size -= 1;
+                return Some(entry);
+            }
+
+            let Some(leaf_ptr) = self.back_leaf else {
+                self.tree.size -= 1;
+                return self.front.next_back();
+            };
+
+            self.back_leaf = unsafe { leaf_ptr.as_ref().as_leaf().prev };
+            self.back = unsafe { Self::take_leaf_data(leaf_ptr) };
+        }
+    }
+}
+
+impl<K, V, A, C> ExactSizeIterator for IntoIter<K, V, A, C>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+}
+
+impl<K, V, A, C> FusedIterator for IntoIter<K, V, A, C>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+}
+
+impl<K, V, A, C> IntoIterator for BPlusTree<K, V, A, C>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V, A, C>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter::new(self)
+    }
+}
+
+impl<'a, K, V, A, C> IntoIterator for &'a BPlusTree<K, V, A, C>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V, A>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, K, V, C> IntoIterator for &'a mut BPlusTree<K, V, (), C>
+where
+    K: Clone,
+    C: Comparator<K>,
+{
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
@@ -1,38 +1,84 @@
+use crate::bplustree::aggregate::Aggregate;
+use crate::bplustree::comparator::Comparator;
 use crate::bplustree::internal::Internal;
 use crate::bplustree::node::Node;
+use std::borrow::Borrow;
 use std::fmt::Debug;
 use std::mem::swap;
 use std::ptr::NonNull;
 
 #[derive(Debug)]
-pub(crate) struct Leaf<K, V> {
-    pub(crate) parent: Option<NonNull<Node<K, V>>>,
+pub(crate) struct Leaf<K, V, A = ()>
+where
+    A: Aggregate<K, V>,
+{
+    pub(crate) parent: Option<NonNull<Node<K, V, A>>>,
+    pub(crate) prev: Option<NonNull<Node<K, V, A>>>,
+    pub(crate) next: Option<NonNull<Node<K, V, A>>>,
     pub(crate) data: Vec<(K, V)>,
 }
 
-impl<K, V> Leaf<K, V>
+impl<K, V, A> Leaf<K, V, A>
 where
-    K: Ord + PartialOrd + Clone,
+    K: Clone,
+    A: Aggregate<K, V>,
 {
     pub(crate) fn new() -> Self {
         Self {
             parent: None,
+            prev: None,
+            next: None,
             data: vec![],
         }
     }
 
-    pub(crate) fn split(&mut self) -> NonNull<Node<K, V>> {
+    pub(crate) fn split(&mut self) -> NonNull<Node<K, V, A>> {
         let right = self.data.split_off(self.data.len() / 2);
         debug_assert!(self.data.len() <= right.len());
 
         unsafe {
             NonNull::new_unchecked(Box::into_raw(Box::new(Node::Leaf(Leaf {
                 parent: None,
+                prev: None,
+                next: None,
                 data: right,
             }))))
         }
     }
 
+    /// SAFETY:
+    ///  * both pointers MUST point to Leaf nodes
+    ///  * new_ptr MUST NOT be linked to any other leaf yet
+    pub(crate) unsafe fn link_after(
+        mut leaf_ptr: NonNull<Node<K, V, A>>,
+        mut new_ptr: NonNull<Node<K, V, A>>,
+    ) {
+        let leaf = unsafe { leaf_ptr.as_mut().as_leaf_mut() };
+        let new = unsafe { new_ptr.as_mut().as_leaf_mut() };
+
+        new.prev = Some(leaf_ptr);
+        new.next = leaf.next;
+        if let Some(mut next_ptr) = leaf.next {
+            unsafe { next_ptr.as_mut().as_leaf_mut().prev = Some(new_ptr) };
+        }
+
+        leaf.next = Some(new_ptr);
+    }
+
+    /// SAFETY: leaf_ptr MUST point to a Leaf node
+    pub(crate) unsafe fn unlink(mut leaf_ptr: NonNull<Node<K, V, A>>) {
+        let leaf = unsafe { leaf_ptr.as_mut().as_leaf_mut() };
+        let (prev, next) = (leaf.prev.take(), leaf.next.take());
+
+        if let Some(mut prev_ptr) = prev {
+            unsafe { prev_ptr.as_mut().as_leaf_mut().next = next };
+        }
+
+        if let Some(mut next_ptr) = next {
+            unsafe { next_ptr.as_mut().as_leaf_mut().prev = prev };
+        }
+    }
+
     pub fn size(&self) -> usize {
         self.data.len()
     }
@@ -65,21 +111,38 @@ where
         self.data.pop().unwrap()
     }
 
-    pub fn lmerge_into(&mut self, other: &mut Leaf<K, V>) {
+    pub fn lmerge_into(&mut self, other: &mut Leaf<K, V, A>) {
         self.data.append(&mut other.data); // TODO: Should just use a VecDeque
         swap(&mut self.data, &mut other.data);
+
+        // self is about to be freed, its right neighbour is other
+        other.prev = self.prev.take();
+        if let Some(mut prev_ptr) = other.prev {
+            unsafe { prev_ptr.as_mut().as_leaf_mut().next = self.next };
+        }
+        self.next = None;
     }
 
-    pub fn rmerge_into(&mut self, other: &mut Leaf<K, V>) {
+    pub fn rmerge_into(&mut self, other: &mut Leaf<K, V, A>) {
         other.data.append(&mut self.data);
+
+        // self is about to be freed, its left neighbour is other
+        other.next = self.next.take();
+        if let Some(mut next_ptr) = other.next {
+            unsafe { next_ptr.as_mut().as_leaf_mut().prev = self.prev };
+        }
+        self.prev = None;
     }
 
     pub fn is_root(&self) -> bool {
         self.parent.is_none()
     }
 
-    pub fn insert(&mut self, k: K, v: V) -> Option<V> {
-        let result = self.data.binary_search_by(|(key, _)| key.cmp(&k));
+    pub fn insert<C>(&mut self, k: K, v: V, cmp: &C) -> Option<V>
+    where
+        C: Comparator<K>,
+    {
+        let result = self.data.binary_search_by(|(key, _)| cmp.compare(key, &k));
         let mut pair = (k, v);
         match result {
             Ok(index) => {
@@ -93,8 +156,11 @@ where
         }
     }
 
-    pub fn remove(&mut self, k: &K) -> Option<V> {
-        let result = self.data.binary_search_by(|(key, _)| key.cmp(k));
+    pub fn remove<C>(&mut self, k: &K, cmp: &C) -> Option<V>
+    where
+        C: Comparator<K>,
+    {
+        let result = self.data.binary_search_by(|(key, _)| cmp.compare(key, k));
         match result {
             Ok(index) => {
                 let (k, v) = self.data.remove(index);
@@ -104,21 +170,49 @@ where
         }
     }
 
-    pub fn find(&self, k: &K) -> Option<&(K, V)> {
-        self.data.iter().find(|(key, _)| key == k)
-    }
-
-    pub fn find_mut(&mut self, k: &K) -> Option<&mut (K, V)> {
-        self.data.iter_mut().find(|(key, _)| key == k)
-    }
-
-    pub fn update_parent_smallest_key(&mut self) {
+    pub fn find_index<Q, C>(&self, k: &Q, cmp: &C) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        self.data
+            .binary_search_by(|(key, _)| cmp.compare(key.borrow(), k))
+            .ok()
+    }
+
+    pub fn find<Q, C>(&self, k: &Q, cmp: &C) -> Option<&(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let index = self.find_index(k, cmp)?;
+        Some(&self.data[index])
+    }
+
+    pub fn find_mut<Q, C>(&mut self, k: &Q, cmp: &C) -> Option<&mut (K, V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let index = self.find_index(k, cmp)?;
+        Some(&mut self.data[index])
+    }
+
+    pub fn update_parent_smallest_key<C>(&mut self, cmp: &C)
+    where
+        C: Comparator<K>,
+    {
         let current_smallest_key = self.smallest_key().clone();
         let mut current_parent = self.parent_mut();
         while let Some(parent) = current_parent {
-            let needs_updating = *parent.smallest_key() > current_smallest_key;
+            let needs_updating = cmp
+                .compare(parent.smallest_key(), &current_smallest_key)
+                .is_gt();
             if needs_updating {
-                let k = parent.find_key_mut_less_or_equal_to(&current_smallest_key);
+                let k = parent.find_key_mut_less_or_equal_to(&current_smallest_key, cmp);
                 *k = current_smallest_key.clone();
             } else {
                 break;
@@ -128,15 +222,15 @@ where
         }
     }
 
-    pub fn parent_raw(&self) -> Option<NonNull<Node<K, V>>> {
+    pub fn parent_raw(&self) -> Option<NonNull<Node<K, V, A>>> {
         self.parent
     }
 
-    pub fn parent(&self) -> Option<&Internal<K, V>> {
+    pub fn parent(&self) -> Option<&Internal<K, V, A>> {
         unsafe { Some(self.parent_raw()?.as_ref().as_internal()) }
     }
 
-    pub fn parent_mut(&mut self) -> Option<&mut Internal<K, V>> {
+    pub fn parent_mut(&mut self) -> Option<&mut Internal<K, V, A>> {
         unsafe { Some(self.parent_raw()?.as_mut().as_internal_mut()) }
     }
 }
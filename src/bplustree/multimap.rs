@@ -0,0 +1,217 @@
+use crate::bplustree::BPlusTree;
+use crate::bplustree::iter;
+use std::iter::{FusedIterator, Peekable};
+use std::ops::Bound;
+
+/// Keys paired with the sequence number of their insertion. Every entry has its own key in the
+/// underlying tree, so the separators route to the right leaf even when the values of a single
+/// key span several leaves.
+type SequencedKey<K> = (K, u64);
+
+/// An ordered map that keeps every value inserted for a key. Values of the same key are kept in
+/// insertion order.
+#[derive(Debug)]
+pub struct BPlusMultiMap<K, V>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    pub(crate) tree: BPlusTree<SequencedKey<K>, V>,
+    key_count: usize,
+    next_sequence: u64,
+}
+
+impl<K, V> BPlusMultiMap<K, V>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    pub fn new(order: usize) -> Self {
+        Self {
+            tree: BPlusTree::new(order),
+            key_count: 0,
+            next_sequence: 0,
+        }
+    }
+
+    /// Number of values across all keys
+    pub fn size(&self) -> usize {
+        self.tree.size()
+    }
+
+    /// Number of distinct keys
+    pub fn key_count(&self) -> usize {
+        self.key_count
+    }
+
+    /// Number of values stored for the key, counted from the link counts of the tree
+    pub fn count(&self, k: &K) -> usize {
+        self.tree
+            .count_range((k.clone(), 0)..=(k.clone(), u64::MAX))
+    }
+
+    pub fn contains(&self, k: &K) -> bool {
+        self.count(k) > 0
+    }
+
+    /// Adds the value after all values already stored for the key
+    pub fn insert(&mut self, k: K, v: V) {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+
+        if !self.contains(&k) {
+            self.key_count += 1;
+        }
+        self.tree.insert((k, sequence), v);
+    }
+
+    /// All values of the key, in insertion order
+    pub fn get_all(&self, k: &K) -> GetAll<'_, K, V> {
+        GetAll {
+            inner: self.tree.range((k.clone(), 0)..=(k.clone(), u64::MAX)),
+        }
+    }
+
+    /// The first value inserted for the key that is still stored
+    pub fn get_first(&self, k: &K) -> Option<&V> {
+        self.get_all(k).next()
+    }
+
+    /// Removes the oldest value of the key
+    pub fn remove_one(&mut self, k: &K) -> Option<V> {
+        let mut cursor = self.tree.lower_bound_mut(Bound::Included(&(k.clone(), 0)));
+        if cursor.key().is_none_or(|(key, _)| key != k) {
+            return None;
+        }
+
+        let (_, v) = cursor.remove_current()?;
+        if !self.contains(k) {
+            self.key_count -= 1;
+        }
+        Some(v)
+    }
+
+    /// Removes every value of the key, returning them in insertion order
+    pub fn remove_all(&mut self, k: &K) -> Vec<V> {
+        let mut removed = vec![];
+        let mut cursor = self.tree.lower_bound_mut(Bound::Included(&(k.clone(), 0)));
+        while cursor.key().is_some_and(|(key, _)| key == k) {
+            let (_, v) = cursor
+                .remove_current()
+                .expect("Cursor is pointing at an entry");
+            removed.push(v);
+        }
+
+        if !removed.is_empty() {
+            self.key_count -= 1;
+        }
+        removed
+    }
+
+    /// All entries ordered by key, values of the same key in insertion order
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            inner: self.tree.iter(),
+        }
+    }
+
+    /// Distinct keys with their number of values
+    pub fn counts(&self) -> Counts<'_, K, V> {
+        Counts {
+            inner: self.tree.iter().peekable(),
+        }
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a BPlusMultiMap<K, V>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct GetAll<'a, K, V> {
+    inner: iter::Range<'a, SequencedKey<K>, V>,
+}
+
+impl<'a, K, V> Iterator for GetAll<'a, K, V>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, v)| v)
+    }
+}
+
+impl<K, V> DoubleEndedIterator for GetAll<'_, K, V>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(_, v)| v)
+    }
+}
+
+impl<K, V> FusedIterator for GetAll<'_, K, V> where K: Ord + PartialOrd + Clone {}
+
+pub struct Counts<'a, K, V>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    inner: Peekable<iter::Iter<'a, SequencedKey<K>, V>>,
+}
+
+impl<'a, K, V> Iterator for Counts<'a, K, V>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    type Item = (&'a K, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let ((k, _), _) = self.inner.next()?;
+        let mut count = 1;
+        while self.inner.next_if(|((next, _), _)| next == k).is_some() {
+            count += 1;
+        }
+        Some((k, count))
+    }
+}
+
+impl<K, V> FusedIterator for Counts<'_, K, V> where K: Ord + PartialOrd + Clone {}
+
+pub struct Iter<'a, K, V> {
+    inner: iter::Iter<'a, SequencedKey<K>, V>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|((k, _), v)| (k, v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Iter<'_, K, V>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|((k, _), v)| (k, v))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> where K: Ord + PartialOrd + Clone {}
+
+impl<K, V> FusedIterator for Iter<'_, K, V> where K: Ord + PartialOrd + Clone {}
@@ -1,16 +1,27 @@
-use crate::bplustree::internal::Internal;
+use crate::bplustree::aggregate::Aggregate;
+use crate::bplustree::comparator::Comparator;
+use crate::bplustree::internal::{Internal, Link};
 use crate::bplustree::leaf::Leaf;
 use std::fmt::Debug;
 use std::ptr::NonNull;
 
+/// A leaf and an index into its entries
+pub(crate) type Position<K, V, A = ()> = (NonNull<Node<K, V, A>>, usize);
+
 #[derive(Debug)]
-pub(crate) enum NodeEntry<K, V> {
-    Internal((K, NonNull<Node<K, V>>)),
+pub(crate) enum NodeEntry<K, V, A = ()>
+where
+    A: Aggregate<K, V>,
+{
+    Internal(Link<K, V, A>),
     Leaf((K, V)),
 }
 
-impl<K, V> NodeEntry<K, V> {
-    pub(crate) fn new(k: K, v: NodeValue<K, V>) -> Self {
+impl<K, V, A> NodeEntry<K, V, A>
+where
+    A: Aggregate<K, V>,
+{
+    pub(crate) fn new(k: K, v: NodeValue<K, V, A>) -> Self {
         match v {
             NodeValue::Internal(v) => Self::Internal((k, v)),
             NodeValue::Leaf(v) => Self::Leaf((k, v)),
@@ -26,40 +37,47 @@ impl<K, V> NodeEntry<K, V> {
 }
 
 #[derive(Debug)]
-pub(crate) enum NodeValue<K, V> {
+pub(crate) enum NodeValue<K, V, A = ()>
+where
+    A: Aggregate<K, V>,
+{
     Leaf(V),
-    Internal(NonNull<Node<K, V>>),
+    Internal(NonNull<Node<K, V, A>>),
 }
 
 #[derive(Debug)]
-pub(crate) enum Node<K, V> {
-    Internal(Internal<K, V>),
-    Leaf(Leaf<K, V>),
+pub(crate) enum Node<K, V, A = ()>
+where
+    A: Aggregate<K, V>,
+{
+    Internal(Internal<K, V, A>),
+    Leaf(Leaf<K, V, A>),
 }
 
-impl<K, V> Node<K, V>
+impl<K, V, A> Node<K, V, A>
 where
-    K: Ord + PartialOrd + Clone,
+    K: Clone,
+    A: Aggregate<K, V>,
 {
-    pub(crate) fn parent_raw(&self) -> Option<NonNull<Node<K, V>>> {
+    pub(crate) fn parent_raw(&self) -> Option<NonNull<Node<K, V, A>>> {
         match self {
             Node::Internal(internal) => internal.parent_raw(),
             Node::Leaf(leaf) => leaf.parent_raw(),
         }
     }
 
-    pub(crate) fn parent(&self) -> Option<&Internal<K, V>> {
+    pub(crate) fn parent(&self) -> Option<&Internal<K, V, A>> {
         unsafe { Some(self.parent_raw()?.as_ref().as_internal()) }
     }
 
-    pub(crate) fn parent_mut(&mut self) -> Option<&mut Internal<K, V>> {
+    pub(crate) fn parent_mut(&mut self) -> Option<&mut Internal<K, V, A>> {
         unsafe { Some(self.parent_raw()?.as_mut().as_internal_mut()) }
     }
 
     /// SAFETY:
     ///  * ptr MUST NOT point to self
     ///  * ptr MUST NOT be dangling
-    pub(crate) unsafe fn set_parent(&mut self, parent: Option<NonNull<Node<K, V>>>) {
+    pub(crate) unsafe fn set_parent(&mut self, parent: Option<NonNull<Node<K, V, A>>>) {
         match self {
             Node::Internal(internal) => internal.parent = parent,
             Node::Leaf(leaf) => leaf.parent = parent,
@@ -94,7 +112,23 @@ where
         }
     }
 
-    pub(crate) fn as_internal(&self) -> &Internal<K, V> {
+    /// Number of entries in all leaves below and including this node
+    pub(crate) fn subtree_size(&self) -> usize {
+        match self {
+            Node::Internal(internal) => internal.subtree_size(),
+            Node::Leaf(leaf) => leaf.size(),
+        }
+    }
+
+    /// Summary of all entries in the leaves below and including this node
+    pub(crate) fn summary(&self, aggregate: &A) -> A::Summary {
+        match self {
+            Node::Internal(internal) => internal.summary(aggregate),
+            Node::Leaf(leaf) => aggregate.summarize(&leaf.data),
+        }
+    }
+
+    pub(crate) fn as_internal(&self) -> &Internal<K, V, A> {
         match self {
             Node::Internal(internal) => internal,
             Node::Leaf(_leaf) => {
@@ -103,7 +137,7 @@ where
         }
     }
 
-    pub(crate) fn as_internal_mut(&mut self) -> &mut Internal<K, V> {
+    pub(crate) fn as_internal_mut(&mut self) -> &mut Internal<K, V, A> {
         match self {
             Node::Internal(internal) => internal,
             Node::Leaf(_leaf) => {
@@ -112,7 +146,7 @@ where
         }
     }
 
-    pub(crate) fn as_leaf(&self) -> &Leaf<K, V> {
+    pub(crate) fn as_leaf(&self) -> &Leaf<K, V, A> {
         match self {
             Node::Internal(_internal) => {
                 panic!("Expected a Leaf node but got Internal")
@@ -121,7 +155,7 @@ where
         }
     }
 
-    pub(crate) fn as_leaf_mut(&mut self) -> &mut Leaf<K, V> {
+    pub(crate) fn as_leaf_mut(&mut self) -> &mut Leaf<K, V, A> {
         match self {
             Node::Internal(_internal) => {
                 panic!("Expected a Leaf node but got Internal")
@@ -130,9 +164,11 @@ where
         }
     }
 
-    pub(crate) fn insert_smallest_entry(&mut self, e: NodeEntry<K, V>) {
+    pub(crate) fn insert_smallest_entry(&mut self, e: NodeEntry<K, V, A>, aggregate: &A) {
         match (self, e) {
-            (Node::Internal(internal), NodeEntry::Internal(e)) => internal.insert_smallest_entry(e),
+            (Node::Internal(internal), NodeEntry::Internal(e)) => {
+                internal.insert_smallest_entry(e, aggregate)
+            }
             (Node::Leaf(leaf), NodeEntry::Leaf(e)) => leaf.insert_smallest_entry(e),
             (Node::Leaf(..), NodeEntry::Internal(..)) => {
                 panic!("Trying to insert Internal node entry into a Leaf!")
@@ -143,16 +179,18 @@ where
         }
     }
 
-    pub(crate) fn remove_smallest_entry(&mut self) -> NodeEntry<K, V> {
+    pub(crate) fn remove_smallest_entry(&mut self) -> NodeEntry<K, V, A> {
         match self {
             Node::Internal(internal) => NodeEntry::Internal(internal.remove_smallest_entry()),
             Node::Leaf(leaf) => NodeEntry::Leaf(leaf.remove_smallest_entry()),
         }
     }
 
-    pub(crate) fn insert_largest_entry(&mut self, e: NodeEntry<K, V>) {
+    pub(crate) fn insert_largest_entry(&mut self, e: NodeEntry<K, V, A>, aggregate: &A) {
         match (self, e) {
-            (Node::Internal(internal), NodeEntry::Internal(e)) => internal.insert_largest_entry(e),
+            (Node::Internal(internal), NodeEntry::Internal(e)) => {
+                internal.insert_largest_entry(e, aggregate)
+            }
             (Node::Leaf(leaf), NodeEntry::Leaf(e)) => leaf.insert_largest_entry(e),
             (Node::Leaf(..), NodeEntry::Internal(..)) => {
                 panic!("Trying to insert Internal node entry into a Leaf!")
@@ -163,21 +201,21 @@ where
         }
     }
 
-    pub(crate) fn remove_largest_entry(&mut self) -> NodeEntry<K, V> {
+    pub(crate) fn remove_largest_entry(&mut self) -> NodeEntry<K, V, A> {
         match self {
             Node::Internal(internal) => NodeEntry::Internal(internal.remove_largest_entry()),
             Node::Leaf(leaf) => NodeEntry::Leaf(leaf.remove_largest_entry()),
         }
     }
 
-    pub(crate) fn lmerge_into(&mut self, other: &mut Node<K, V>) {
+    pub(crate) fn lmerge_into(&mut self, other: &mut Node<K, V, A>) {
         match self {
             Node::Internal(internal) => internal.lmerge_into(other.as_internal_mut()),
             Node::Leaf(leaf) => leaf.lmerge_into(other.as_leaf_mut()),
         }
     }
 
-    pub(crate) fn rmerge_into(&mut self, other: &mut Node<K, V>) {
+    pub(crate) fn rmerge_into(&mut self, other: &mut Node<K, V, A>) {
         match self {
             Node::Internal(internal) => internal.rmerge_into(other.as_internal_mut()),
             Node::Leaf(leaf) => leaf.rmerge_into(other.as_leaf_mut()),
@@ -191,14 +229,17 @@ where
         }
     }
 
-    pub(crate) fn remove(&mut self, k: &K) -> Option<NodeValue<K, V>> {
+    pub(crate) fn remove<C>(&mut self, k: &K, cmp: &C) -> Option<NodeValue<K, V, A>>
+    where
+        C: Comparator<K>,
+    {
         match self {
             Node::Internal(internal) => {
-                let v = internal.remove(k)?;
+                let v = internal.remove(k, cmp)?;
                 Some(NodeValue::Internal(v))
             }
             Node::Leaf(leaf) => {
-                let v = leaf.remove(k)?;
+                let v = leaf.remove(k, cmp)?;
                 Some(NodeValue::Leaf(v))
             }
         }
@@ -0,0 +1,1084 @@
+use crate::bplustree::DEFAULT_ORDER;
+use crate::bplustree::paged::codec::{BinaryCodec, Codec, DecodeError};
+use crate::bplustree::paged::page::{
+    HEADER_SIZE, Header, InternalPage, LeafPage, MAX_PAGE_SIZE, NODE_HEADER_SIZE, PageNode,
+    decode_free, encode_free,
+};
+use crate::bplustree::paged::pager::Pager;
+use crate::bplustree::paged::pool::{BufferPool, Eviction, PoolStats};
+use crate::bplustree::paged::wal::{RecordKind, Wal};
+use std::borrow::Borrow;
+use std::cell::RefCell;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fmt::{Display, Formatter};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read};
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+use std::mem::replace;
+use std::ops::{Bound, RangeBounds};
+use std::path::{Path, PathBuf};
+
+pub mod codec;
+mod page;
+pub(crate) mod pager;
+pub mod pool;
+mod wal;
+
+/// Index of a page in the file, the page starts at `id * page_size`
+pub type PageId = u64;
+
+const DEFAULT_PAGE_SIZE: usize = 4096;
+
+const DEFAULT_POOL_CAPACITY: usize = 256;
+
+/// Smallest room an entry can get in a page, smaller pages or larger orders are rejected
+const MIN_ENTRY_SIZE: usize = 16;
+
+#[derive(Debug)]
+pub enum PagedError {
+    Io(io::Error),
+    /// A page holds bytes the codec can't read
+    Decode(DecodeError),
+    /// The file isn't a tree, or its pages don't link up
+    Corrupt(&'static str),
+    /// An encoded entry takes more than the `max` bytes a node leaves for each entry
+    EntryTooLarge {
+        size: usize,
+        max: usize,
+    },
+    /// The options can't make a tree
+    Options(&'static str),
+    /// Every page in the buffer pool is pinned, or dirty while there is a WAL, so none can be
+    /// evicted. With a WAL, an operation also fails with it up front if the pool is too small to
+    /// hold every page it could change.
+    PoolFull,
+}
+
+impl Display for PagedError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PagedError::Io(error) => write!(f, "io error: {error}"),
+            PagedError::Decode(error) => write!(f, "decode error: {error}"),
+            PagedError::Corrupt(reason) => write!(f, "corrupt file: {reason}"),
+            PagedError::EntryTooLarge { size, max } => {
+                write!(f, "entry takes {size} bytes, at most {max} fit in a node")
+            }
+            PagedError::Options(reason) => write!(f, "invalid options: {reason}"),
+            PagedError::PoolFull => write!(f, "no page in the buffer pool can be evicted"),
+        }
+    }
+}
+
+impl Error for PagedError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PagedError::Io(error) => Some(error),
+            PagedError::Decode(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PagedError {
+    fn from(error: io::Error) -> Self {
+        PagedError::Io(error)
+    }
+}
+
+impl From<DecodeError> for PagedError {
+    fn from(error: DecodeError) -> Self {
+        PagedError::Decode(error)
+    }
+}
+
+/// Shape of a new file and the buffer pool in front of it. An existing file keeps the order and
+/// page size it was created with.
+#[derive(Debug, Copy, Clone)]
+pub struct PagedOptions {
+    order: usize,
+    page_size: usize,
+    pool_capacity: usize,
+    eviction: Eviction,
+    wal: bool,
+}
+
+impl Default for PagedOptions {
+    fn default() -> Self {
+        Self {
+            order: DEFAULT_ORDER,
+            page_size: DEFAULT_PAGE_SIZE,
+            pool_capacity: DEFAULT_POOL_CAPACITY,
+            eviction: Eviction::default(),
+            wal: false,
+        }
+    }
+}
+
+impl PagedOptions {
+    pub fn order(mut self, order: usize) -> Self {
+        self.order = order;
+        self
+    }
+
+    pub fn page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    /// Pages kept in memory at most
+    pub fn pool_capacity(mut self, pool_capacity: usize) -> Self {
+        self.pool_capacity = pool_capacity;
+        self
+    }
+
+    pub fn eviction(mut self, eviction: Eviction) -> Self {
+        self.eviction = eviction;
+        self
+    }
+
+    /// Logs every insert and remove to `<path>.wal` before it's applied, see [`PagedBPlusTree`]
+    pub fn wal(mut self, wal: bool) -> Self {
+        self.wal = wal;
+        self
+    }
+}
+
+/// A BPlusTree whose nodes live in fixed size pages of a single file, child links are page ids.
+/// Keys and values are turned into bytes by the codec `D`.
+///
+/// Pages are cached in a [`BufferPool`], changed pages reach the file when they're evicted, and
+/// together with the header when the tree is flushed or dropped.
+///
+/// With [`PagedOptions::wal`], every insert and remove is appended to a write-ahead log and synced
+/// before it changes any page, and changed pages stay in memory until the next flush. The tree
+/// flushes before they could outgrow the pool, so replaying the log after a crash needs a pool at
+/// least as large as the one that wrote it. Opening the tree after a crash replays the log, so the
+/// tree holds every change whose call returned. Only
+/// PagedBPlusTree is logged and recovered, the in-memory [`BPlusTree`](crate::bplustree::BPlusTree)
+/// has no file to come back from.
+pub struct PagedBPlusTree<K, V, D = BinaryCodec> {
+    /// In a RefCell, as lookups through `&self` still pin pages and update the eviction order
+    pool: RefCell<BufferPool>,
+    header: Header,
+    /// In a RefCell too, so a flush through `&self` can checkpoint
+    wal: RefCell<Option<Wal>>,
+    codec: D,
+    _marker: PhantomData<(K, V)>,
+}
+
+/// What an insert into a subtree leaves for the parent to fix
+struct Inserted<K, V> {
+    old: Option<V>,
+    smallest: K,
+    /// Separator and page of a new right sibling
+    split: Option<(K, PageId)>,
+}
+
+impl<K, V, D> PagedBPlusTree<K, V, D>
+where
+    K: Ord + Clone,
+    D: Codec<K> + Codec<V>,
+{
+    /// Opens the tree stored at `path`, or creates an empty one if the file doesn't exist or is empty.
+    /// A log left at `<path>.wal` is replayed first, even if `options` don't enable the WAL.
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        options: PagedOptions,
+        codec: D,
+    ) -> Result<Self, PagedError> {
+        let mut tree = Self::open_file(path.as_ref(), options, codec)?;
+        tree.recover(&wal_path(path.as_ref()), options.wal)?;
+        Ok(tree)
+    }
+
+    fn open_file(path: &Path, options: PagedOptions, codec: D) -> Result<Self, PagedError> {
+        if options.pool_capacity == 0 {
+            return Err(PagedError::Options("pool capacity must be at least 1"));
+        }
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        if file.metadata()?.len() == 0 {
+            Self::check_options(options)?;
+            let header = Header {
+                page_size: options.page_size,
+                order: options.order,
+                root: None,
+                size: 0,
+                page_count: 1,
+                free_head: None,
+            };
+            let tree = Self::with_pool(file, header, options, codec);
+            tree.write_header()?;
+            tree.pool.borrow().pager().sync()?;
+            return Ok(tree);
+        }
+
+        let mut bytes = [0; HEADER_SIZE];
+        file.read_exact(&mut bytes)
+            .map_err(|_| PagedError::Corrupt("file is too short for a header"))?;
+        let header = Header::decode(&bytes)?;
+        Self::check_options(PagedOptions {
+            order: header.order,
+            page_size: header.page_size,
+            ..options
+        })
+        .map_err(|_| PagedError::Corrupt("header holds an invalid order or page size"))?;
+
+        Ok(Self::with_pool(file, header, options, codec))
+    }
+
+    fn with_pool(file: File, header: Header, options: PagedOptions, codec: D) -> Self {
+        let pager = Pager::new(file, header.page_size);
+        Self {
+            pool: RefCell::new(BufferPool::new(
+                pager,
+                header.page_size,
+                options.pool_capacity,
+                options.eviction,
+            )),
+            header,
+            wal: RefCell::new(None),
+            codec,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Brings the data file up to date with the log and starts a new one if `enabled`, or removes it
+    fn recover(&mut self, path: &Path, enabled: bool) -> Result<(), PagedError> {
+        if !enabled && !path.exists() {
+            return Ok(());
+        }
+
+        let mut wal = Wal::open(path)?;
+        let mut records = wal.read_records()?;
+        if let Some(end) = records
+            .iter()
+            .rposition(|record| record.kind == RecordKind::Checkpoint)
+        {
+            // The last checkpoint logged its pages in full, but they may not all have reached the
+            // data file before the log was truncated
+            let pool = self.pool.borrow();
+            for record in records[..end]
+                .iter()
+                .filter(|record| record.kind == RecordKind::Page)
+            {
+                let (id, page) = record
+                    .body
+                    .split_first_chunk()
+                    .ok_or(PagedError::Corrupt("page record is too short"))?;
+                if page.len() != self.header.page_size {
+                    return Err(PagedError::Corrupt("page record has the wrong size"));
+                }
+                pool.pager().write(PageId::from_le_bytes(*id), page)?;
+            }
+            drop(pool);
+
+            self.header = Header::decode(&records[end].body)?;
+            records.drain(..=end);
+        }
+
+        self.pool.get_mut().set_steal(false);
+        for record in records {
+            let bytes = &mut record.body.as_slice();
+            match record.kind {
+                RecordKind::Insert => {
+                    let (k, v) = (self.codec.decode(bytes)?, self.codec.decode(bytes)?);
+                    self.apply_insert(k, v)?;
+                }
+                RecordKind::Remove => {
+                    let k: K = self.codec.decode(bytes)?;
+                    self.apply_remove(&k)?;
+                }
+                // Pages of a checkpoint that didn't finish, the data file wasn't touched yet
+                RecordKind::Page | RecordKind::Checkpoint => {}
+            }
+        }
+
+        *self.wal.get_mut() = Some(wal);
+        self.checkpoint()?;
+        if !enabled {
+            *self.wal.get_mut() = None;
+            self.pool.get_mut().set_steal(true);
+            fs::remove_file(path)?;
+        }
+
+        Ok(())
+    }
+
+    fn check_options(options: PagedOptions) -> Result<(), PagedError> {
+        if options.order <= 2 {
+            return Err(PagedError::Options("order must be at least 3"));
+        }
+
+        if options.page_size > MAX_PAGE_SIZE {
+            return Err(PagedError::Options("page size must be at most 16 MiB"));
+        }
+
+        if options.page_size < HEADER_SIZE + NODE_HEADER_SIZE
+            || (options.page_size - NODE_HEADER_SIZE) / options.order < MIN_ENTRY_SIZE
+        {
+            return Err(PagedError::Options(
+                "page size leaves less than 16 bytes for each entry of a node",
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn size(&self) -> usize {
+        self.header.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.header.size == 0
+    }
+
+    pub fn order(&self) -> usize {
+        self.header.order
+    }
+
+    pub fn page_size(&self) -> usize {
+        self.header.page_size
+    }
+
+    pub fn max_node_size(&self) -> usize {
+        self.header.order
+    }
+
+    pub fn min_node_size(&self) -> usize {
+        self.header.order.div_ceil(2)
+    }
+
+    /// Bytes a node leaves for each of its entries, the encoded key and value of a leaf entry, and
+    /// the encoded key plus a page id of an internal link have to fit in it
+    pub fn max_entry_size(&self) -> usize {
+        (self.header.page_size - NODE_HEADER_SIZE) / self.header.order
+    }
+
+    pub fn pool_capacity(&self) -> usize {
+        self.pool.borrow().capacity()
+    }
+
+    pub fn eviction(&self) -> Eviction {
+        self.pool.borrow().eviction()
+    }
+
+    /// Hits, misses and evictions of the buffer pool since the tree was opened
+    pub fn pool_stats(&self) -> PoolStats {
+        self.pool.borrow().stats()
+    }
+
+    /// Pages held in the buffer pool
+    pub fn cached_pages(&self) -> usize {
+        self.pool.borrow().len()
+    }
+
+    pub fn find<Q>(&self, k: &Q) -> Result<Option<V>, PagedError>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        Ok(self.find_entry(k)?.map(|(_, v)| v))
+    }
+
+    fn find_entry<Q>(&self, k: &Q) -> Result<Option<(K, V)>, PagedError>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let Some(mut id) = self.header.root else {
+            return Ok(None);
+        };
+
+        loop {
+            match self.read_node(id)? {
+                PageNode::Internal(internal) => id = internal.links[internal.child_index(k)].1,
+                PageNode::Leaf(mut leaf) => {
+                    let found = leaf
+                        .entries
+                        .binary_search_by(|(key, _)| key.borrow().cmp(k));
+                    return Ok(found.ok().map(|index| leaf.entries.swap_remove(index)));
+                }
+            }
+        }
+    }
+
+    pub fn contains<Q>(&self, k: &Q) -> Result<bool, PagedError>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        Ok(self.find(k)?.is_some())
+    }
+
+    pub fn insert(&mut self, k: K, v: V) -> Result<Option<V>, PagedError> {
+        let entry = self.encode_entry(&k, &v)?;
+        self.log(RecordKind::Insert, &entry)?;
+        self.apply_insert(k, v)
+    }
+
+    fn apply_insert(&mut self, k: K, v: V) -> Result<Option<V>, PagedError> {
+        let Some(root) = self.header.root else {
+            let id = self.allocate()?;
+            let leaf = LeafPage {
+                next: None,
+                entries: vec![(k, v)],
+            };
+            self.write_node(id, &PageNode::Leaf(leaf))?;
+            self.header.root = Some(id);
+            self.header.size = 1;
+            return Ok(None);
+        };
+
+        let inserted = self.insert_into(root, k, v)?;
+        if let Some(split) = inserted.split {
+            let new_root = self.allocate()?;
+            let internal = InternalPage {
+                links: vec![(inserted.smallest, root), split],
+            };
+            self.write_node(new_root, &PageNode::Internal(internal))?;
+            self.header.root = Some(new_root);
+        }
+
+        if inserted.old.is_none() {
+            self.header.size += 1;
+        }
+        Ok(inserted.old)
+    }
+
+    /// Encodes the key followed by the value, fails if the entry wouldn't fit in a node
+    fn encode_entry(&self, k: &K, v: &V) -> Result<Vec<u8>, PagedError> {
+        let mut out = Vec::new();
+        self.codec.encode(k, &mut out);
+        let key_size = out.len();
+        self.codec.encode(v, &mut out);
+
+        let size = out.len().max(key_size + size_of::<PageId>());
+        let max = self.max_entry_size();
+        if size > max {
+            return Err(PagedError::EntryTooLarge { size, max });
+        }
+
+        Ok(out)
+    }
+
+    /// Appends a record to the WAL, if there is one. A checkpoint comes first once the changed pages
+    /// fill half of the pool, the log grew as large as the pool, or the operation might change more
+    /// pages than the pool has room for. Dirty pages can't be evicted with a WAL, so an operation
+    /// that wouldn't fit even in an empty pool fails before anything is logged.
+    fn log(&mut self, kind: RecordKind, body: &[u8]) -> Result<(), PagedError> {
+        let Some(wal) = self.wal.get_mut() else {
+            return Ok(());
+        };
+
+        let pool = self.pool.borrow();
+        let full = pool.dirty_count() * 2 >= pool.capacity()
+            || wal.len() >= (pool.capacity() * self.header.page_size) as u64;
+        drop(pool);
+        if full {
+            self.checkpoint()?;
+        }
+
+        // An insert or remove writes at most two pages on each level, and one for a new root
+        let pages = 2 * self.height()? + 1;
+        let pool = self.pool.borrow();
+        let (dirty, capacity) = (pool.dirty_count(), pool.capacity());
+        drop(pool);
+        if pages > capacity {
+            return Err(PagedError::PoolFull);
+        }
+        if dirty + pages > capacity {
+            self.checkpoint()?;
+        }
+
+        let wal = self.wal.get_mut().as_mut().expect("WAL was checked above");
+        wal.append(kind, body)?;
+        Ok(())
+    }
+
+    fn insert_into(&mut self, id: PageId, k: K, v: V) -> Result<Inserted<K, V>, PagedError> {
+        match self.read_node(id)? {
+            PageNode::Leaf(mut leaf) => {
+                let old = match leaf.entries.binary_search_by(|(key, _)| key.cmp(&k)) {
+                    Ok(index) => Some(replace(&mut leaf.entries[index], (k, v)).1),
+                    Err(index) => {
+                        leaf.entries.insert(index, (k, v));
+                        None
+                    }
+                };
+
+                let split = if leaf.entries.len() > self.max_node_size() {
+                    let right_id = self.allocate()?;
+                    let right = LeafPage {
+                        next: leaf.next.replace(right_id),
+                        entries: leaf.entries.split_off(leaf.entries.len() / 2),
+                    };
+                    let separator = right.entries[0].0.clone();
+                    self.write_node(right_id, &PageNode::Leaf(right))?;
+                    Some((separator, right_id))
+                } else {
+                    None
+                };
+
+                let node = PageNode::Leaf(leaf);
+                let smallest = node.smallest_key().clone();
+                self.write_node(id, &node)?;
+                Ok(Inserted {
+                    old,
+                    smallest,
+                    split,
+                })
+            }
+            PageNode::Internal(mut internal) => {
+                let index = internal.child_index(&k);
+                let inserted = self.insert_into(internal.links[index].1, k, v)?;
+                internal.links[index].0 = inserted.smallest;
+                if let Some(split) = inserted.split {
+                    internal.links.insert(index + 1, split);
+                }
+
+                let split = if internal.links.len() > self.max_node_size() {
+                    let right_id = self.allocate()?;
+                    let right = InternalPage {
+                        links: internal.links.split_off(internal.links.len() / 2),
+                    };
+                    let separator = right.links[0].0.clone();
+                    self.write_node(right_id, &PageNode::Internal(right))?;
+                    Some((separator, right_id))
+                } else {
+                    None
+                };
+
+                let node = PageNode::Internal(internal);
+                let smallest = node.smallest_key().clone();
+                self.write_node(id, &node)?;
+                Ok(Inserted {
+                    old: inserted.old,
+                    smallest,
+                    split,
+                })
+            }
+        }
+    }
+
+    pub fn remove<Q>(&mut self, k: &Q) -> Result<Option<V>, PagedError>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        if self.wal.get_mut().is_some() {
+            // The log holds whole keys, and nothing needs to be logged if k isn't there
+            let Some((key, _)) = self.find_entry(k)? else {
+                return Ok(None);
+            };
+
+            let mut body = Vec::new();
+            self.codec.encode(&key, &mut body);
+            self.log(RecordKind::Remove, &body)?;
+        }
+
+        self.apply_remove(k)
+    }
+
+    fn apply_remove<Q>(&mut self, k: &Q) -> Result<Option<V>, PagedError>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let Some(root) = self.header.root else {
+            return Ok(None);
+        };
+
+        let Some(v) = self.remove_from(root, k)? else {
+            return Ok(None);
+        };
+        self.header.size -= 1;
+
+        match self.read_node(root)? {
+            PageNode::Leaf(leaf) if leaf.entries.is_empty() => {
+                self.free(root)?;
+                self.header.root = None;
+            }
+            PageNode::Internal(internal) if internal.links.len() == 1 => {
+                self.free(root)?;
+                self.header.root = Some(internal.links[0].1);
+            }
+            _ => {}
+        }
+
+        Ok(Some(v))
+    }
+
+    fn remove_from<Q>(&mut self, id: PageId, k: &Q) -> Result<Option<V>, PagedError>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        match self.read_node(id)? {
+            PageNode::Leaf(mut leaf) => {
+                let Ok(index) = leaf
+                    .entries
+                    .binary_search_by(|(key, _)| key.borrow().cmp(k))
+                else {
+                    return Ok(None);
+                };
+
+                let (_, v) = leaf.entries.remove(index);
+                self.write_node(id, &PageNode::Leaf(leaf))?;
+                Ok(Some(v))
+            }
+            PageNode::Internal(mut internal) => {
+                let index = internal.child_index(k);
+                let Some(v) = self.remove_from(internal.links[index].1, k)? else {
+                    return Ok(None);
+                };
+
+                self.fix_child(&mut internal, index)?;
+                self.write_node(id, &PageNode::Internal(internal))?;
+                Ok(Some(v))
+            }
+        }
+    }
+
+    /// Updates the link to the child at `index` after a remove, an underfull child takes an entry
+    /// from a sibling that can spare one, or is merged with it
+    fn fix_child(
+        &mut self,
+        internal: &mut InternalPage<K>,
+        index: usize,
+    ) -> Result<(), PagedError> {
+        let child_id = internal.links[index].1;
+        let child = self.read_node(child_id)?;
+        internal.links[index].0 = child.smallest_key().clone();
+        if child.size() >= self.min_node_size() {
+            return Ok(());
+        }
+
+        let (left_index, mut left, mut right) = if index > 0 {
+            (
+                index - 1,
+                self.read_node(internal.links[index - 1].1)?,
+                child,
+            )
+        } else {
+            (index, child, self.read_node(internal.links[index + 1].1)?)
+        };
+        let (left_id, right_id) = (
+            internal.links[left_index].1,
+            internal.links[left_index + 1].1,
+        );
+
+        if left.size().max(right.size()) > self.min_node_size() {
+            Self::transfer(&mut left, &mut right);
+            internal.links[left_index + 1].0 = right.smallest_key().clone();
+            self.write_node(right_id, &right)?;
+        } else {
+            Self::merge(&mut left, right);
+            internal.links.remove(left_index + 1);
+            self.free(right_id)?;
+        }
+
+        internal.links[left_index].0 = left.smallest_key().clone();
+        self.write_node(left_id, &left)
+    }
+
+    /// Moves one entry from the larger of two neighbouring nodes to the smaller one
+    fn transfer(left: &mut PageNode<K, V>, right: &mut PageNode<K, V>) {
+        let to_right = left.size() > right.size();
+        match (left, right) {
+            (PageNode::Leaf(left), PageNode::Leaf(right)) => {
+                if to_right {
+                    let entry = left.entries.pop().expect("Left MUST NOT be empty");
+                    right.entries.insert(0, entry);
+                } else {
+                    left.entries.push(right.entries.remove(0));
+                }
+            }
+            (PageNode::Internal(left), PageNode::Internal(right)) => {
+                if to_right {
+                    let link = left.links.pop().expect("Left MUST NOT be empty");
+                    right.links.insert(0, link);
+                } else {
+                    left.links.push(right.links.remove(0));
+                }
+            }
+            _ => unreachable!("Siblings MUST be on the same level"),
+        }
+    }
+
+    /// Moves everything from right into left, right's page has to be freed afterwards
+    fn merge(left: &mut PageNode<K, V>, right: PageNode<K, V>) {
+        match (left, right) {
+            (PageNode::Leaf(left), PageNode::Leaf(right)) => {
+                left.entries.extend(right.entries);
+                left.next = right.next;
+            }
+            (PageNode::Internal(left), PageNode::Internal(right)) => {
+                left.links.extend(right.links);
+            }
+            _ => unreachable!("Siblings MUST be on the same level"),
+        }
+    }
+
+    /// Entries with keys in `range`, in key order. Leaves are read one at a time as the iterator
+    /// reaches them.
+    pub fn range<R>(&self, range: R) -> Result<PagedRange<'_, K, V, D>, PagedError>
+    where
+        R: RangeBounds<K>,
+    {
+        match (range.start_bound(), range.end_bound()) {
+            (Bound::Excluded(s), Bound::Excluded(e)) if s == e => {
+                panic!("range start and end are equal and excluded in BPlusTree")
+            }
+            (Bound::Included(s) | Bound::Excluded(s), Bound::Included(e) | Bound::Excluded(e))
+                if s > e =>
+            {
+                panic!("range start is greater than range end in BPlusTree")
+            }
+            _ => {}
+        }
+
+        let mut iter = PagedRange {
+            tree: self,
+            entries: Vec::new().into_iter(),
+            next: None,
+            end: range.end_bound().cloned(),
+            done: false,
+        };
+
+        let Some(mut id) = self.header.root else {
+            iter.done = true;
+            return Ok(iter);
+        };
+
+        let leaf = loop {
+            match self.read_node(id)? {
+                PageNode::Internal(internal) => {
+                    id = match range.start_bound() {
+                        Bound::Included(k) | Bound::Excluded(k) => {
+                            internal.links[internal.child_index(k)].1
+                        }
+                        Bound::Unbounded => internal.links[0].1,
+                    }
+                }
+                PageNode::Leaf(leaf) => break leaf,
+            }
+        };
+
+        let start = match range.start_bound() {
+            Bound::Included(k) => leaf.entries.partition_point(|(key, _)| key < k),
+            Bound::Excluded(k) => leaf.entries.partition_point(|(key, _)| key <= k),
+            Bound::Unbounded => 0,
+        };
+
+        let mut entries = leaf.entries;
+        entries.drain(..start);
+        iter.entries = entries.into_iter();
+        iter.next = leaf.next;
+        Ok(iter)
+    }
+
+    pub fn iter(&self) -> Result<PagedRange<'_, K, V, D>, PagedError> {
+        self.range(..)
+    }
+
+    /// Walks every page and checks that the nodes are sorted, sized and linked like the
+    /// in-memory tree, and that every page is either reachable or on the free list
+    pub fn verify(&self) -> Result<(), PagedError> {
+        let mut leaves = Vec::new();
+        let mut pages = 1;
+        let mut entries = 0;
+
+        if let Some(root) = self.header.root {
+            let mut level = vec![root];
+            loop {
+                pages += level.len() as u64;
+                let mut below = Vec::new();
+                for &id in &level {
+                    let node = self.read_node(id)?;
+                    let is_root = id == root;
+                    if node.size() > self.max_node_size()
+                        || (!is_root && node.size() < self.min_node_size())
+                        || node.size() == 0
+                    {
+                        return Err(PagedError::Corrupt("node has too many or too few entries"));
+                    }
+
+                    match node {
+                        PageNode::Internal(internal) => {
+                            if is_root && internal.links.len() < 2 {
+                                return Err(PagedError::Corrupt(
+                                    "internal root has a single child",
+                                ));
+                            }
+                            if !internal.links.is_sorted_by(|(a, _), (b, _)| a < b) {
+                                return Err(PagedError::Corrupt("links are not sorted"));
+                            }
+                            for (k, child) in &internal.links {
+                                if self.read_node(*child)?.smallest_key() != k {
+                                    return Err(PagedError::Corrupt(
+                                        "link doesn't hold the smallest key of its child",
+                                    ));
+                                }
+                                below.push(*child);
+                            }
+                        }
+                        PageNode::Leaf(leaf) => {
+                            if !leaf.entries.is_sorted_by(|(a, _), (b, _)| a < b) {
+                                return Err(PagedError::Corrupt("entries are not sorted"));
+                            }
+                            entries += leaf.entries.len();
+                            leaves.push((id, leaf));
+                        }
+                    }
+                }
+
+                if !leaves.is_empty() && !below.is_empty() {
+                    return Err(PagedError::Corrupt("leaves are not all on the same level"));
+                }
+                if below.is_empty() {
+                    break;
+                }
+                level = below;
+            }
+        }
+
+        for (index, (_, leaf)) in leaves.iter().enumerate() {
+            if leaf.next != leaves.get(index + 1).map(|(id, _)| *id) {
+                return Err(PagedError::Corrupt("leaves are not linked in key order"));
+            }
+            if let Some((_, next)) = leaves.get(index + 1)
+                && leaf.entries.last().map(|(k, _)| k) >= next.entries.first().map(|(k, _)| k)
+            {
+                return Err(PagedError::Corrupt("leaves overlap"));
+            }
+        }
+
+        if entries != self.header.size {
+            return Err(PagedError::Corrupt("header size doesn't match the entries"));
+        }
+
+        let mut free = self.header.free_head;
+        while let Some(id) = free {
+            pages += 1;
+            if pages > self.header.page_count {
+                return Err(PagedError::Corrupt("free list is longer than the file"));
+            }
+            free = self.read_free(id)?;
+        }
+
+        if pages != self.header.page_count {
+            return Err(PagedError::Corrupt("pages are neither reachable nor free"));
+        }
+
+        Ok(())
+    }
+
+    fn read_node(&self, id: PageId) -> Result<PageNode<K, V>, PagedError> {
+        self.read_page(id, |page| {
+            PageNode::decode(&self.codec, self.header.order, page)
+        })
+    }
+
+    fn write_node(&self, id: PageId, node: &PageNode<K, V>) -> Result<(), PagedError> {
+        let mut pool = self.pool.borrow_mut();
+        node.encode(&self.codec, pool.pin_for_write(id)?);
+        pool.unpin(id, true);
+        Ok(())
+    }
+
+    fn read_free(&self, id: PageId) -> Result<Option<PageId>, PagedError> {
+        self.read_page(id, decode_free)
+    }
+
+    /// Pins the page for as long as `f` reads it
+    fn read_page<T, F>(&self, id: PageId, f: F) -> Result<T, PagedError>
+    where
+        F: FnOnce(&[u8]) -> Result<T, PagedError>,
+    {
+        if id == 0 || id >= self.header.page_count {
+            return Err(PagedError::Corrupt("link to a page outside of the file"));
+        }
+
+        let mut pool = self.pool.borrow_mut();
+        let read = f(pool.pin(id)?);
+        pool.unpin(id, false);
+        read
+    }
+
+    /// Levels of the tree, found by following the first link of every internal node
+    fn height(&self) -> Result<usize, PagedError> {
+        let mut height = 0;
+        let mut current = self.header.root;
+        while let Some(id) = current {
+            height += 1;
+            current = match self.read_node(id)? {
+                PageNode::Internal(internal) => internal.links.first().map(|&(_, id)| id),
+                PageNode::Leaf(_) => None,
+            };
+        }
+        Ok(height)
+    }
+
+    /// Takes a page off the free list, or grows the file by one page
+    fn allocate(&mut self) -> Result<PageId, PagedError> {
+        if let Some(id) = self.header.free_head {
+            self.header.free_head = self.read_free(id)?;
+            return Ok(id);
+        }
+
+        let id = self.header.page_count;
+        self.header.page_count += 1;
+        Ok(id)
+    }
+
+    fn free(&mut self, id: PageId) -> Result<(), PagedError> {
+        let mut pool = self.pool.borrow_mut();
+        encode_free(self.header.free_head, pool.pin_for_write(id)?);
+        pool.unpin(id, true);
+        self.header.free_head = Some(id);
+        Ok(())
+    }
+}
+
+impl<K, V, D> PagedBPlusTree<K, V, D> {
+    /// Writes the changed pages and the header to the file, and forces them onto the disk. With a
+    /// WAL this is a checkpoint, after which the log is empty.
+    pub fn flush(&self) -> Result<(), PagedError> {
+        self.checkpoint()
+    }
+
+    /// With a WAL, the pages and the header are logged in full first, and the log is truncated once
+    /// they're in the file. Opening the tree after a crash in between writes them again.
+    pub(crate) fn checkpoint(&self) -> Result<(), PagedError> {
+        self.log_checkpoint()?;
+        self.pool.borrow_mut().flush()?;
+        self.write_header()?;
+        self.pool.borrow().pager().sync()?;
+
+        if let Some(wal) = self.wal.borrow_mut().as_mut() {
+            wal.truncate()?;
+        }
+        Ok(())
+    }
+
+    /// First half of a checkpoint, logs the changed pages and the header
+    pub(crate) fn log_checkpoint(&self) -> Result<(), PagedError> {
+        let mut wal = self.wal.borrow_mut();
+        let Some(wal) = wal.as_mut() else {
+            return Ok(());
+        };
+
+        let pool = self.pool.borrow();
+        for (id, page) in pool.dirty_pages() {
+            let mut body = id.to_le_bytes().to_vec();
+            body.extend_from_slice(page);
+            wal.write(RecordKind::Page, &body)?;
+        }
+
+        let mut header = vec![0; HEADER_SIZE];
+        self.header.encode(&mut header);
+        wal.append(RecordKind::Checkpoint, &header)?;
+        Ok(())
+    }
+
+    /// Bytes in the write-ahead log, 0 without one
+    pub fn wal_len(&self) -> u64 {
+        self.wal.borrow().as_ref().map_or(0, Wal::len)
+    }
+
+    fn write_header(&self) -> Result<(), PagedError> {
+        let mut page = vec![0; self.header.page_size];
+        self.header.encode(&mut page);
+        self.pool.borrow().pager().write(0, &page)?;
+        Ok(())
+    }
+}
+
+/// Errors can't be returned from drop, call [`PagedBPlusTree::flush`] to see them
+impl<K, V, D> Drop for PagedBPlusTree<K, V, D> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// The log of the tree at `path` sits next to it
+fn wal_path(path: &Path) -> PathBuf {
+    let mut wal_path = OsString::from(path);
+    wal_path.push(".wal");
+    PathBuf::from(wal_path)
+}
+
+/// Entries of a [`PagedBPlusTree`] in key order, see [`PagedBPlusTree::range`]
+pub struct PagedRange<'a, K, V, D> {
+    tree: &'a PagedBPlusTree<K, V, D>,
+    /// Rest of the current leaf
+    entries: std::vec::IntoIter<(K, V)>,
+    next: Option<PageId>,
+    end: Bound<K>,
+    done: bool,
+}
+
+impl<K, V, D> Iterator for PagedRange<'_, K, V, D>
+where
+    K: Ord + Clone,
+    D: Codec<K> + Codec<V>,
+{
+    type Item = Result<(K, V), PagedError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.done {
+                return None;
+            }
+
+            if let Some((k, v)) = self.entries.next() {
+                let past_end = match &self.end {
+                    Bound::Included(end) => k > *end,
+                    Bound::Excluded(end) => k >= *end,
+                    Bound::Unbounded => false,
+                };
+                if past_end {
+                    self.done = true;
+                    return None;
+                }
+                return Some(Ok((k, v)));
+            }
+
+            let Some(id) = self.next else {
+                self.done = true;
+                return None;
+            };
+
+            match self.tree.read_node(id) {
+                Ok(PageNode::Leaf(leaf)) => {
+                    self.entries = leaf.entries.into_iter();
+                    self.next = leaf.next;
+                }
+                Ok(PageNode::Internal(_)) => {
+                    self.done = true;
+                    return Some(Err(PagedError::Corrupt("leaf links to an internal node")));
+                }
+                Err(error) => {
+                    self.done = true;
+                    return Some(Err(error));
+                }
+            }
+        }
+    }
+}
+
+impl<K, V, D> FusedIterator for PagedRange<'_, K, V, D>
+where
+    K: Ord + Clone,
+    D: Codec<K> + Codec<V>,
+{
+}
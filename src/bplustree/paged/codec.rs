@@ -0,0 +1,114 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+/// Turns keys and values into bytes for the pages of a
+/// [`PagedBPlusTree`](crate::bplustree::paged::PagedBPlusTree) and back.
+///
+/// Encoded values are written back to back, so `decode` has to know where a value ends on its own,
+/// for example through a fixed size or a length prefix. A tree uses one codec for its keys and its
+/// values, so the codec implements this trait for both.
+pub trait Codec<T> {
+    fn encode(&self, value: &T, out: &mut Vec<u8>);
+
+    /// Reads a value from the start of `bytes` and advances `bytes` past it
+    fn decode(&self, bytes: &mut &[u8]) -> Result<T, DecodeError>;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The bytes ended in the middle of a value
+    UnexpectedEnd,
+    /// The bytes don't hold a valid value
+    Invalid(&'static str),
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::UnexpectedEnd => write!(f, "bytes ended in the middle of a value"),
+            DecodeError::Invalid(reason) => write!(f, "invalid value: {reason}"),
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+/// Splits off the first `n` bytes
+pub(crate) fn take<'a>(bytes: &mut &'a [u8], n: usize) -> Result<&'a [u8], DecodeError> {
+    if bytes.len() < n {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+
+    let (taken, rest) = bytes.split_at(n);
+    *bytes = rest;
+    Ok(taken)
+}
+
+/// Little endian integers, length prefixed strings and byte vectors, and tuples of those
+#[derive(Debug, Copy, Clone, Default)]
+pub struct BinaryCodec;
+
+macro_rules! impl_integer_codec {
+    ($($t:ty),*) => {
+        $(
+            impl Codec<$t> for BinaryCodec {
+                fn encode(&self, value: &$t, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&value.to_le_bytes());
+                }
+
+                fn decode(&self, bytes: &mut &[u8]) -> Result<$t, DecodeError> {
+                    let taken = take(bytes, size_of::<$t>())?;
+                    Ok(<$t>::from_le_bytes(taken.try_into().expect("Exactly the size of the integer was taken")))
+                }
+            }
+        )*
+    };
+}
+
+impl_integer_codec!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+impl Codec<Vec<u8>> for BinaryCodec {
+    fn encode(&self, value: &Vec<u8>, out: &mut Vec<u8>) {
+        self.encode(&(value.len() as u32), out);
+        out.extend_from_slice(value);
+    }
+
+    fn decode(&self, bytes: &mut &[u8]) -> Result<Vec<u8>, DecodeError> {
+        let len: u32 = self.decode(bytes)?;
+        Ok(take(bytes, len as usize)?.to_vec())
+    }
+}
+
+impl Codec<String> for BinaryCodec {
+    fn encode(&self, value: &String, out: &mut Vec<u8>) {
+        self.encode(&(value.len() as u32), out);
+        out.extend_from_slice(value.as_bytes());
+    }
+
+    fn decode(&self, bytes: &mut &[u8]) -> Result<String, DecodeError> {
+        let bytes: Vec<u8> = self.decode(bytes)?;
+        String::from_utf8(bytes).map_err(|_| DecodeError::Invalid("string is not UTF-8"))
+    }
+}
+
+impl Codec<()> for BinaryCodec {
+    fn encode(&self, _: &(), _: &mut Vec<u8>) {}
+
+    fn decode(&self, _: &mut &[u8]) -> Result<(), DecodeError> {
+        Ok(())
+    }
+}
+
+impl<A, B> Codec<(A, B)> for BinaryCodec
+where
+    BinaryCodec: Codec<A> + Codec<B>,
+{
+    fn encode(&self, (a, b): &(A, B), out: &mut Vec<u8>) {
+        self.encode(a, out);
+        self.encode(b, out);
+    }
+
+    fn decode(&self, bytes: &mut &[u8]) -> Result<(A, B), DecodeError> {
+        Ok((self.decode(bytes)?, self.decode(bytes)?))
+    }
+}
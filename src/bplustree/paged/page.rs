@@ -0,0 +1,223 @@
+use crate::bplustree::paged::codec::{Codec, DecodeError, take};
+use crate::bplustree::paged::{PageId, PagedError};
+use std::borrow::Borrow;
+
+/// Identifies the file as a tree, the last byte is the version of the format
+const MAGIC: [u8; 8] = *b"BPTREE\0\x01";
+
+/// Bytes of the header in page 0, the rest of the page is unused
+pub(crate) const HEADER_SIZE: usize = 56;
+
+/// Kind and entry count of every node page, plus the next leaf of a Leaf
+pub(crate) const NODE_HEADER_SIZE: usize = 13;
+
+/// Largest page size a file can have, so a corrupt header can't ask for a huge page buffer
+pub(crate) const MAX_PAGE_SIZE: usize = 1 << 24;
+
+const LEAF: u8 = 1;
+const INTERNAL: u8 = 2;
+const FREE: u8 = 3;
+
+/// Page 0 of the file, everything needed to find the nodes again
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct Header {
+    pub(crate) page_size: usize,
+    pub(crate) order: usize,
+    pub(crate) root: Option<PageId>,
+    pub(crate) size: usize,
+    /// Pages in the file, including the header
+    pub(crate) page_count: u64,
+    /// First page of the list of freed pages
+    pub(crate) free_head: Option<PageId>,
+}
+
+impl Header {
+    pub(crate) fn encode(&self, page: &mut [u8]) {
+        let mut out = Vec::with_capacity(HEADER_SIZE);
+        out.extend_from_slice(&MAGIC);
+        out.extend_from_slice(&(self.page_size as u64).to_le_bytes());
+        out.extend_from_slice(&(self.order as u64).to_le_bytes());
+        out.extend_from_slice(&self.root.unwrap_or(0).to_le_bytes());
+        out.extend_from_slice(&(self.size as u64).to_le_bytes());
+        out.extend_from_slice(&self.page_count.to_le_bytes());
+        out.extend_from_slice(&self.free_head.unwrap_or(0).to_le_bytes());
+
+        page.fill(0);
+        page[..HEADER_SIZE].copy_from_slice(&out);
+    }
+
+    pub(crate) fn decode(mut bytes: &[u8]) -> Result<Self, PagedError> {
+        let bytes = &mut bytes;
+        if take(bytes, MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+            return Err(PagedError::Corrupt("file is not a tree"));
+        }
+
+        let header = Self {
+            page_size: read_u64(bytes)? as usize,
+            order: read_u64(bytes)? as usize,
+            root: read_page_id(bytes)?,
+            size: read_u64(bytes)? as usize,
+            page_count: read_u64(bytes)?,
+            free_head: read_page_id(bytes)?,
+        };
+        if header.page_size > MAX_PAGE_SIZE {
+            return Err(PagedError::Corrupt(
+                "header holds a page size over the maximum",
+            ));
+        }
+
+        Ok(header)
+    }
+}
+
+fn read_u64(bytes: &mut &[u8]) -> Result<u64, DecodeError> {
+    let taken = take(bytes, 8)?;
+    Ok(u64::from_le_bytes(
+        taken.try_into().expect("Exactly 8 bytes were taken"),
+    ))
+}
+
+/// Page 0 is always the header, so it doubles as "no page"
+fn read_page_id(bytes: &mut &[u8]) -> Result<Option<PageId>, DecodeError> {
+    Ok(Some(read_u64(bytes)?).filter(|&id| id != 0))
+}
+
+pub(crate) struct LeafPage<K, V> {
+    pub(crate) next: Option<PageId>,
+    pub(crate) entries: Vec<(K, V)>,
+}
+
+/// Like the in-memory Internal node, every link holds the smallest key of its child
+pub(crate) struct InternalPage<K> {
+    pub(crate) links: Vec<(K, PageId)>,
+}
+
+impl<K> InternalPage<K> {
+    /// Index of the child whose keys k falls between
+    pub(crate) fn child_index<Q>(&self, k: &Q) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.links
+            .partition_point(|(key, _)| key.borrow() <= k)
+            .saturating_sub(1)
+    }
+}
+
+pub(crate) enum PageNode<K, V> {
+    Leaf(LeafPage<K, V>),
+    Internal(InternalPage<K>),
+}
+
+impl<K, V> PageNode<K, V> {
+    pub(crate) fn size(&self) -> usize {
+        match self {
+            PageNode::Leaf(leaf) => leaf.entries.len(),
+            PageNode::Internal(internal) => internal.links.len(),
+        }
+    }
+
+    pub(crate) fn smallest_key(&self) -> &K {
+        match self {
+            PageNode::Leaf(leaf) => &leaf.entries.first().expect("A node MUST NOT be empty").0,
+            PageNode::Internal(internal) => {
+                &internal.links.first().expect("A node MUST NOT be empty").0
+            }
+        }
+    }
+
+    /// Writes the node into a whole page. Entry sizes are checked before they get into a node, so a
+    /// node always fits.
+    pub(crate) fn encode<D>(&self, codec: &D, page: &mut [u8])
+    where
+        D: Codec<K> + Codec<V>,
+    {
+        let mut out = Vec::with_capacity(page.len());
+        match self {
+            PageNode::Leaf(leaf) => {
+                out.push(LEAF);
+                out.extend_from_slice(&(leaf.entries.len() as u32).to_le_bytes());
+                out.extend_from_slice(&leaf.next.unwrap_or(0).to_le_bytes());
+                for (k, v) in &leaf.entries {
+                    codec.encode(k, &mut out);
+                    codec.encode(v, &mut out);
+                }
+            }
+            PageNode::Internal(internal) => {
+                out.push(INTERNAL);
+                out.extend_from_slice(&(internal.links.len() as u32).to_le_bytes());
+                out.extend_from_slice(&0u64.to_le_bytes());
+                for (k, child) in &internal.links {
+                    codec.encode(k, &mut out);
+                    out.extend_from_slice(&child.to_le_bytes());
+                }
+            }
+        }
+
+        assert!(
+            out.len() <= page.len(),
+            "A node MUST fit in its page, got {} bytes for a page of {}",
+            out.len(),
+            page.len()
+        );
+
+        page[..out.len()].copy_from_slice(&out);
+        page[out.len()..].fill(0);
+    }
+
+    /// Reads a node of a tree with the given order, a count above what the order allows is rejected
+    /// before anything is allocated for it
+    pub(crate) fn decode<D>(codec: &D, order: usize, mut page: &[u8]) -> Result<Self, PagedError>
+    where
+        D: Codec<K> + Codec<V>,
+    {
+        let bytes = &mut page;
+        let kind = take(bytes, 1)?[0];
+        let count = u32::from_le_bytes(take(bytes, 4)?.try_into().expect("4 bytes were taken"));
+        let next = read_page_id(bytes)?;
+        if count as usize > order + 1 {
+            return Err(PagedError::Corrupt(
+                "node holds more entries than its order allows",
+            ));
+        }
+
+        match kind {
+            LEAF => {
+                let mut entries = Vec::with_capacity(count as usize + 1);
+                for _ in 0..count {
+                    entries.push((codec.decode(bytes)?, codec.decode(bytes)?));
+                }
+                Ok(PageNode::Leaf(LeafPage { next, entries }))
+            }
+            INTERNAL => {
+                let mut links = Vec::with_capacity(count as usize + 1);
+                for _ in 0..count {
+                    let k = codec.decode(bytes)?;
+                    let child = read_page_id(bytes)?
+                        .ok_or(PagedError::Corrupt("link to the header page"))?;
+                    links.push((k, child));
+                }
+                Ok(PageNode::Internal(InternalPage { links }))
+            }
+            FREE => Err(PagedError::Corrupt("link to a freed page")),
+            _ => Err(PagedError::Corrupt("unknown page kind")),
+        }
+    }
+}
+
+/// A page on the free list, pointing at the next freed page
+pub(crate) fn encode_free(next: Option<PageId>, page: &mut [u8]) {
+    page.fill(0);
+    page[0] = FREE;
+    page[5..NODE_HEADER_SIZE].copy_from_slice(&next.unwrap_or(0).to_le_bytes());
+}
+
+pub(crate) fn decode_free(mut page: &[u8]) -> Result<Option<PageId>, PagedError> {
+    let bytes = &mut page;
+    if take(bytes, 5)?[0] != FREE {
+        return Err(PagedError::Corrupt("page on the free list is in use"));
+    }
+
+    Ok(read_page_id(bytes)?)
+}
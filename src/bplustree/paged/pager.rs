@@ -0,0 +1,31 @@
+use crate::bplustree::paged::PageId;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+/// Reads and writes whole pages of the file, without any caching
+pub(crate) struct Pager {
+    file: File,
+    page_size: usize,
+}
+
+impl Pager {
+    pub(crate) fn new(file: File, page_size: usize) -> Self {
+        Self { file, page_size }
+    }
+
+    pub(crate) fn read(&self, id: PageId, page: &mut [u8]) -> io::Result<()> {
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(id * self.page_size as u64))?;
+        file.read_exact(page)
+    }
+
+    pub(crate) fn write(&self, id: PageId, page: &[u8]) -> io::Result<()> {
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(id * self.page_size as u64))?;
+        file.write_all(page)
+    }
+
+    pub(crate) fn sync(&self) -> io::Result<()> {
+        self.file.sync_all()
+    }
+}
@@ -0,0 +1,300 @@
+use crate::bplustree::paged::pager::Pager;
+use crate::bplustree::paged::{PageId, PagedError};
+use std::collections::{BTreeMap, HashMap};
+
+/// Which unpinned page makes room when the pool is full
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Eviction {
+    /// The page that was pinned longest ago
+    #[default]
+    Lru,
+    /// A hand sweeps over the frames and takes the first page that wasn't pinned since its last pass
+    Clock,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct PoolStats {
+    /// Pins of a page that was already in memory
+    pub hits: u64,
+    /// Pins that had to read the page from the file
+    pub misses: u64,
+    /// Pages dropped to make room for another one, dirty pages are written back first
+    pub evictions: u64,
+}
+
+struct Frame {
+    /// None while the frame holds no page
+    id: Option<PageId>,
+    data: Box<[u8]>,
+    pins: usize,
+    dirty: bool,
+    /// Tick of the last pin, for Eviction::Lru
+    last_used: u64,
+    /// Set by every pin and cleared by the hand of Eviction::Clock
+    referenced: bool,
+}
+
+/// Keeps up to `capacity` pages of the file in memory. A page is pinned while it's used, pinned
+/// pages are never evicted, and pages changed while pinned are written back when they're evicted
+/// or flushed.
+///
+/// Without `steal`, dirty pages are never evicted either, so the file only changes on a flush. The
+/// pool never grows past its capacity, once every page is pinned or dirty it fails with PoolFull.
+pub(crate) struct BufferPool {
+    pager: Pager,
+    page_size: usize,
+    capacity: usize,
+    eviction: Eviction,
+    steal: bool,
+    frames: Vec<Frame>,
+    /// Frame holding each page that is in memory
+    table: HashMap<PageId, usize>,
+    /// Frames that lost their page without getting a new one
+    empty: Vec<usize>,
+    /// Every evictable frame by the tick of its last pin, the first one is the LRU victim
+    unpinned: BTreeMap<u64, usize>,
+    /// Frames holding a dirty page
+    dirty: usize,
+    tick: u64,
+    hand: usize,
+    stats: PoolStats,
+}
+
+impl BufferPool {
+    pub(crate) fn new(pager: Pager, page_size: usize, capacity: usize, eviction: Eviction) -> Self {
+        assert!(capacity > 0, "BufferPool capacity must be at least 1");
+        Self {
+            pager,
+            page_size,
+            capacity,
+            eviction,
+            steal: true,
+            frames: Vec::with_capacity(capacity),
+            table: HashMap::with_capacity(capacity),
+            empty: Vec::new(),
+            unpinned: BTreeMap::new(),
+            dirty: 0,
+            tick: 0,
+            hand: 0,
+            stats: PoolStats::default(),
+        }
+    }
+
+    pub(crate) fn pager(&self) -> &Pager {
+        &self.pager
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub(crate) fn eviction(&self) -> Eviction {
+        self.eviction
+    }
+
+    /// Dirty pages become evictable or stop being so, the evictable frames are gathered again
+    pub(crate) fn set_steal(&mut self, steal: bool) {
+        self.steal = steal;
+        self.unpinned = self
+            .frames
+            .iter()
+            .enumerate()
+            .filter(|(_, frame)| frame.id.is_some() && self.is_evictable(frame))
+            .map(|(index, frame)| (frame.last_used, index))
+            .collect();
+    }
+
+    pub(crate) fn stats(&self) -> PoolStats {
+        self.stats
+    }
+
+    /// Pages in memory
+    pub(crate) fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    pub(crate) fn dirty_count(&self) -> usize {
+        self.dirty
+    }
+
+    /// Pages changed since they were read or last flushed
+    pub(crate) fn dirty_pages(&self) -> impl Iterator<Item = (PageId, &[u8])> {
+        self.frames
+            .iter()
+            .filter(|frame| frame.dirty)
+            .filter_map(|frame| Some((frame.id?, &*frame.data)))
+    }
+
+    /// Pins the page and returns its bytes, reading them from the file if the page isn't in memory.
+    /// Every pin MUST be followed by an unpin.
+    pub(crate) fn pin(&mut self, id: PageId) -> Result<&mut [u8], PagedError> {
+        let index = match self.table.get(&id) {
+            Some(&index) => {
+                self.stats.hits += 1;
+                index
+            }
+            None => {
+                self.stats.misses += 1;
+                let index = self.take_frame()?;
+                if let Err(error) = self.pager.read(id, &mut self.frames[index].data) {
+                    self.empty.push(index);
+                    return Err(error.into());
+                }
+                self.assign(index, id);
+                index
+            }
+        };
+
+        Ok(self.touch(index))
+    }
+
+    /// Pins a page whose bytes are about to be overwritten completely, so they aren't read from the
+    /// file. Doesn't count as a hit or a miss.
+    pub(crate) fn pin_for_write(&mut self, id: PageId) -> Result<&mut [u8], PagedError> {
+        let index = match self.table.get(&id) {
+            Some(&index) => index,
+            None => {
+                let index = self.take_frame()?;
+                self.assign(index, id);
+                index
+            }
+        };
+
+        Ok(self.touch(index))
+    }
+
+    /// Releases a pin, `dirty` marks the page as changed since it was pinned
+    pub(crate) fn unpin(&mut self, id: PageId, dirty: bool) {
+        let index = *self
+            .table
+            .get(&id)
+            .expect("Unpinned page MUST be in memory");
+        let frame = &mut self.frames[index];
+        assert!(
+            frame.pins > 0,
+            "Page {id} is unpinned more often than pinned"
+        );
+        frame.pins -= 1;
+        if dirty && !frame.dirty {
+            frame.dirty = true;
+            self.dirty += 1;
+        }
+
+        let frame = &self.frames[index];
+        if self.is_evictable(frame) {
+            self.unpinned.insert(frame.last_used, index);
+        }
+    }
+
+    /// Writes every dirty page back to the file, the pages stay in memory
+    pub(crate) fn flush(&mut self) -> Result<(), PagedError> {
+        for (index, frame) in self.frames.iter_mut().enumerate() {
+            if let Some(id) = frame.id
+                && frame.dirty
+            {
+                self.pager.write(id, &frame.data)?;
+                frame.dirty = false;
+                self.dirty -= 1;
+                if frame.pins == 0 {
+                    self.unpinned.insert(frame.last_used, index);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn assign(&mut self, index: usize, id: PageId) {
+        self.frames[index].id = Some(id);
+        self.table.insert(id, index);
+    }
+
+    fn touch(&mut self, index: usize) -> &mut [u8] {
+        self.tick += 1;
+        let frame = &mut self.frames[index];
+        if frame.pins == 0 {
+            self.unpinned.remove(&frame.last_used);
+        }
+        frame.pins += 1;
+        frame.last_used = self.tick;
+        frame.referenced = true;
+        &mut frame.data
+    }
+
+    /// Finds a frame for a page that isn't in memory: an empty one, a new one while the pool is
+    /// below capacity, or the frame of an evicted page
+    fn take_frame(&mut self) -> Result<usize, PagedError> {
+        if let Some(index) = self.empty.pop() {
+            return Ok(index);
+        }
+
+        let victim = if self.frames.len() < self.capacity {
+            None
+        } else {
+            match self.eviction {
+                Eviction::Lru => self.lru_victim(),
+                Eviction::Clock => self.clock_victim(),
+            }
+        };
+
+        let Some(victim) = victim else {
+            if self.frames.len() >= self.capacity {
+                return Err(PagedError::PoolFull);
+            }
+
+            self.frames.push(Frame {
+                id: None,
+                data: vec![0; self.page_size].into_boxed_slice(),
+                pins: 0,
+                dirty: false,
+                last_used: 0,
+                referenced: false,
+            });
+            return Ok(self.frames.len() - 1);
+        };
+
+        let frame = &mut self.frames[victim];
+        let id = frame.id.expect("Frames outside of empty MUST hold a page");
+        if frame.dirty {
+            self.pager.write(id, &frame.data)?;
+            frame.dirty = false;
+            self.dirty -= 1;
+        }
+        self.unpinned.remove(&frame.last_used);
+
+        frame.id = None;
+        self.table.remove(&id);
+        self.stats.evictions += 1;
+        Ok(victim)
+    }
+
+    fn is_evictable(&self, frame: &Frame) -> bool {
+        frame.pins == 0 && (self.steal || !frame.dirty)
+    }
+
+    fn lru_victim(&self) -> Option<usize> {
+        self.unpinned.first_key_value().map(|(_, &index)| index)
+    }
+
+    /// Two passes are enough, the first one clears every reference bit
+    fn clock_victim(&mut self) -> Option<usize> {
+        for _ in 0..2 * self.frames.len() {
+            let index = self.hand;
+            self.hand = (self.hand + 1) % self.frames.len();
+
+            if !self.is_evictable(&self.frames[index]) {
+                continue;
+            }
+
+            let frame = &mut self.frames[index];
+            if frame.referenced {
+                frame.referenced = false;
+                continue;
+            }
+
+            return Some(index);
+        }
+
+        None
+    }
+}
@@ -0,0 +1,159 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Bytes in front of every record: the length of the payload and its checksum
+const RECORD_HEADER_SIZE: usize = 8;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum RecordKind {
+    /// Encoded key and value
+    Insert = 1,
+    /// Encoded key
+    Remove = 2,
+    /// Page id followed by the whole page, part of a checkpoint
+    Page = 3,
+    /// Encoded header, ends a checkpoint
+    Checkpoint = 4,
+}
+
+impl RecordKind {
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            1 => Some(RecordKind::Insert),
+            2 => Some(RecordKind::Remove),
+            3 => Some(RecordKind::Page),
+            4 => Some(RecordKind::Checkpoint),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) struct Record {
+    pub(crate) kind: RecordKind,
+    pub(crate) body: Vec<u8>,
+}
+
+/// Append-only log of changes that haven't reached the data file yet. Every record is
+/// `[payload length: u32][crc32 of payload: u32][kind: u8][body]`, a record that is cut short or
+/// fails its checksum ends the log.
+pub(crate) struct Wal {
+    file: File,
+    len: u64,
+}
+
+impl Wal {
+    pub(crate) fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let len = file.metadata()?.len();
+        Ok(Self { file, len })
+    }
+
+    pub(crate) fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Reads every intact record from the start of the log. Whatever follows the last intact record
+    /// was never completely written, so it's cut off and new records go right after.
+    pub(crate) fn read_records(&mut self) -> io::Result<Vec<Record>> {
+        let mut bytes = Vec::with_capacity(self.len as usize);
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.read_to_end(&mut bytes)?;
+
+        let mut records = Vec::new();
+        let mut rest = bytes.as_slice();
+        while rest.len() >= RECORD_HEADER_SIZE {
+            let len = u32::from_le_bytes(rest[..4].try_into().expect("4 bytes")) as usize;
+            let checksum = u32::from_le_bytes(rest[4..8].try_into().expect("4 bytes"));
+            let Some(payload) = rest[RECORD_HEADER_SIZE..].get(..len) else {
+                break;
+            };
+            if payload.is_empty() || crc32(payload) != checksum {
+                break;
+            }
+            let Some(kind) = RecordKind::from_byte(payload[0]) else {
+                break;
+            };
+
+            records.push(Record {
+                kind,
+                body: payload[1..].to_vec(),
+            });
+            rest = &rest[RECORD_HEADER_SIZE + len..];
+        }
+
+        let intact = (bytes.len() - rest.len()) as u64;
+        if intact != self.len {
+            self.file.set_len(intact)?;
+            self.len = intact;
+        }
+
+        Ok(records)
+    }
+
+    /// Appends a record without waiting for it to reach the disk, see [`Wal::sync`]
+    pub(crate) fn write(&mut self, kind: RecordKind, body: &[u8]) -> io::Result<()> {
+        let mut record = Vec::with_capacity(RECORD_HEADER_SIZE + 1 + body.len());
+        record.extend_from_slice(&(body.len() as u32 + 1).to_le_bytes());
+        record.extend_from_slice(&[0; 4]);
+        record.push(kind as u8);
+        record.extend_from_slice(body);
+        let checksum = crc32(&record[RECORD_HEADER_SIZE..]);
+        record[4..8].copy_from_slice(&checksum.to_le_bytes());
+
+        self.file.seek(SeekFrom::Start(self.len))?;
+        self.file.write_all(&record)?;
+        self.len += record.len() as u64;
+        Ok(())
+    }
+
+    /// Appends a record and waits until it's on the disk
+    pub(crate) fn append(&mut self, kind: RecordKind, body: &[u8]) -> io::Result<()> {
+        self.write(kind, body)?;
+        self.sync()
+    }
+
+    pub(crate) fn sync(&self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+
+    pub(crate) fn truncate(&mut self) -> io::Result<()> {
+        self.file.set_len(0)?;
+        self.len = 0;
+        self.file.sync_all()
+    }
+}
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+/// Lookup table of the reflected CRC-32 polynomial used by zlib and ethernet
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, &byte| {
+        CRC32_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
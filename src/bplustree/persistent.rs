@@ -0,0 +1,567 @@
+use std::borrow::Borrow;
+use std::iter::FusedIterator;
+use std::mem::replace;
+use std::ops::{Bound, RangeBounds};
+use std::slice;
+use std::sync::Arc;
+
+/// Node shared between every version of a [`PersistentBPlusTree`] that contains it. It has no
+/// parent or neighbour links, as those would differ between the versions, so nodes are only ever
+/// reached by descending from a root.
+#[derive(Clone)]
+pub(crate) enum SharedNode<K, V> {
+    Leaf(Vec<(K, V)>),
+    /// Smallest key of each child, and the child
+    Internal(Vec<SharedLink<K, V>>),
+}
+
+pub(crate) type SharedLink<K, V> = (K, Arc<SharedNode<K, V>>);
+
+impl<K, V> SharedNode<K, V> {
+    fn size(&self) -> usize {
+        match self {
+            SharedNode::Leaf(entries) => entries.len(),
+            SharedNode::Internal(links) => links.len(),
+        }
+    }
+
+    fn smallest_key(&self) -> &K {
+        match self {
+            SharedNode::Leaf(entries) => &entries.first().expect("A node MUST NOT be empty").0,
+            SharedNode::Internal(links) => &links.first().expect("A node MUST NOT be empty").0,
+        }
+    }
+}
+
+/// Index of the child whose keys k falls between
+fn child_index<K, V, Q>(links: &[SharedLink<K, V>], k: &Q) -> usize
+where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+{
+    links
+        .partition_point(|(key, _)| key.borrow() <= k)
+        .saturating_sub(1)
+}
+
+/// Immutable version of a [`PersistentBPlusTree`], see [`PersistentBPlusTree::snapshot`]. Cloning
+/// it only clones the pointer to the root.
+pub struct Snapshot<K, V> {
+    order: usize,
+    pub(crate) root: Option<Arc<SharedNode<K, V>>>,
+    size: usize,
+}
+
+impl<K, V> Clone for Snapshot<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            order: self.order,
+            root: self.root.clone(),
+            size: self.size,
+        }
+    }
+}
+
+impl<K, V> Snapshot<K, V>
+where
+    K: Ord + Clone,
+{
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    pub fn find<Q>(&self, k: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut node = self.root.as_deref()?;
+        loop {
+            match node {
+                SharedNode::Internal(links) => node = &links[child_index(links, k)].1,
+                SharedNode::Leaf(entries) => {
+                    let index = entries
+                        .binary_search_by(|(key, _)| key.borrow().cmp(k))
+                        .ok()?;
+                    return Some(&entries[index].1);
+                }
+            }
+        }
+    }
+
+    pub fn contains<Q>(&self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.find(k).is_some()
+    }
+
+    /// Entries with keys in `range`, in key order. Leaves have no links to each other, so the
+    /// iterator keeps the path to the current leaf instead.
+    pub fn range<R>(&self, range: R) -> PersistentRange<'_, K, V>
+    where
+        R: RangeBounds<K>,
+    {
+        match (range.start_bound(), range.end_bound()) {
+            (Bound::Excluded(s), Bound::Excluded(e)) if s == e => {
+                panic!("range start and end are equal and excluded in BPlusTree")
+            }
+            (Bound::Included(s) | Bound::Excluded(s), Bound::Included(e) | Bound::Excluded(e))
+                if s > e =>
+            {
+                panic!("range start is greater than range end in BPlusTree")
+            }
+            _ => {}
+        }
+
+        let mut iter = PersistentRange {
+            path: Vec::new(),
+            entries: [].iter(),
+            end: range.end_bound().cloned(),
+        };
+
+        let Some(mut node) = self.root.as_deref() else {
+            return iter;
+        };
+
+        let entries = loop {
+            match node {
+                SharedNode::Internal(links) => {
+                    let index = match range.start_bound() {
+                        Bound::Included(k) | Bound::Excluded(k) => child_index(links, k),
+                        Bound::Unbounded => 0,
+                    };
+                    iter.path.push(links[index + 1..].iter());
+                    node = &links[index].1;
+                }
+                SharedNode::Leaf(entries) => break entries,
+            }
+        };
+
+        let start = match range.start_bound() {
+            Bound::Included(k) => entries.partition_point(|(key, _)| key < k),
+            Bound::Excluded(k) => entries.partition_point(|(key, _)| key <= k),
+            Bound::Unbounded => 0,
+        };
+        iter.entries = entries[start..].iter();
+        iter
+    }
+
+    pub fn iter(&self) -> PersistentRange<'_, K, V> {
+        self.range(..)
+    }
+
+    /// Checks that the nodes are sorted and sized like the in-memory tree, that every leaf is on
+    /// the same level and that every link holds the smallest key of its child
+    pub fn verify(&self) {
+        let Some(root) = self.root.as_deref() else {
+            assert_eq!(self.size, 0, "Empty tree MUST have a size of 0");
+            return;
+        };
+
+        if let SharedNode::Internal(links) = root {
+            assert!(
+                links.len() > 1,
+                "Internal root MUST have at least 2 children"
+            );
+        }
+
+        let mut leaf_depth = None;
+        let size = self.verify_node(root, true, 0, &mut leaf_depth);
+        assert_eq!(size, self.size, "Size MUST match the entries in the leaves");
+    }
+
+    /// Returns the number of entries in the subtree
+    fn verify_node(
+        &self,
+        node: &SharedNode<K, V>,
+        is_root: bool,
+        depth: usize,
+        leaf_depth: &mut Option<usize>,
+    ) -> usize {
+        assert!(node.size() > 0, "A node MUST NOT be empty");
+        assert!(node.size() <= self.order, "A node MUST NOT be overfull");
+        assert!(
+            is_root || node.size() >= self.order.div_ceil(2),
+            "A node below the root MUST NOT be underfull"
+        );
+
+        match node {
+            SharedNode::Internal(links) => {
+                assert!(
+                    links.is_sorted_by(|(a, _), (b, _)| a < b),
+                    "Links MUST be sorted"
+                );
+                links
+                    .iter()
+                    .map(|(k, child)| {
+                        assert!(
+                            child.smallest_key() == k,
+                            "A link MUST hold the smallest key of its child"
+                        );
+                        self.verify_node(child, false, depth + 1, leaf_depth)
+                    })
+                    .sum()
+            }
+            SharedNode::Leaf(entries) => {
+                assert!(
+                    entries.is_sorted_by(|(a, _), (b, _)| a < b),
+                    "Entries MUST be sorted"
+                );
+                assert_eq!(
+                    *leaf_depth.get_or_insert(depth),
+                    depth,
+                    "Leaves MUST all be on the same level"
+                );
+                entries.len()
+            }
+        }
+    }
+}
+
+/// A BPlusTree whose versions share their nodes. An insert or remove copies the nodes on the path
+/// from the root to the leaf it changes, plus the sibling a node is rebalanced with, and leaves
+/// every other node shared, so [`PersistentBPlusTree::snapshot`] only has to clone the root.
+///
+/// Nodes that no snapshot shares are changed in place, so without snapshots the tree copies
+/// nothing.
+pub struct PersistentBPlusTree<K, V> {
+    pub(crate) current: Snapshot<K, V>,
+}
+
+/// What an insert into a subtree leaves for the parent to fix
+struct Inserted<K, V> {
+    old: Option<V>,
+    smallest: K,
+    /// Separator and node of a new right sibling
+    split: Option<SharedLink<K, V>>,
+}
+
+impl<K, V> PersistentBPlusTree<K, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    pub fn new(order: usize) -> Self {
+        assert!(order > 2, "BPlusTree order must be at least 2");
+        Self {
+            current: Snapshot {
+                order,
+                root: None,
+                size: 0,
+            },
+        }
+    }
+
+    /// The tree as it is now. Takes O(1), and later inserts and removes don't change it.
+    pub fn snapshot(&self) -> Snapshot<K, V> {
+        self.current.clone()
+    }
+
+    pub fn size(&self) -> usize {
+        self.current.size()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.current.is_empty()
+    }
+
+    pub fn order(&self) -> usize {
+        self.current.order()
+    }
+
+    pub fn max_node_size(&self) -> usize {
+        self.current.order
+    }
+
+    pub fn min_node_size(&self) -> usize {
+        self.current.order.div_ceil(2)
+    }
+
+    pub fn find<Q>(&self, k: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.current.find(k)
+    }
+
+    pub fn contains<Q>(&self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.current.contains(k)
+    }
+
+    pub fn range<R>(&self, range: R) -> PersistentRange<'_, K, V>
+    where
+        R: RangeBounds<K>,
+    {
+        self.current.range(range)
+    }
+
+    pub fn iter(&self) -> PersistentRange<'_, K, V> {
+        self.current.iter()
+    }
+
+    pub fn verify(&self) {
+        self.current.verify()
+    }
+
+    pub fn insert(&mut self, k: K, v: V) -> Option<V> {
+        let max = self.max_node_size();
+        let Some(root) = &mut self.current.root else {
+            self.current.root = Some(Arc::new(SharedNode::Leaf(vec![(k, v)])));
+            self.current.size = 1;
+            return None;
+        };
+
+        let inserted = Self::insert_into(root, k, v, max);
+        if let Some(split) = inserted.split {
+            let left = root.clone();
+            *root = Arc::new(SharedNode::Internal(vec![(inserted.smallest, left), split]));
+        }
+
+        if inserted.old.is_none() {
+            self.current.size += 1;
+        }
+        inserted.old
+    }
+
+    fn insert_into(node: &mut Arc<SharedNode<K, V>>, k: K, v: V, max: usize) -> Inserted<K, V> {
+        match Arc::make_mut(node) {
+            SharedNode::Leaf(entries) => {
+                let old = match entries.binary_search_by(|(key, _)| key.cmp(&k)) {
+                    Ok(index) => Some(replace(&mut entries[index], (k, v)).1),
+                    Err(index) => {
+                        entries.insert(index, (k, v));
+                        None
+                    }
+                };
+
+                let split = (entries.len() > max).then(|| {
+                    let right = entries.split_off(entries.len() / 2);
+                    (right[0].0.clone(), Arc::new(SharedNode::Leaf(right)))
+                });
+
+                Inserted {
+                    old,
+                    smallest: entries[0].0.clone(),
+                    split,
+                }
+            }
+            SharedNode::Internal(links) => {
+                let index = child_index(links, &k);
+                let inserted = Self::insert_into(&mut links[index].1, k, v, max);
+                links[index].0 = inserted.smallest;
+                if let Some(split) = inserted.split {
+                    links.insert(index + 1, split);
+                }
+
+                let split = (links.len() > max).then(|| {
+                    let right = links.split_off(links.len() / 2);
+                    (right[0].0.clone(), Arc::new(SharedNode::Internal(right)))
+                });
+
+                Inserted {
+                    old: inserted.old,
+                    smallest: links[0].0.clone(),
+                    split,
+                }
+            }
+        }
+    }
+
+    pub fn remove<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        // Nodes on the path would be copied for nothing if k isn't there
+        if !self.contains(k) {
+            return None;
+        }
+
+        let min = self.min_node_size();
+        let root = self
+            .current
+            .root
+            .as_mut()
+            .expect("Tree contains k, there MUST be a root");
+        let v = Self::remove_from(root, k, min);
+        self.current.size -= 1;
+
+        match &**root {
+            SharedNode::Leaf(entries) if entries.is_empty() => self.current.root = None,
+            SharedNode::Internal(links) if links.len() == 1 => {
+                self.current.root = Some(links[0].1.clone());
+            }
+            _ => {}
+        }
+
+        Some(v)
+    }
+
+    fn remove_from<Q>(node: &mut Arc<SharedNode<K, V>>, k: &Q, min: usize) -> V
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        match Arc::make_mut(node) {
+            SharedNode::Leaf(entries) => {
+                let index = entries
+                    .binary_search_by(|(key, _)| key.borrow().cmp(k))
+                    .expect("Tree contains k, it MUST be in this leaf");
+                entries.remove(index).1
+            }
+            SharedNode::Internal(links) => {
+                let index = child_index(links, k);
+                let v = Self::remove_from(&mut links[index].1, k, min);
+                Self::fix_child(links, index, min);
+                v
+            }
+        }
+    }
+
+    /// Updates the link to the child at `index` after a remove, an underfull child takes an entry
+    /// from a sibling that can spare one, or is merged with it
+    fn fix_child(links: &mut Vec<SharedLink<K, V>>, index: usize, min: usize) {
+        links[index].0 = links[index].1.smallest_key().clone();
+        if links[index].1.size() >= min {
+            return;
+        }
+
+        let left_index = index.saturating_sub(1);
+        let (left, right) = links.split_at_mut(left_index + 1);
+        let (left, right) = (&mut left[left_index], &mut right[0]);
+        if left.1.size().max(right.1.size()) > min {
+            Self::transfer(Arc::make_mut(&mut left.1), Arc::make_mut(&mut right.1));
+            right.0 = right.1.smallest_key().clone();
+        } else {
+            let (_, right) = links.remove(left_index + 1);
+            Self::merge(
+                Arc::make_mut(&mut links[left_index].1),
+                Arc::unwrap_or_clone(right),
+            );
+        }
+
+        links[left_index].0 = links[left_index].1.smallest_key().clone();
+    }
+
+    /// Moves one entry from the larger of two neighbouring nodes to the smaller one
+    fn transfer(left: &mut SharedNode<K, V>, right: &mut SharedNode<K, V>) {
+        let to_right = left.size() > right.size();
+        match (left, right) {
+            (SharedNode::Leaf(left), SharedNode::Leaf(right)) => {
+                if to_right {
+                    let entry = left.pop().expect("Left MUST NOT be empty");
+                    right.insert(0, entry);
+                } else {
+                    left.push(right.remove(0));
+                }
+            }
+            (SharedNode::Internal(left), SharedNode::Internal(right)) => {
+                if to_right {
+                    let link = left.pop().expect("Left MUST NOT be empty");
+                    right.insert(0, link);
+                } else {
+                    left.push(right.remove(0));
+                }
+            }
+            _ => unreachable!("Siblings MUST be on the same level"),
+        }
+    }
+
+    /// Moves everything from right into left
+    fn merge(left: &mut SharedNode<K, V>, right: SharedNode<K, V>) {
+        match (left, right) {
+            (SharedNode::Leaf(left), SharedNode::Leaf(right)) => left.extend(right),
+            (SharedNode::Internal(left), SharedNode::Internal(right)) => left.extend(right),
+            _ => unreachable!("Siblings MUST be on the same level"),
+        }
+    }
+}
+
+impl<K, V> Extend<(K, V)> for PersistentBPlusTree<K, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
+    }
+}
+
+/// Entries of a [`Snapshot`] or [`PersistentBPlusTree`] in key order, see [`Snapshot::range`]
+pub struct PersistentRange<'a, K, V> {
+    /// Links still to be visited in every internal node above the current leaf
+    path: Vec<slice::Iter<'a, SharedLink<K, V>>>,
+    /// Rest of the current leaf
+    entries: slice::Iter<'a, (K, V)>,
+    end: Bound<K>,
+}
+
+impl<'a, K, V> Iterator for PersistentRange<'a, K, V>
+where
+    K: Ord,
+{
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((k, v)) = self.entries.next() {
+                let past_end = match &self.end {
+                    Bound::Included(end) => k > end,
+                    Bound::Excluded(end) => k >= end,
+                    Bound::Unbounded => false,
+                };
+                if past_end {
+                    self.path.clear();
+                    self.entries = [].iter();
+                    return None;
+                }
+                return Some((k, v));
+            }
+
+            // Climbs to the lowest node with links left, then down to the leftmost leaf below
+            // its next link
+            let mut node = loop {
+                let links = self.path.last_mut()?;
+                match links.next() {
+                    Some((_, child)) => break &**child,
+                    None => {
+                        self.path.pop();
+                    }
+                }
+            };
+
+            loop {
+                match node {
+                    SharedNode::Internal(links) => {
+                        let mut links = links.iter();
+                        node = &links.next().expect("A node MUST NOT be empty").1;
+                        self.path.push(links);
+                    }
+                    SharedNode::Leaf(entries) => {
+                        self.entries = entries.iter();
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<K, V> FusedIterator for PersistentRange<'_, K, V> where K: Ord {}
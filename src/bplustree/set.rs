@@ -0,0 +1,441 @@
+use crate::bplustree::BPlusTree;
+use crate::bplustree::iter;
+use std::borrow::Borrow;
+use std::cmp::{Ordering, max, min};
+use std::iter::{FusedIterator, Peekable};
+use std::ops::RangeBounds;
+
+/// An ordered set, stored as the keys of a [`BPlusTree`] with `()` values.
+#[derive(Debug)]
+pub struct BPlusSet<K>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    tree: BPlusTree<K, ()>,
+}
+
+impl<K> BPlusSet<K>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    pub fn new(order: usize) -> Self {
+        Self {
+            tree: BPlusTree::new(order),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.tree.size()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size() == 0
+    }
+
+    /// Returns true if the key was not in the set yet
+    pub fn insert(&mut self, k: K) -> bool {
+        self.tree.insert(k, ()).is_none()
+    }
+
+    /// Returns true if the key was in the set
+    pub fn remove<Q>(&mut self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.tree.remove(k).is_some()
+    }
+
+    pub fn contains<Q>(&self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.tree.contains(k)
+    }
+
+    pub fn first(&self) -> Option<&K> {
+        self.tree.smallest_key()
+    }
+
+    pub fn last(&self) -> Option<&K> {
+        self.tree.largest_key()
+    }
+
+    pub fn pop_first(&mut self) -> Option<K> {
+        self.tree.pop_first().map(|(k, _)| k)
+    }
+
+    pub fn pop_last(&mut self) -> Option<K> {
+        self.tree.pop_last().map(|(k, _)| k)
+    }
+
+    pub fn iter(&self) -> Iter<'_, K> {
+        Iter {
+            inner: self.tree.keys(),
+        }
+    }
+
+    pub fn range<Q, R>(&self, range: R) -> Range<'_, K>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+        R: RangeBounds<Q>,
+    {
+        Range {
+            inner: self.tree.range(range),
+        }
+    }
+
+    /// Keys that are in self or in other, in ascending order
+    pub fn union<'a>(&'a self, other: &'a Self) -> Union<'a, K> {
+        Union {
+            inner: MergeIter::new(self, other),
+        }
+    }
+
+    /// Keys that are both in self and in other, in ascending order
+    pub fn intersection<'a>(&'a self, other: &'a Self) -> Intersection<'a, K> {
+        Intersection {
+            inner: MergeIter::new(self, other),
+        }
+    }
+
+    /// Keys that are in self but not in other, in ascending order
+    pub fn difference<'a>(&'a self, other: &'a Self) -> Difference<'a, K> {
+        Difference {
+            inner: MergeIter::new(self, other),
+        }
+    }
+
+    /// Keys that are in exactly one of self and other, in ascending order
+    pub fn symmetric_difference<'a>(&'a self, other: &'a Self) -> SymmetricDifference<'a, K> {
+        SymmetricDifference {
+            inner: MergeIter::new(self, other),
+        }
+    }
+
+    pub fn is_subset(&self, other: &Self) -> bool {
+        self.size() <= other.size() && self.difference(other).next().is_none()
+    }
+
+    pub fn is_superset(&self, other: &Self) -> bool {
+        other.is_subset(self)
+    }
+
+    pub fn is_disjoint(&self, other: &Self) -> bool {
+        self.intersection(other).next().is_none()
+    }
+}
+
+impl<K> FromIterator<K> for BPlusSet<K>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    fn from_iter<T: IntoIterator<Item = K>>(iter: T) -> Self {
+        Self {
+            tree: iter.into_iter().map(|k| (k, ())).collect(),
+        }
+    }
+}
+
+impl<'a, K> IntoIterator for &'a BPlusSet<K>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    type Item = &'a K;
+    type IntoIter = Iter<'a, K>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<K> IntoIterator for BPlusSet<K>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    type Item = K;
+    type IntoIter = IntoIter<K>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            inner: self.tree.into_iter(),
+        }
+    }
+}
+
+pub struct Iter<'a, K> {
+    inner: iter::Keys<'a, K, ()>,
+}
+
+impl<K> Clone for Iter<'_, K> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'a, K> Iterator for Iter<'a, K>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K> DoubleEndedIterator for Iter<'_, K>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back()
+    }
+}
+
+impl<K> ExactSizeIterator for Iter<'_, K> where K: Ord + PartialOrd + Clone {}
+
+impl<K> FusedIterator for Iter<'_, K> where K: Ord + PartialOrd + Clone {}
+
+pub struct Range<'a, K> {
+    inner: iter::Range<'a, K, ()>,
+}
+
+impl<'a, K> Iterator for Range<'a, K>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(k, _)| k)
+    }
+}
+
+impl<K> DoubleEndedIterator for Range<'_, K>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(k, _)| k)
+    }
+}
+
+impl<K> FusedIterator for Range<'_, K> where K: Ord + PartialOrd + Clone {}
+
+pub struct IntoIter<K>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    inner: iter::IntoIter<K, ()>,
+}
+
+impl<K> Iterator for IntoIter<K>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    type Item = K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(k, _)| k)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K> DoubleEndedIterator for IntoIter<K>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(k, _)| k)
+    }
+}
+
+impl<K> ExactSizeIterator for IntoIter<K> where K: Ord + PartialOrd + Clone {}
+
+impl<K> FusedIterator for IntoIter<K> where K: Ord + PartialOrd + Clone {}
+
+/// Walks two sets side by side, yielding the next key of either or both sets, whichever is smaller.
+/// Equal keys are yielded together.
+struct MergeIter<'a, K>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    a: Peekable<Iter<'a, K>>,
+    b: Peekable<Iter<'a, K>>,
+}
+
+impl<'a, K> MergeIter<'a, K>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    fn new(a: &'a BPlusSet<K>, b: &'a BPlusSet<K>) -> Self {
+        Self {
+            a: a.iter().peekable(),
+            b: b.iter().peekable(),
+        }
+    }
+
+    fn nexts(&mut self) -> (Option<&'a K>, Option<&'a K>) {
+        let (Some(a), Some(b)) = (self.a.peek(), self.b.peek()) else {
+            return (self.a.next(), self.b.next());
+        };
+
+        match a.cmp(b) {
+            Ordering::Less => (self.a.next(), None),
+            Ordering::Equal => (self.a.next(), self.b.next()),
+            Ordering::Greater => (None, self.b.next()),
+        }
+    }
+
+    fn a_is_empty(&mut self) -> bool {
+        self.a.peek().is_none()
+    }
+
+    fn b_is_empty(&mut self) -> bool {
+        self.b.peek().is_none()
+    }
+
+    fn lens(&self) -> (usize, usize) {
+        (self.a.len(), self.b.len())
+    }
+}
+
+pub struct Union<'a, K>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    inner: MergeIter<'a, K>,
+}
+
+impl<'a, K> Iterator for Union<'a, K>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (a, b) = self.inner.nexts();
+        a.or(b)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (a, b) = self.inner.lens();
+        (max(a, b), Some(a + b))
+    }
+}
+
+impl<K> FusedIterator for Union<'_, K> where K: Ord + PartialOrd + Clone {}
+
+pub struct Intersection<'a, K>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    inner: MergeIter<'a, K>,
+}
+
+impl<'a, K> Iterator for Intersection<'a, K>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.inner.a_is_empty() || self.inner.b_is_empty() {
+                return None;
+            }
+
+            match self.inner.nexts() {
+                (Some(a), Some(_)) => return Some(a),
+                (Some(_), None) | (None, Some(_)) => continue,
+                (None, None) => return None,
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (a, b) = self.inner.lens();
+        (0, Some(min(a, b)))
+    }
+}
+
+impl<K> FusedIterator for Intersection<'_, K> where K: Ord + PartialOrd + Clone {}
+
+pub struct Difference<'a, K>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    inner: MergeIter<'a, K>,
+}
+
+impl<'a, K> Iterator for Difference<'a, K>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.inner.a_is_empty() {
+                return None;
+            }
+
+            match self.inner.nexts() {
+                (Some(a), None) => return Some(a),
+                (Some(_), Some(_)) | (None, Some(_)) => continue,
+                (None, None) => return None,
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (a, b) = self.inner.lens();
+        (a.saturating_sub(b), Some(a))
+    }
+}
+
+impl<K> FusedIterator for Difference<'_, K> where K: Ord + PartialOrd + Clone {}
+
+pub struct SymmetricDifference<'a, K>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    inner: MergeIter<'a, K>,
+}
+
+impl<'a, K> Iterator for SymmetricDifference<'a, K>
+where
+    K: Ord + PartialOrd + Clone,
+{
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.inner.nexts() {
+                (Some(a), None) => return Some(a),
+                (None, Some(b)) => return Some(b),
+                (Some(_), Some(_)) => continue,
+                (None, None) => return None,
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (a, b) = self.inner.lens();
+        (0, Some(a + b))
+    }
+}
+
+impl<K> FusedIterator for SymmetricDifference<'_, K> where K: Ord + PartialOrd + Clone {}
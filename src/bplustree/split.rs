@@ -0,0 +1,368 @@
+use crate::bplustree::BPlusTree;
+use crate::bplustree::aggregate::Aggregate;
+use crate::bplustree::comparator::Comparator;
+use crate::bplustree::internal::Internal;
+use crate::bplustree::iter::IntoIter;
+use crate::bplustree::leaf::Leaf;
+use crate::bplustree::node::Node;
+use std::borrow::Borrow;
+use std::mem::{replace, swap};
+use std::ops::{Bound, RangeBounds};
+use std::ptr::NonNull;
+
+impl<K, V, A, C> BPlusTree<K, V, A, C>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+    /// Moves every entry with a key greater than or equal to `k` into a new tree. Subtrees to the
+    /// right of the path to `k` are moved as a whole, only the nodes along the cut get rebalanced.
+    pub fn split_off<Q>(&mut self, k: &Q) -> Self
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+        A: Clone,
+        C: Clone,
+    {
+        self.split_off_at(Bound::Included(k))
+    }
+
+    /// Removes every entry in `range` and returns how many there were. The subtrees between the two
+    /// ends of the range are freed as a whole, only the nodes along the two cuts get rebalanced.
+    pub fn remove_range<Q, R>(&mut self, range: R) -> usize
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+        R: RangeBounds<Q>,
+        A: Clone,
+        C: Clone,
+    {
+        self.cut_range(range).size()
+    }
+
+    /// Removes every entry in `range` like [`BPlusTree::remove_range`] and returns them in key order.
+    /// The entries are gone from the tree even if the iterator is dropped before the end.
+    pub fn drain<Q, R>(&mut self, range: R) -> IntoIter<K, V, A, C>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+        R: RangeBounds<Q>,
+        A: Clone,
+        C: Clone,
+    {
+        self.cut_range(range).into_iter()
+    }
+
+    /// Cuts the tree before and after the range and joins the outer parts again, returns a tree
+    /// holding the range
+    fn cut_range<Q, R>(&mut self, range: R) -> Self
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+        R: RangeBounds<Q>,
+        A: Clone,
+        C: Clone,
+    {
+        let mut middle = self.split_off_at(range.start_bound());
+        let mut right = match range.end_bound() {
+            Bound::Included(k) => middle.split_off_at(Bound::Excluded(k)),
+            Bound::Excluded(k) => middle.split_off_at(Bound::Included(k)),
+            Bound::Unbounded => return middle,
+        };
+
+        self.append(&mut right);
+        middle
+    }
+
+    /// Like split_off, but the bound decides whether an entry equal to the key moves as well
+    fn split_off_at<Q>(&mut self, bound: Bound<&Q>) -> Self
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+        A: Clone,
+        C: Clone,
+    {
+        if let Bound::Unbounded = bound {
+            return self.take();
+        }
+
+        let mut right = Self::with_aggregate_and_comparator(
+            self.order,
+            self.aggregate.clone(),
+            self.comparator.clone(),
+        );
+        let Some(root_ptr) = self.root else {
+            return right;
+        };
+
+        right.root = unsafe { self.split_node(root_ptr, bound) };
+        if unsafe { root_ptr.as_ref() }.size() == 0 {
+            unsafe { self.release_node(root_ptr, "freeing emptied root") };
+            self.root = None;
+        }
+
+        // The leaf chain is still continuous, cut it between the two trees
+        if let Some(mut last_ptr) = self.last_leaf_raw() {
+            unsafe { last_ptr.as_mut().as_leaf_mut().next = None };
+        }
+
+        if let Some(mut first_ptr) = right.first_leaf_raw() {
+            unsafe { first_ptr.as_mut().as_leaf_mut().prev = None };
+        }
+
+        right.size = right
+            .root
+            .map_or(0, |root_ptr| unsafe { root_ptr.as_ref() }.subtree_size());
+        self.size -= right.size;
+
+        unsafe {
+            self.fix_edge(true);
+            right.fix_edge(false);
+        }
+
+        right
+    }
+
+    /// Moves every entry of `other` into this tree, leaving `other` empty. When the key ranges
+    /// don't overlap, the root of the shorter tree is grafted into the taller one at the matching
+    /// height and only the seam gets rebalanced. Otherwise the entries are inserted one by one and
+    /// the values from `other` win.
+    pub fn append(&mut self, other: &mut Self)
+    where
+        A: Clone,
+        C: Clone,
+    {
+        let (Some(other_first), Some(other_last)) = (other.first_leaf_raw(), other.last_leaf_raw())
+        else {
+            return;
+        };
+
+        let (Some(self_first), Some(self_last)) = (self.first_leaf_raw(), self.last_leaf_raw())
+        else {
+            if self.order == other.order {
+                swap(self, other);
+            } else {
+                let other = other.take();
+                *self = self
+                    .take()
+                    .load_sorted(1.0, other)
+                    .expect("Entries of a tree are sorted and unique");
+            }
+            return;
+        };
+
+        let smallest = |ptr: NonNull<Node<K, V, A>>| unsafe { ptr.as_ref() }.smallest_key();
+        let largest = |ptr: NonNull<Node<K, V, A>>| unsafe { ptr.as_ref() }.largest_key();
+
+        let below = |largest: Option<&K>, smallest: &K| {
+            largest.is_none_or(|largest| self.comparator.compare(largest, smallest).is_lt())
+        };
+
+        let other_is_right = below(largest(self_last), smallest(other_first));
+        let other_is_left = below(largest(other_last), smallest(self_first));
+        if self.order != other.order || !(other_is_right || other_is_left) {
+            let other = other.take();
+            for (k, v) in other {
+                self.insert(k, v);
+            }
+            return;
+        }
+
+        if other_is_left {
+            swap(self, other);
+        }
+
+        // From here on all keys in self are smaller than all keys in other
+        unsafe { self.graft_right(other) };
+    }
+
+    /// SAFETY: every key in other MUST be larger than every key in self, both trees MUST NOT be
+    /// empty and MUST have the same order
+    unsafe fn graft_right(&mut self, other: &mut Self) {
+        let mut left_last = self.last_leaf_raw().unwrap();
+        let mut right_first = other.first_leaf_raw().unwrap();
+        unsafe {
+            left_last.as_mut().as_leaf_mut().next = Some(right_first);
+            right_first.as_mut().as_leaf_mut().prev = Some(left_last);
+        }
+
+        let left_height = self.height();
+        let right_height = other.height();
+        let size = self.size + other.size;
+
+        if left_height >= right_height {
+            let edge_ptr = self.edge_node_at_height(true, right_height);
+            let grafted_ptr = other.root.take().unwrap();
+            other.size = 0;
+            self.size = size;
+
+            unsafe {
+                self.insert_into_parent_node(edge_ptr, grafted_ptr);
+                self.refresh_links_above(grafted_ptr);
+                if left_height == right_height {
+                    // Both old roots are now children of a new root and either of them can be underfull
+                    self.fix_edge(false);
+                    self.fix_edge(true);
+                } else {
+                    self.fill_underfull_node(grafted_ptr);
+                }
+            }
+        } else {
+            let edge_ptr = other.edge_node_at_height(false, left_height);
+            let grafted_ptr = self.root.take().unwrap();
+            self.size = 0;
+            other.size = size;
+
+            unsafe {
+                other.insert_into_parent_node(edge_ptr, grafted_ptr);
+                other.refresh_links_above(grafted_ptr);
+                let parent_ptr = grafted_ptr.as_ref().parent_raw().unwrap();
+                other.update_separators_above(parent_ptr);
+                other.fill_underfull_node(grafted_ptr);
+            }
+
+            swap(self, other);
+        }
+    }
+
+    /// Splits the subtree so that node_ptr keeps the keys below the bound and returns a new node
+    /// holding the rest, or None if nothing is moved. Children that end up empty are unlinked and freed.
+    ///
+    /// SAFETY: node_ptr MUST point to a node of this tree, bound MUST NOT be Unbounded
+    unsafe fn split_node<Q>(
+        &mut self,
+        mut node_ptr: NonNull<Node<K, V, A>>,
+        bound: Bound<&Q>,
+    ) -> Option<NonNull<Node<K, V, A>>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let (k, inclusive) = match bound {
+            Bound::Included(k) => (k, true),
+            Bound::Excluded(k) => (k, false),
+            Bound::Unbounded => unreachable!("An Unbounded split moves the whole tree"),
+        };
+
+        let internal = match unsafe { node_ptr.as_mut() } {
+            Node::Leaf(leaf) => {
+                let index = leaf.data.partition_point(|(key, _)| {
+                    let ordering = self.comparator.compare(key.borrow(), k);
+                    ordering.is_lt() || (!inclusive && ordering.is_eq())
+                });
+                if index == leaf.size() {
+                    return None;
+                }
+
+                let mut right = Leaf::new();
+                right.data = leaf.data.split_off(index);
+                let right_ptr =
+                    unsafe { NonNull::new_unchecked(Box::into_raw(Box::new(Node::Leaf(right)))) };
+
+                unsafe { Leaf::link_after(node_ptr, right_ptr) };
+                return Some(right_ptr);
+            }
+            Node::Internal(internal) => internal,
+        };
+
+        let index = internal.less_or_equal_to_index(k, &self.comparator);
+        let moved = internal.links.split_off(index + 1);
+        internal.counts.truncate(index + 1);
+        internal.summaries.truncate(index + 1);
+        let (_, mut child_ptr) = internal.links[index];
+
+        let child_right = unsafe { self.split_node(child_ptr, bound) };
+
+        let internal = unsafe { node_ptr.as_mut().as_internal_mut() };
+        if unsafe { child_ptr.as_ref() }.size() == 0 {
+            internal.links.pop();
+            unsafe {
+                child_ptr.as_mut().set_parent(None);
+                if let Node::Leaf(_) = child_ptr.as_ref() {
+                    Leaf::unlink(child_ptr);
+                }
+                self.release_node(child_ptr, "freeing emptied node after split_off");
+            }
+            internal.counts.pop();
+            internal.summaries.pop();
+        } else {
+            internal.refresh_link(child_ptr, &self.aggregate);
+        }
+
+        let mut links = Vec::with_capacity(moved.len() + 1);
+        if let Some(child_right_ptr) = child_right {
+            let key = unsafe { child_right_ptr.as_ref() }.smallest_key().clone();
+            links.push((key, child_right_ptr));
+        }
+        links.extend(moved);
+
+        if links.is_empty() {
+            return None;
+        }
+
+        let right = Node::Internal(Internal::new(links, &self.aggregate));
+        let right_ptr = unsafe { NonNull::new_unchecked(Box::into_raw(Box::new(right))) };
+        for (_, child_ptr) in &unsafe { right_ptr.as_ref() }.as_internal().links {
+            let mut child_ptr = *child_ptr;
+            unsafe { child_ptr.as_mut().set_parent(Some(right_ptr)) };
+        }
+
+        Some(right_ptr)
+    }
+
+    /// Moves every entry into a new tree, leaving an empty tree with the same order, aggregate and
+    /// comparator
+    fn take(&mut self) -> Self
+    where
+        A: Clone,
+        C: Clone,
+    {
+        let empty = Self::with_aggregate_and_comparator(
+            self.order,
+            self.aggregate.clone(),
+            self.comparator.clone(),
+        );
+        replace(self, empty)
+    }
+
+    /// Number of levels, a tree with only a root Leaf has height 1
+    fn height(&self) -> usize {
+        let Some(mut current) = self.root else {
+            return 0;
+        };
+
+        let mut height = 1;
+        while let Node::Internal(internal) = unsafe { current.as_ref() } {
+            current = internal.smallest_value();
+            height += 1;
+        }
+
+        height
+    }
+
+    /// The first or last node on the given height, counted from the leaves upwards starting at 1
+    fn edge_node_at_height(&self, rightmost: bool, height: usize) -> NonNull<Node<K, V, A>> {
+        let mut current = self.root.expect("Tree MUST NOT be empty");
+        for _ in height..self.height() {
+            let internal = unsafe { current.as_ref() }.as_internal();
+            current = if rightmost {
+                internal
+                    .links
+                    .last()
+                    .expect("An Internal node MUST have a child")
+                    .1
+            } else {
+                internal.smallest_value()
+            };
+        }
+
+        current
+    }
+}
@@ -0,0 +1,119 @@
+use crate::bplustree::BPlusTree;
+use crate::bplustree::aggregate::Aggregate;
+use crate::bplustree::comparator::Comparator;
+use crate::bplustree::internal::Link;
+use crate::bplustree::node::Node;
+use std::mem::size_of;
+use std::ptr::NonNull;
+
+/// Number of buckets in [`TreeStats::fill_histogram`]
+pub const FILL_BUCKETS: usize = 10;
+
+/// Shape and memory use of a tree, see [`BPlusTree::stats`]
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TreeStats {
+    /// Number of levels, a tree with only a root Leaf has height 1
+    pub height: usize,
+    pub internal_nodes: usize,
+    pub leaf_nodes: usize,
+    pub entries: usize,
+    /// Nodes by their fill factor `size / max_node_size()`. Bucket `i` counts the nodes filled
+    /// from `i / FILL_BUCKETS` up to `(i + 1) / FILL_BUCKETS`, full nodes count towards the last one.
+    pub fill_histogram: [usize; FILL_BUCKETS],
+    /// Average fill factor of the leaves, 0 for an empty tree
+    pub average_leaf_occupancy: f64,
+    pub heap: HeapUsage,
+}
+
+/// Approximate heap bytes held by the nodes of a tree. Memory owned by the keys and values
+/// themselves, like the buffer of a `String`, is not included.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct HeapUsage {
+    /// The boxed nodes
+    pub nodes: usize,
+    /// Entries of the leaves, and links, counts and summaries of the Internal nodes
+    pub entries: usize,
+    /// Capacity of those vectors that is allocated but not in use
+    pub spare_capacity: usize,
+}
+
+impl HeapUsage {
+    pub fn total(&self) -> usize {
+        self.nodes + self.entries + self.spare_capacity
+    }
+}
+
+impl<K, V, A, C> BPlusTree<K, V, A, C>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+    /// Walks the whole tree to report its shape, how full its nodes are and roughly how much
+    /// memory they use, which helps picking an order.
+    pub fn stats(&self) -> TreeStats {
+        let mut stats = TreeStats::default();
+        if let Some(root_ptr) = self.root {
+            unsafe { self.collect_stats(root_ptr, 1, &mut stats) };
+        }
+
+        if stats.leaf_nodes > 0 {
+            stats.average_leaf_occupancy =
+                stats.entries as f64 / (stats.leaf_nodes * self.max_node_size()) as f64;
+        }
+
+        stats
+    }
+
+    /// SAFETY: node_ptr MUST point to a node of this tree
+    unsafe fn collect_stats(
+        &self,
+        node_ptr: NonNull<Node<K, V, A>>,
+        depth: usize,
+        stats: &mut TreeStats,
+    ) {
+        let node = unsafe { node_ptr.as_ref() };
+        let bucket = node.size() * FILL_BUCKETS / self.max_node_size();
+        stats.fill_histogram[bucket.min(FILL_BUCKETS - 1)] += 1;
+        stats.heap.nodes += size_of::<Node<K, V, A>>();
+
+        match node {
+            Node::Internal(internal) => {
+                stats.internal_nodes += 1;
+                for (used, capacity, size) in [
+                    (
+                        internal.links.len(),
+                        internal.links.capacity(),
+                        size_of::<Link<K, V, A>>(),
+                    ),
+                    (
+                        internal.counts.len(),
+                        internal.counts.capacity(),
+                        size_of::<usize>(),
+                    ),
+                    (
+                        internal.summaries.len(),
+                        internal.summaries.capacity(),
+                        size_of::<A::Summary>(),
+                    ),
+                ] {
+                    stats.heap.entries += used * size;
+                    stats.heap.spare_capacity += (capacity - used) * size;
+                }
+
+                for (_, child_ptr) in &internal.links {
+                    unsafe { self.collect_stats(*child_ptr, depth + 1, stats) };
+                }
+            }
+            Node::Leaf(leaf) => {
+                stats.leaf_nodes += 1;
+                stats.entries += leaf.size();
+                stats.height = stats.height.max(depth);
+
+                let size = size_of::<(K, V)>();
+                stats.heap.entries += leaf.data.len() * size;
+                stats.heap.spare_capacity += (leaf.data.capacity() - leaf.data.len()) * size;
+            }
+        }
+    }
+}
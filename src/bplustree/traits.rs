@@ -0,0 +1,235 @@
+use crate::bplustree::aggregate::Aggregate;
+use crate::bplustree::comparator::Comparator;
+use crate::bplustree::internal::Internal;
+use crate::bplustree::leaf::Leaf;
+use crate::bplustree::node::Node;
+use crate::bplustree::{BPlusTree, DEFAULT_ORDER};
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt::{Debug, Formatter};
+use std::hash::{Hash, Hasher};
+use std::ops::Index;
+use std::ptr::NonNull;
+
+/// Copies every node, so the clone has the same shape as the original
+impl<K, V, A, C> Clone for BPlusTree<K, V, A, C>
+where
+    K: Clone,
+    V: Clone,
+    A: Aggregate<K, V> + Clone,
+    C: Clone,
+{
+    fn clone(&self) -> Self {
+        let mut previous_leaf = None;
+        Self {
+            order: self.order,
+            root: self
+                .root
+                .map(|root_ptr| unsafe { clone_node(root_ptr, None, &mut previous_leaf) }),
+            size: self.size,
+            aggregate: self.aggregate.clone(),
+            comparator: self.comparator.clone(),
+            retired: None,
+        }
+    }
+}
+
+/// Clones the subtree, leaves are visited in key order and linked to previous_leaf
+unsafe fn clone_node<K, V, A>(
+    node_ptr: NonNull<Node<K, V, A>>,
+    parent: Option<NonNull<Node<K, V, A>>>,
+    previous_leaf: &mut Option<NonNull<Node<K, V, A>>>,
+) -> NonNull<Node<K, V, A>>
+where
+    K: Clone,
+    V: Clone,
+    A: Aggregate<K, V>,
+{
+    match unsafe { node_ptr.as_ref() } {
+        Node::Internal(internal) => {
+            let clone = Node::Internal(Internal {
+                parent,
+                links: Vec::with_capacity(internal.links.capacity()),
+                counts: internal.counts.clone(),
+                summaries: internal.summaries.clone(),
+            });
+            let mut clone_ptr = unsafe { NonNull::new_unchecked(Box::into_raw(Box::new(clone))) };
+
+            for (k, child_ptr) in &internal.links {
+                let child_clone_ptr =
+                    unsafe { clone_node(*child_ptr, Some(clone_ptr), previous_leaf) };
+                unsafe { clone_ptr.as_mut().as_internal_mut() }
+                    .links
+                    .push((k.clone(), child_clone_ptr));
+            }
+
+            clone_ptr
+        }
+        Node::Leaf(leaf) => {
+            let mut data = Vec::with_capacity(leaf.data.capacity());
+            data.extend(leaf.data.iter().cloned());
+            let clone = Node::Leaf(Leaf {
+                parent,
+                prev: *previous_leaf,
+                next: None,
+                data,
+            });
+            let clone_ptr = unsafe { NonNull::new_unchecked(Box::into_raw(Box::new(clone))) };
+
+            if let Some(mut previous_ptr) = previous_leaf.replace(clone_ptr) {
+                unsafe { previous_ptr.as_mut().as_leaf_mut().next = Some(clone_ptr) };
+            }
+
+            clone_ptr
+        }
+    }
+}
+
+/// Trees are equal when they hold the same entries, regardless of their order or shape
+impl<K, V, A, C> PartialEq for BPlusTree<K, V, A, C>
+where
+    K: Clone + PartialEq,
+    V: PartialEq,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.size == other.size && self.iter().eq(other.iter())
+    }
+}
+
+impl<K, V, A, C> Eq for BPlusTree<K, V, A, C>
+where
+    K: Clone + Eq,
+    V: Eq,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+}
+
+/// Compares the entries lexicographically in iteration order
+impl<K, V, A, C> PartialOrd for BPlusTree<K, V, A, C>
+where
+    K: Clone + PartialOrd,
+    V: PartialOrd,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other.iter())
+    }
+}
+
+impl<K, V, A, C> Ord for BPlusTree<K, V, A, C>
+where
+    K: Clone + Ord,
+    V: Ord,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+impl<K, V, A, C> Hash for BPlusTree<K, V, A, C>
+where
+    K: Clone + Hash,
+    V: Hash,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(self.size);
+        for entry in self {
+            entry.hash(state);
+        }
+    }
+}
+
+impl<K, V, A, C> Default for BPlusTree<K, V, A, C>
+where
+    K: Clone,
+    A: Aggregate<K, V> + Default,
+    C: Comparator<K> + Default,
+{
+    fn default() -> Self {
+        Self::with_aggregate_and_comparator(DEFAULT_ORDER, A::default(), C::default())
+    }
+}
+
+impl<K, V, A, C> Extend<(K, V)> for BPlusTree<K, V, A, C>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
+    }
+}
+
+impl<'a, K, V, A, C> Extend<(&'a K, &'a V)> for BPlusTree<K, V, A, C>
+where
+    K: Copy,
+    V: Copy,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+    fn extend<T: IntoIterator<Item = (&'a K, &'a V)>>(&mut self, iter: T) {
+        self.extend(iter.into_iter().map(|(k, v)| (*k, *v)));
+    }
+}
+
+impl<K, Q, V, A, C> Index<&Q> for BPlusTree<K, V, A, C>
+where
+    K: Clone + Borrow<Q>,
+    Q: ?Sized,
+    A: Aggregate<K, V>,
+    C: Comparator<K> + Comparator<Q>,
+{
+    type Output = V;
+
+    /// Panics if the key is not in the tree
+    fn index(&self, k: &Q) -> &V {
+        self.find(k).expect("no entry found for key")
+    }
+}
+
+/// Prints the entries like a map, [`crate::bplustree::debug::print_bplustree`] shows the nodes
+impl<K, V, A, C> Debug for BPlusTree<K, V, A, C>
+where
+    K: Clone + Debug,
+    V: Debug,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+// SAFETY: the tree owns all of its nodes, the pointers between them never leave it. Moving the tree
+// to another thread moves the keys, values and summaries along with it.
+unsafe impl<K, V, A, C> Send for BPlusTree<K, V, A, C>
+where
+    K: Clone + Send,
+    V: Send,
+    A: Aggregate<K, V> + Send,
+    A::Summary: Send,
+    C: Send,
+{
+}
+
+// SAFETY: a shared tree only hands out shared references to its keys, values and summaries
+unsafe impl<K, V, A, C> Sync for BPlusTree<K, V, A, C>
+where
+    K: Clone + Sync,
+    V: Sync,
+    A: Aggregate<K, V> + Sync,
+    A::Summary: Sync,
+    C: Sync,
+{
+}
@@ -0,0 +1,325 @@
+use crate::bplustree::aggregate::Aggregate;
+use crate::bplustree::comparator::Comparator;
+use crate::bplustree::internal::Internal;
+use crate::bplustree::leaf::Leaf;
+use crate::bplustree::node::Node;
+use crate::bplustree::{BPlusTree, free_node_ptr};
+use std::borrow::Borrow;
+use std::collections::HashSet;
+use std::ptr::NonNull;
+
+/// Changes to a tree that are either all kept or all undone, see [`BPlusTree::begin`].
+///
+/// Every insert and remove goes straight into the tree, so the transaction sees its own writes.
+/// Before each of them, the nodes it can touch are copied into an undo record, and a rollback
+/// writes those copies back, restoring the exact nodes the tree had before. Dropping the
+/// transaction without committing it rolls it back.
+///
+/// Leaking a transaction, for example with [`std::mem::forget`], keeps its changes like a commit,
+/// but the nodes they took out of the tree, and those of every later remove, are only freed by the
+/// next [`BPlusTree::begin`] or when the tree is dropped.
+pub struct Transaction<'a, K, V, A, C>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    tree: &'a mut BPlusTree<K, V, A, C>,
+    undo: Vec<Undo<K, V, A>>,
+    done: bool,
+}
+
+/// A node and the copy of it that gets written back
+type Image<K, V, A> = (NonNull<Node<K, V, A>>, Node<K, V, A>);
+
+/// What a single insert or remove needs to be undone
+struct Undo<K, V, A>
+where
+    A: Aggregate<K, V>,
+{
+    root: Option<NonNull<Node<K, V, A>>>,
+    size: usize,
+    /// Copies of every node the mutation could change, taken right before it
+    images: Vec<Image<K, V, A>>,
+    /// Nodes the mutation created
+    allocated: Vec<NonNull<Node<K, V, A>>>,
+}
+
+impl<K, V, A, C> BPlusTree<K, V, A, C>
+where
+    K: Clone,
+    V: Clone,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+    /// Starts a transaction, the tree can only be changed through it until it's committed or
+    /// rolled back
+    pub fn begin(&mut self) -> Transaction<'_, K, V, A, C> {
+        // Left over by a leaked transaction, whose changes were kept
+        unsafe { self.free_retired() };
+        self.retired = Some(Vec::new());
+        Transaction {
+            tree: self,
+            undo: Vec::new(),
+            done: false,
+        }
+    }
+
+    /// SAFETY: the retired nodes MUST be out of the tree for good, no undo record may put them back
+    unsafe fn free_retired(&mut self) {
+        for ptr in self.retired.take().into_iter().flatten() {
+            unsafe { free_node_ptr(ptr, "freeing node retired by a transaction") };
+        }
+    }
+
+    /// Runs `f` in a transaction, which is committed if `f` returns Ok and rolled back if it
+    /// returns Err or panics
+    pub fn transaction<T, E, F>(&mut self, f: F) -> Result<T, E>
+    where
+        F: FnOnce(&mut Transaction<'_, K, V, A, C>) -> Result<T, E>,
+    {
+        let mut transaction = self.begin();
+        let result = f(&mut transaction);
+        if result.is_ok() {
+            transaction.commit();
+        } else {
+            transaction.rollback();
+        }
+        result
+    }
+
+    /// Copies the path down to the leaf of k, the neighbours each node on it can transfer to or
+    /// merge with, and the leaves whose links a split or merge of those neighbours changes
+    unsafe fn capture<Q>(&self, k: &Q) -> Undo<K, V, A>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        let mut nodes = Vec::new();
+        if let Some(leaf_ptr) = self.find_leaf_node_raw(k) {
+            let leaf = unsafe { leaf_ptr.as_ref().as_leaf() };
+            let (mut prev, mut next) = (leaf.prev, leaf.next);
+            for _ in 0..2 {
+                if let Some(prev_ptr) = prev {
+                    nodes.push(prev_ptr);
+                    prev = unsafe { prev_ptr.as_ref().as_leaf() }.prev;
+                }
+                if let Some(next_ptr) = next {
+                    nodes.push(next_ptr);
+                    next = unsafe { next_ptr.as_ref().as_leaf() }.next;
+                }
+            }
+
+            let mut current = Some(leaf_ptr);
+            while let Some(current_ptr) = current {
+                nodes.push(current_ptr);
+                let (left, right) = unsafe { self.get_node_neighbours(current_ptr) };
+                nodes.extend(left);
+                nodes.extend(right);
+                current = unsafe { current_ptr.as_ref() }.parent_raw();
+            }
+        }
+
+        nodes.sort_unstable();
+        nodes.dedup();
+        Undo {
+            root: self.root,
+            size: self.size,
+            images: nodes
+                .into_iter()
+                .map(|ptr| (ptr, unsafe { image(ptr) }))
+                .collect(),
+            allocated: Vec::new(),
+        }
+    }
+
+    /// Finds the nodes a mutation created: every node it created hangs off a copied node, the root,
+    /// or another created node
+    unsafe fn find_allocated(&self, undo: &mut Undo<K, V, A>) {
+        let mut known = HashSet::new();
+        known.extend(undo.root);
+        for (ptr, image) in &undo.images {
+            known.insert(*ptr);
+            known.extend(linked_nodes(image));
+        }
+
+        let mut queue: Vec<_> = self.root.into_iter().collect();
+        for (ptr, _) in &undo.images {
+            queue.extend(linked_nodes(unsafe { ptr.as_ref() }));
+        }
+
+        while let Some(ptr) = queue.pop() {
+            if known.insert(ptr) {
+                undo.allocated.push(ptr);
+                queue.extend(linked_nodes(unsafe { ptr.as_ref() }));
+            }
+        }
+    }
+}
+
+impl<K, V, A, C> BPlusTree<K, V, A, C>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    /// SAFETY: undo records MUST be undone in the reverse order they were taken in
+    unsafe fn undo(&mut self, undo: Undo<K, V, A>) {
+        let restored: Vec<_> = undo.images.iter().map(|(ptr, _)| *ptr).collect();
+        for (mut ptr, image) in undo.images {
+            unsafe { *ptr.as_mut() = image };
+        }
+
+        // Children that were moved to another node still point at it
+        for ptr in restored {
+            if let Node::Internal(internal) = unsafe { ptr.as_ref() } {
+                for &(_, mut child_ptr) in &internal.links {
+                    unsafe { child_ptr.as_mut().set_parent(Some(ptr)) };
+                }
+            }
+        }
+
+        self.root = undo.root;
+        self.size = undo.size;
+        for ptr in undo.allocated {
+            let _ = unsafe { Box::from_raw(ptr.as_ptr()) };
+        }
+    }
+}
+
+impl<K, V, A, C> Transaction<'_, K, V, A, C>
+where
+    K: Clone,
+    V: Clone,
+    A: Aggregate<K, V>,
+    C: Comparator<K>,
+{
+    pub fn insert(&mut self, k: K, v: V) -> Option<V> {
+        let mut undo = unsafe { self.tree.capture(&k) };
+        let old = self.tree.insert(k, v);
+        unsafe { self.tree.find_allocated(&mut undo) };
+        self.undo.push(undo);
+        old
+    }
+
+    pub fn remove<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        if !self.tree.contains(k) {
+            return None;
+        }
+
+        let mut undo = unsafe { self.tree.capture(k) };
+        let removed = self.tree.remove(k);
+        unsafe { self.tree.find_allocated(&mut undo) };
+        self.undo.push(undo);
+        removed
+    }
+
+    pub fn get<Q>(&self, k: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        self.tree.find(k)
+    }
+
+    pub fn contains<Q>(&self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized,
+        C: Comparator<Q>,
+    {
+        self.tree.contains(k)
+    }
+
+    pub fn size(&self) -> usize {
+        self.tree.size()
+    }
+
+    /// The tree with the changes made so far, for iterating or any other read
+    pub fn tree(&self) -> &BPlusTree<K, V, A, C> {
+        self.tree
+    }
+
+    /// Keeps every change, and frees the nodes they took out of the tree
+    pub fn commit(mut self) {
+        self.undo.clear();
+        unsafe { self.tree.free_retired() };
+        self.done = true;
+    }
+
+    /// Undoes every change, the tree gets back the contents and the shape it had on begin
+    pub fn rollback(self) {}
+}
+
+impl<K, V, A, C> Transaction<'_, K, V, A, C>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    fn undo_all(&mut self) {
+        while let Some(undo) = self.undo.pop() {
+            unsafe { self.tree.undo(undo) };
+        }
+
+        // Every retired node was put back by its undo record, or freed as a node the transaction made
+        self.tree.retired = None;
+        self.done = true;
+    }
+}
+
+impl<K, V, A, C> Drop for Transaction<'_, K, V, A, C>
+where
+    K: Clone,
+    A: Aggregate<K, V>,
+{
+    fn drop(&mut self) {
+        if !self.done {
+            self.undo_all();
+        }
+    }
+}
+
+/// Copy of a node, pointing at the same parent, neighbours and children
+unsafe fn image<K, V, A>(ptr: NonNull<Node<K, V, A>>) -> Node<K, V, A>
+where
+    K: Clone,
+    V: Clone,
+    A: Aggregate<K, V>,
+{
+    match unsafe { ptr.as_ref() } {
+        Node::Internal(internal) => Node::Internal(Internal {
+            parent: internal.parent,
+            links: internal.links.clone(),
+            counts: internal.counts.clone(),
+            summaries: internal.summaries.clone(),
+        }),
+        Node::Leaf(leaf) => Node::Leaf(Leaf {
+            parent: leaf.parent,
+            prev: leaf.prev,
+            next: leaf.next,
+            data: leaf.data.clone(),
+        }),
+    }
+}
+
+fn linked_nodes<K, V, A>(node: &Node<K, V, A>) -> Vec<NonNull<Node<K, V, A>>>
+where
+    A: Aggregate<K, V>,
+{
+    match node {
+        Node::Internal(internal) => internal
+            .parent
+            .into_iter()
+            .chain(internal.links.iter().map(|(_, ptr)| *ptr))
+            .collect(),
+        Node::Leaf(leaf) => [leaf.parent, leaf.prev, leaf.next]
+            .into_iter()
+            .flatten()
+            .collect(),
+    }
+}